@@ -2,22 +2,52 @@ use glam::{Quat, Vec2, Vec3};
 
 use crate::{
     core::{input_manager::InputManager, time_manager::TimeManager},
-    render::render_manager::RenderManager,
+    render::{coordinate_system::CoordinateSystem, render_manager::RenderManager, scene::Camera},
+    utils::terrain_generator::{TerrainBounds, TerrainHeights},
 };
 
+use super::controller::{fit_clip_planes, framing_distance, Controller, MAX_PITCH};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraMode {
+    /// Yaw and pitch with the horizon kept level. Pitch is limited short of looking
+    /// straight up or down.
+    #[default]
+    Fps,
+    /// Looking and rolling rotate the camera around its own axes, without limits
+    FreeLook6Dof,
+}
+
 #[derive(Clone, Copy)]
 pub struct CameraSettings {
-    initial_pos: Vec3,
-    initial_rotation_angles: Vec2,
-    speed: f32,
+    pub mode: CameraMode,
+    pub initial_pos: Vec3,
+    pub initial_rotation_angles: Vec2,
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    pub acceleration: f32,
+    /// Degrees per second, used in `CameraMode::FreeLook6Dof`
+    pub roll_speed: f32,
+    /// Keeps the camera above the terrain set with `set_terrain_heights`
+    pub collide_with_terrain: bool,
+    pub ground_offset: f32,
+    /// Fits the near and far planes to the terrain set with `set_terrain_bounds`
+    pub auto_fit_planes: bool,
 }
 
 impl Default for CameraSettings {
     fn default() -> Self {
         Self {
+            mode: CameraMode::Fps,
             initial_pos: Vec3::ZERO,
-            initial_rotation_angles: Vec2::ZERO,
+            initial_rotation_angles: Vec2::new(45.0, 30.0),
             speed: 1.0,
+            sprint_multiplier: 3.0,
+            acceleration: 10.0,
+            roll_speed: 90.0,
+            collide_with_terrain: false,
+            ground_offset: 0.3,
+            auto_fit_planes: false,
         }
     }
 }
@@ -25,7 +55,12 @@ impl Default for CameraSettings {
 pub struct CameraController {
     settings: CameraSettings,
     position: Vec3,
+    velocity: Vec3,
     rotation_angles: Vec2,
+    // Rotation in the 6 DoF mode, set up from the angles when entering it
+    orientation: Option<Quat>,
+    terrain_heights: Option<TerrainHeights>,
+    terrain_bounds: Option<TerrainBounds>,
 }
 
 impl CameraController {
@@ -33,28 +68,167 @@ impl CameraController {
         Self {
             settings: *settings,
             position: settings.initial_pos,
+            velocity: Vec3::ZERO,
             rotation_angles: settings.initial_rotation_angles,
+            orientation: None,
+            terrain_heights: None,
+            terrain_bounds: None,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.velocity.length()
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.settings.mode
+    }
+
+    /// Leaving the 6 DoF mode keeps the look direction, dropping the roll
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.settings.mode = mode;
+    }
+
+    pub fn set_terrain_heights(&mut self, terrain_heights: Option<TerrainHeights>) {
+        self.terrain_heights = terrain_heights;
+    }
+
+    pub fn set_terrain_bounds(&mut self, terrain_bounds: Option<TerrainBounds>) {
+        self.terrain_bounds = terrain_bounds;
+    }
+
+    fn rotation(&self, coordinate_system: CoordinateSystem) -> Quat {
+        self.orientation.unwrap_or_else(|| {
+            coordinate_system.yaw_pitch_rotation(self.rotation_angles.x, self.rotation_angles.y)
+        })
+    }
+
+    /// Converts the rotation between angles and the orientation after a mode change
+    fn sync_mode(&mut self, coordinate_system: CoordinateSystem) {
+        match self.settings.mode {
+            CameraMode::Fps => {
+                let Some(orientation) = self.orientation.take() else {
+                    return;
+                };
+
+                let forward = coordinate_system
+                    .from_forward_z(orientation.mul_vec3(coordinate_system.camera_forward()));
+                self.rotation_angles = Vec2::new(
+                    forward.x.atan2(forward.z).to_degrees(),
+                    (-forward.y).clamp(-1.0, 1.0).asin().to_degrees(),
+                );
+                self.rotation_angles.y = self.rotation_angles.y.clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            CameraMode::FreeLook6Dof => {
+                if self.orientation.is_none() {
+                    self.orientation = Some(self.rotation(coordinate_system));
+                }
+            }
+        }
+    }
+
+    fn update_rotation(
+        &mut self,
+        time_manager: &TimeManager,
+        input_manager: &InputManager,
+        coordinate_system: CoordinateSystem,
+    ) {
+        let look_delta = if input_manager.is_look_active() {
+            input_manager.smoothed_look_delta()
+        } else {
+            Vec2::ZERO
+        };
+
+        match &mut self.orientation {
+            Some(orientation) => {
+                let roll = input_manager.roll_axis()
+                    * self.settings.roll_speed
+                    * time_manager.fixed_delta();
+                // Negative angles around the local Z roll clockwise in both systems
+                *orientation = (*orientation
+                    * coordinate_system.yaw_pitch_rotation(look_delta.x, look_delta.y)
+                    * Quat::from_rotation_z(-roll.to_radians()))
+                .normalize();
+            }
+            None => {
+                self.rotation_angles += look_delta;
+                self.rotation_angles.y = self.rotation_angles.y.clamp(-MAX_PITCH, MAX_PITCH);
+            }
         }
     }
 
-    pub fn update(
+    fn fit_clip_planes(&self, camera: &mut Camera) {
+        let Some(bounds) = self
+            .terrain_bounds
+            .filter(|_| self.settings.auto_fit_planes)
+        else {
+            return;
+        };
+
+        let (near, far) = fit_clip_planes(&bounds, self.position);
+        camera.set_near_plane(near);
+        camera.set_far_plane(far);
+    }
+}
+
+impl Controller for CameraController {
+    fn update(
         &mut self,
         time_manager: &TimeManager,
         input_manager: &InputManager,
         render_manager: &mut RenderManager,
     ) {
-        self.rotation_angles += input_manager.look_delta();
+        let coordinate_system = render_manager.coordinate_system();
+        self.sync_mode(coordinate_system);
+        self.update_rotation(time_manager, input_manager, coordinate_system);
+        let rotation = self.rotation(coordinate_system);
 
-        let rotation = Quat::from_rotation_y(self.rotation_angles.x.to_radians())
-            * Quat::from_rotation_x(self.rotation_angles.y.to_radians());
+        let mut target_speed = self.settings.speed;
+        if input_manager.is_sprinting() {
+            target_speed *= self.settings.sprint_multiplier;
+        }
 
-        self.position += self.settings.speed
-            * time_manager.delta()
-            * rotation.mul_vec3(input_manager.move_vector());
+        let move_vector = coordinate_system.from_forward_z(input_manager.move_vector());
+        let target_velocity = target_speed * rotation.mul_vec3(move_vector.normalize_or_zero());
+        let delta = time_manager.fixed_delta();
 
+        self.velocity = self.velocity.lerp(
+            target_velocity,
+            1.0 - (-self.settings.acceleration * delta).exp(),
+        );
+        self.position += self.velocity * delta;
+
+        if self.settings.collide_with_terrain {
+            let height = self
+                .terrain_heights
+                .as_ref()
+                .and_then(|heights| heights.height_at(self.position.x, self.position.z));
+            // There's no ground to clamp to outside the terrain
+            if let Some(height) = height {
+                self.position.y = self.position.y.max(height + self.settings.ground_offset);
+            }
+        }
+
+        let mut camera = render_manager.camera().borrow_mut();
+
+        camera.set_position(self.position);
+        camera.set_rotation(rotation);
+        self.fit_clip_planes(&mut camera);
+    }
+
+    fn frame(&mut self, bounds: &TerrainBounds, render_manager: &mut RenderManager) {
+        let coordinate_system = render_manager.coordinate_system();
+        self.sync_mode(coordinate_system);
+        let rotation = self.rotation(coordinate_system);
         let mut camera = render_manager.camera().borrow_mut();
 
+        self.position = bounds.center()
+            - rotation.mul_vec3(coordinate_system.camera_forward())
+                * framing_distance(bounds, &camera);
+        self.velocity = Vec3::ZERO;
+
         camera.set_position(self.position);
         camera.set_rotation(rotation);
+        self.fit_clip_planes(&mut camera);
     }
 }
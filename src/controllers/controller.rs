@@ -0,0 +1,51 @@
+use glam::Vec3;
+
+use crate::{
+    core::{input_manager::InputManager, time_manager::TimeManager},
+    render::{render_manager::RenderManager, scene::Camera},
+    utils::terrain_generator::TerrainBounds,
+};
+
+/// Pitch limit in degrees, which keeps yaw and pitch controlled cameras from flipping
+pub(super) const MAX_PITCH: f32 = 89.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ControllerType {
+    FreeFly,
+    Orbit,
+}
+
+pub trait Controller {
+    fn update(
+        &mut self,
+        time_manager: &TimeManager,
+        input_manager: &InputManager,
+        render_manager: &mut RenderManager,
+    );
+
+    /// Moves the camera so the bounds fit in the view, keeping its current direction
+    fn frame(&mut self, bounds: &TerrainBounds, render_manager: &mut RenderManager);
+}
+
+/// Distance from the center of the bounds at which they fit in the camera view from
+/// any direction. The bounding sphere is fitted into the narrower of the two fovs.
+pub fn framing_distance(bounds: &TerrainBounds, camera: &Camera) -> f32 {
+    let radius = bounds.size().length() * 0.5;
+    let half_fov = camera.fov().to_radians() * 0.5;
+    let half_fov = half_fov.min((half_fov.tan() * camera.aspect_ratio()).atan());
+
+    radius / half_fov.sin()
+}
+
+// Depth precision depends on the ratio between the far and the near planes
+const FAR_NEAR_RATIO: f32 = 1000.0;
+const FAR_PLANE_MARGIN: f32 = 1.1;
+
+/// Near and far planes keeping the whole bounds in view from the position. The far
+/// plane reaches the farthest corner with a margin.
+pub fn fit_clip_planes(bounds: &TerrainBounds, position: Vec3) -> (f32, f32) {
+    let farthest_corner = (position - bounds.center()).abs() + bounds.size() * 0.5;
+    let far = (farthest_corner.length() * FAR_PLANE_MARGIN).max(f32::EPSILON);
+
+    (far / FAR_NEAR_RATIO, far)
+}
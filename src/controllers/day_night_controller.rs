@@ -0,0 +1,156 @@
+use std::{cell::RefCell, f32::consts::TAU, rc::Rc};
+
+use glam::Vec3;
+
+use crate::{
+    core::time_manager::TimeManager,
+    render::{render_manager::RenderManager, scene::GlobalLight, skybox_renderer::SkyboxRenderer},
+};
+
+#[derive(Clone, Copy)]
+pub struct DayNightKeyframe {
+    pub time_of_day: f32,
+    pub light_color: Vec3,
+    pub ambient_light: Vec3,
+    pub sky_color: Vec3,
+    pub horizon_color: Vec3,
+}
+
+/// Time of day runs over `[0, 1)`: 0 is midnight, 0.25 is sunrise in +X,
+/// 0.5 is noon and 0.75 is sunset in -X.
+#[derive(Clone)]
+pub struct DayNightSettings {
+    pub day_length: f32,
+    pub initial_time_of_day: f32,
+    pub sun_tilt: f32,
+    pub keyframes: Vec<DayNightKeyframe>,
+}
+
+impl Default for DayNightSettings {
+    fn default() -> Self {
+        Self {
+            day_length: 120.0,
+            initial_time_of_day: 0.4,
+            sun_tilt: 0.4,
+            keyframes: vec![
+                DayNightKeyframe {
+                    time_of_day: 0.0,
+                    light_color: Vec3::new(0.05, 0.06, 0.12),
+                    ambient_light: Vec3::new(0.02, 0.03, 0.08),
+                    sky_color: Vec3::new(0.02, 0.03, 0.1),
+                    horizon_color: Vec3::new(0.05, 0.07, 0.15),
+                },
+                DayNightKeyframe {
+                    time_of_day: 0.25,
+                    light_color: Vec3::new(0.9, 0.5, 0.3),
+                    ambient_light: Vec3::new(0.1, 0.12, 0.2),
+                    sky_color: Vec3::new(0.35, 0.45, 0.75),
+                    horizon_color: Vec3::new(0.95, 0.6, 0.4),
+                },
+                DayNightKeyframe {
+                    time_of_day: 0.5,
+                    light_color: Vec3::new(0.8, 0.48, 0.74),
+                    ambient_light: Vec3::new(0.085, 0.245, 0.494),
+                    sky_color: Vec3::new(0.17, 0.49, 0.988),
+                    horizon_color: Vec3::new(0.72, 0.9, 0.96),
+                },
+                DayNightKeyframe {
+                    time_of_day: 0.75,
+                    light_color: Vec3::new(0.9, 0.45, 0.25),
+                    ambient_light: Vec3::new(0.1, 0.1, 0.18),
+                    sky_color: Vec3::new(0.3, 0.35, 0.65),
+                    horizon_color: Vec3::new(0.98, 0.55, 0.3),
+                },
+            ],
+        }
+    }
+}
+
+pub struct DayNightController {
+    settings: DayNightSettings,
+    time_of_day: f32,
+    skybox: Option<Rc<RefCell<SkyboxRenderer>>>,
+}
+
+impl DayNightController {
+    pub fn new(
+        settings: &DayNightSettings,
+        skybox: Option<Rc<RefCell<SkyboxRenderer>>>,
+    ) -> DayNightController {
+        let mut settings = settings.clone();
+        settings
+            .keyframes
+            .sort_by(|a, b| a.time_of_day.total_cmp(&b.time_of_day));
+
+        DayNightController {
+            time_of_day: settings.initial_time_of_day.rem_euclid(1.0),
+            settings,
+            skybox,
+        }
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0);
+    }
+
+    pub fn update(&mut self, time_manager: &TimeManager, render_manager: &mut RenderManager) {
+        if self.settings.day_length > 0.0 {
+            self.set_time_of_day(
+                self.time_of_day + time_manager.delta() / self.settings.day_length,
+            );
+        }
+
+        let keyframe = match self.sample_keyframe() {
+            Some(keyframe) => keyframe,
+            None => return,
+        };
+
+        let sun_angle = (self.time_of_day - 0.25) * TAU;
+        let sun_dir =
+            Vec3::new(sun_angle.cos(), sun_angle.sin(), self.settings.sun_tilt).normalize();
+
+        render_manager.set_global_light(GlobalLight::new(-sun_dir, keyframe.light_color));
+        render_manager.set_ambient_light(keyframe.ambient_light);
+
+        if let Some(skybox) = &self.skybox {
+            let skybox = skybox.borrow();
+            skybox.set_sky_color(keyframe.sky_color, render_manager);
+            skybox.set_horizon_color(keyframe.horizon_color, render_manager);
+        }
+    }
+
+    fn sample_keyframe(&self) -> Option<DayNightKeyframe> {
+        let keyframes = &self.settings.keyframes;
+        let last = *keyframes.last()?;
+
+        let next_index = keyframes
+            .iter()
+            .position(|k| k.time_of_day > self.time_of_day)
+            .unwrap_or(0);
+        let next = keyframes[next_index];
+        let prev = if next_index == 0 {
+            last
+        } else {
+            keyframes[next_index - 1]
+        };
+
+        let span = (next.time_of_day - prev.time_of_day).rem_euclid(1.0);
+        let t = if span > 0.0 {
+            (self.time_of_day - prev.time_of_day).rem_euclid(1.0) / span
+        } else {
+            0.0
+        };
+
+        Some(DayNightKeyframe {
+            time_of_day: self.time_of_day,
+            light_color: prev.light_color.lerp(next.light_color, t),
+            ambient_light: prev.ambient_light.lerp(next.ambient_light, t),
+            sky_color: prev.sky_color.lerp(next.sky_color, t),
+            horizon_color: prev.horizon_color.lerp(next.horizon_color, t),
+        })
+    }
+}
@@ -1 +1,4 @@
 pub mod camera_controller;
+pub mod controller;
+pub mod day_night_controller;
+pub mod orbit_camera_controller;
@@ -0,0 +1,121 @@
+use glam::{Quat, Vec2, Vec3};
+use winit::event::MouseButton;
+
+use crate::{
+    core::{input_manager::InputManager, time_manager::TimeManager},
+    render::{coordinate_system::CoordinateSystem, render_manager::RenderManager},
+    utils::terrain_generator::TerrainBounds,
+};
+
+use super::controller::{framing_distance, Controller, MAX_PITCH};
+
+#[derive(Clone, Copy)]
+pub struct OrbitCameraSettings {
+    pub focus: Vec3,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub initial_rotation_angles: Vec2,
+    pub zoom_speed: f32,
+    pub pan_speed: f32,
+}
+
+impl Default for OrbitCameraSettings {
+    fn default() -> Self {
+        Self {
+            focus: Vec3::new(5.625, 0.0, 5.625),
+            distance: 10.0,
+            min_distance: 1.0,
+            max_distance: 50.0,
+            initial_rotation_angles: Vec2::new(0.0, 30.0),
+            zoom_speed: 0.1,
+            pan_speed: 0.01,
+        }
+    }
+}
+
+pub struct OrbitCameraController {
+    settings: OrbitCameraSettings,
+    focus: Vec3,
+    distance: f32,
+    rotation_angles: Vec2,
+}
+
+impl OrbitCameraController {
+    pub fn new(settings: &OrbitCameraSettings) -> OrbitCameraController {
+        Self {
+            settings: *settings,
+            focus: settings.focus,
+            distance: settings.distance,
+            rotation_angles: settings.initial_rotation_angles,
+        }
+    }
+
+    pub fn focus(&self) -> Vec3 {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, focus: Vec3) {
+        self.focus = focus;
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.clamp(self.settings.min_distance, self.settings.max_distance);
+    }
+
+    fn rotation(&self, coordinate_system: CoordinateSystem) -> Quat {
+        coordinate_system.yaw_pitch_rotation(self.rotation_angles.x, self.rotation_angles.y)
+    }
+
+    fn apply(&self, render_manager: &mut RenderManager) {
+        let coordinate_system = render_manager.coordinate_system();
+        let rotation = self.rotation(coordinate_system);
+        let position =
+            self.focus - rotation.mul_vec3(coordinate_system.camera_forward()) * self.distance;
+
+        let mut camera = render_manager.camera().borrow_mut();
+
+        camera.set_position(position);
+        camera.set_rotation(rotation);
+    }
+}
+
+impl Controller for OrbitCameraController {
+    fn update(
+        &mut self,
+        _time_manager: &TimeManager,
+        input_manager: &InputManager,
+        render_manager: &mut RenderManager,
+    ) {
+        if input_manager.is_mouse_button_pressed(MouseButton::Left) {
+            self.rotation_angles += input_manager.smoothed_look_delta();
+            self.rotation_angles.y = self.rotation_angles.y.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        self.set_distance(
+            self.distance * (-input_manager.scroll_delta() * self.settings.zoom_speed).exp(),
+        );
+
+        if input_manager.is_mouse_button_pressed(MouseButton::Middle) {
+            let delta =
+                input_manager.smoothed_look_delta() * self.settings.pan_speed * self.distance;
+            let rotation = self.rotation(render_manager.coordinate_system());
+            self.focus += rotation.mul_vec3(Vec3::new(-delta.x, delta.y, 0.0));
+        }
+
+        self.apply(render_manager);
+    }
+
+    /// The framing distance is still limited by `max_distance`
+    fn frame(&mut self, bounds: &TerrainBounds, render_manager: &mut RenderManager) {
+        self.focus = bounds.center();
+        let distance = framing_distance(bounds, &render_manager.camera().borrow());
+        self.set_distance(distance);
+
+        self.apply(render_manager);
+    }
+}
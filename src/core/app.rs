@@ -1,39 +1,99 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+use wgpu::{PolygonMode, SurfaceError};
 use winit::{
     dpi::{PhysicalSize, Size},
-    event::{Event, WindowEvent},
+    event::{DeviceEvent, ElementState, Event, KeyEvent, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
-    window::{Window, WindowBuilder},
+    window::{CursorGrabMode, Fullscreen, Icon, Window, WindowBuilder},
 };
 
 use crate::{
-    controllers::camera_controller::{CameraController, CameraSettings},
+    controllers::{
+        camera_controller::{CameraController, CameraSettings},
+        controller::{Controller, ControllerType},
+        day_night_controller::{DayNightController, DayNightSettings},
+        orbit_camera_controller::{OrbitCameraController, OrbitCameraSettings},
+    },
     render::{
-        mesh_renderer::MeshRenderer,
-        render_manager::{RenderManager, RenderSettings},
+        debug_line_renderer::{DebugLineRenderer, DebugLineRendererSettings},
+        foliage_renderer::{FoliageRenderer, FoliageSettings},
+        gpu_timer::RendererId,
+        grid_renderer::{GridRenderer, GridSettings},
+        mesh_renderer::{MeshRenderer, MeshRendererSettings},
+        minimap::{Minimap, MinimapSettings},
+        render_manager::{BackgroundMode, RenderManager, RenderSettings},
+        renderer::Renderer,
+        shader::ShaderSource,
         skybox_renderer::{SkyboxRenderer, SkyboxRendererSettings},
         water_renderer::{WaterRenderer, WaterRendererSettings},
     },
-    utils::terrain_generator::generate_terrain_mesh,
+    utils::{
+        terrain_generator::{
+            generate_terrain_mesh, TerrainBounds, TerrainHeights, TerrainSettings,
+        },
+        terrain_noise::TerrainNoise,
+    },
 };
 
 use super::{
-    input_manager::{InputManager, InputSettings},
+    error::TerrainError,
+    input_manager::{InputAction, InputManager, InputSettings},
     time_manager::TimeManager,
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CursorMode {
+    Captured,
+    Free,
+}
+
 #[derive(Clone)]
 pub struct AppSettings {
-    initial_size: Size,
-    title: String,
-    resizable: bool,
-    target_frame_rate: u32,
-    input_settings: InputSettings,
-    render_settings: RenderSettings,
-    camera_settings: CameraSettings,
-    skybox_renderer_settings: SkyboxRendererSettings,
-    water_renderer_settings: WaterRendererSettings,
+    pub initial_size: Size,
+    pub title: String,
+    pub resizable: bool,
+    pub fullscreen: Option<Fullscreen>,
+    pub decorations: bool,
+    pub maximized: bool,
+    /// Encoded PNG image
+    pub window_icon: Option<Vec<u8>>,
+    pub target_frame_rate: u32,
+    pub fixed_timestep: f32,
+    /// Stops updating and rendering while the window is unfocused
+    pub pause_when_unfocused: bool,
+    pub initial_cursor_mode: CursorMode,
+    pub input_settings: InputSettings,
+    pub render_settings: RenderSettings,
+    pub controller_type: ControllerType,
+    pub camera_settings: CameraSettings,
+    pub orbit_camera_settings: OrbitCameraSettings,
+    /// Moves the camera at startup so the whole terrain is in view, overriding the
+    /// initial camera position
+    pub frame_terrain: bool,
+    /// Adds the skybox when the background mode is `Skybox`
+    pub enable_skybox: bool,
+    /// Renders the generated terrain. The terrain is still generated without it, as
+    /// the camera and the overlays rely on it.
+    pub enable_default_terrain: bool,
+    pub enable_water: bool,
+    pub day_night_settings: Option<DayNightSettings>,
+    pub skybox_renderer_settings: SkyboxRendererSettings,
+    pub water_renderer_settings: WaterRendererSettings,
+    pub debug_line_renderer_settings: DebugLineRendererSettings,
+    pub grid_settings: GridSettings,
+    pub terrain_settings: TerrainSettings<TerrainNoise>,
+    pub terrain_shader: ShaderSource,
+    pub minimap_settings: MinimapSettings,
+    pub foliage_settings: FoliageSettings,
+    pub screenshot_dir: PathBuf,
 }
 
 impl Default for AppSettings {
@@ -42,83 +102,342 @@ impl Default for AppSettings {
             initial_size: Size::Physical(PhysicalSize::new(800, 600)),
             title: "App".into(),
             resizable: true,
+            fullscreen: None,
+            decorations: true,
+            maximized: false,
+            window_icon: None,
             target_frame_rate: 30,
+            fixed_timestep: 1.0 / 60.0,
+            pause_when_unfocused: false,
+            initial_cursor_mode: CursorMode::Captured,
             input_settings: Default::default(),
             render_settings: Default::default(),
+            controller_type: ControllerType::FreeFly,
             camera_settings: Default::default(),
+            orbit_camera_settings: Default::default(),
+            frame_terrain: true,
+            enable_skybox: true,
+            enable_default_terrain: true,
+            enable_water: true,
+            day_night_settings: None,
             skybox_renderer_settings: Default::default(),
             water_renderer_settings: Default::default(),
+            debug_line_renderer_settings: Default::default(),
+            grid_settings: Default::default(),
+            terrain_settings: Default::default(),
+            terrain_shader: ShaderSource::Builtin,
+            minimap_settings: Default::default(),
+            foliage_settings: Default::default(),
+            screenshot_dir: "screenshots".into(),
         }
     }
 }
 
+/// Returns whether the event was consumed, which skips the later hooks and the
+/// built-in handling
+pub type EventHook = Box<dyn FnMut(&WindowEvent) -> bool>;
+
 pub struct App<'a> {
     event_loop: Option<EventLoop<()>>,
-    _window: Arc<Window>,
-    min_render_time: f32,
+    window: Arc<Window>,
+    cursor_mode: CursorMode,
+    min_render_time: Option<Duration>,
     last_render_time: Instant,
+    pause_when_unfocused: bool,
+    is_focused: bool,
     time_manager: TimeManager,
     input_manager: InputManager,
     render_manager: RenderManager<'a>,
-    camera_controller: CameraController,
+    camera_controller: Box<dyn Controller>,
+    day_night_controller: Option<DayNightController>,
+    debug_line_renderer: Rc<RefCell<DebugLineRenderer>>,
+    terrain_bounds: TerrainBounds,
+    render_error: Option<TerrainError>,
+    screenshot_dir: PathBuf,
+    is_screenshot_requested: bool,
+    last_screenshot_path: Option<PathBuf>,
+    screenshot_error: Option<TerrainError>,
+    event_hooks: Vec<EventHook>,
 }
 
 impl<'a> App<'a> {
-    pub async fn new(settings: &AppSettings) -> Result<App<'a>, String> {
-        let event_loop = EventLoop::new().map_err(|err| err.to_string())?;
+    pub async fn new(settings: &AppSettings) -> Result<App<'a>, TerrainError> {
+        // Fixed steps would never consume the accumulated time
+        if !(settings.fixed_timestep > 0.0 && settings.fixed_timestep.is_finite()) {
+            return Err(TerrainError::InvalidAppSettings(format!(
+                "fixed timestep must be positive, but it's {}",
+                settings.fixed_timestep
+            )));
+        }
+
+        let event_loop = EventLoop::new()?;
         event_loop.set_control_flow(ControlFlow::Poll);
 
+        let window_icon = settings
+            .window_icon
+            .as_deref()
+            .map(Self::load_window_icon)
+            .transpose()?;
+
         let window = Arc::new(
             WindowBuilder::new()
                 .with_inner_size(settings.initial_size)
                 .with_title(settings.title.clone())
                 .with_resizable(settings.resizable)
-                .build(&event_loop)
-                .map_err(|err| err.to_string())?,
+                .with_fullscreen(settings.fullscreen.clone())
+                .with_decorations(settings.decorations)
+                .with_maximized(settings.maximized)
+                .with_window_icon(window_icon)
+                .build(&event_loop)?,
         );
 
         let mut render_manager =
             RenderManager::new(&settings.render_settings, window.clone()).await?;
 
-        render_manager.add_renderer(Box::new(SkyboxRenderer::new(
-            &settings.skybox_renderer_settings,
-            &render_manager,
-        )));
-        render_manager.add_renderer(Box::new(MeshRenderer::new(
-            generate_terrain_mesh(render_manager.device(), &Default::default()),
-            &render_manager,
-        )));
-        render_manager.add_renderer(Box::new(WaterRenderer::new(
-            &settings.water_renderer_settings,
+        let skybox_renderer = if settings.enable_skybox
+            && render_manager.background_mode() == BackgroundMode::Skybox
+        {
+            let skybox_renderer = Rc::new(RefCell::new(SkyboxRenderer::new(
+                &settings.skybox_renderer_settings,
+                &render_manager,
+            )?));
+            render_manager.add_renderer(Box::new(skybox_renderer.clone()));
+            Some(skybox_renderer)
+        } else {
+            None
+        };
+
+        let (terrain_mesh, terrain_bounds) =
+            generate_terrain_mesh(render_manager.device(), &settings.terrain_settings)?;
+        if settings.minimap_settings.enabled {
+            render_manager.add_renderer(Box::new(Minimap::new(
+                &terrain_mesh,
+                &settings.minimap_settings,
+                &render_manager,
+            )));
+        }
+        if settings.enable_default_terrain {
+            render_manager.add_renderer(Box::new(MeshRenderer::new(
+                terrain_mesh,
+                &MeshRendererSettings {
+                    caustics: settings
+                        .enable_water
+                        .then(|| settings.water_renderer_settings.caustics_settings()),
+                    shader: settings.terrain_shader.clone(),
+                    front_face: render_manager.coordinate_system().front_face(),
+                    polygon_mode: if settings.render_settings.wireframe {
+                        PolygonMode::Line
+                    } else {
+                        PolygonMode::Fill
+                    },
+                    ..Default::default()
+                },
+                &render_manager,
+            )?));
+        }
+        if settings.foliage_settings.enabled {
+            render_manager.add_renderer(Box::new(FoliageRenderer::new(
+                &settings.terrain_settings,
+                &settings.foliage_settings,
+                &render_manager,
+            )));
+        }
+        if settings.enable_water {
+            render_manager.add_renderer(Box::new(WaterRenderer::new(
+                &settings.water_renderer_settings,
+                &render_manager,
+            )?));
+        }
+
+        if settings.grid_settings.enabled {
+            render_manager.add_renderer(Box::new(GridRenderer::new(
+                &settings.grid_settings,
+                &render_manager,
+            )));
+        }
+
+        let debug_line_renderer = Rc::new(RefCell::new(DebugLineRenderer::new(
+            &settings.debug_line_renderer_settings,
             &render_manager,
         )));
+        render_manager.add_renderer(Box::new(debug_line_renderer.clone()));
 
-        Ok(App {
+        let mut app = App {
             event_loop: Some(event_loop),
-            _window: window,
-            min_render_time: (1.0 / (settings.target_frame_rate as f32)),
+            window,
+            cursor_mode: settings.initial_cursor_mode,
+            min_render_time: (settings.target_frame_rate > 0)
+                .then(|| Duration::from_secs_f32(1.0 / (settings.target_frame_rate as f32))),
             last_render_time: Instant::now(),
-            time_manager: TimeManager::new(),
+            pause_when_unfocused: settings.pause_when_unfocused,
+            is_focused: true,
+            time_manager: TimeManager::new(settings.fixed_timestep),
             input_manager: InputManager::new(&settings.input_settings),
             render_manager,
-            camera_controller: CameraController::new(&settings.camera_settings),
-        })
+            camera_controller: Self::create_controller(settings, terrain_bounds),
+            day_night_controller: settings
+                .day_night_settings
+                .as_ref()
+                .map(|s| DayNightController::new(s, skybox_renderer)),
+            debug_line_renderer,
+            terrain_bounds,
+            render_error: None,
+            screenshot_dir: settings.screenshot_dir.clone(),
+            is_screenshot_requested: false,
+            last_screenshot_path: None,
+            screenshot_error: None,
+            event_hooks: Vec::new(),
+        };
+        app.set_cursor_mode(settings.initial_cursor_mode);
+        if settings.frame_terrain {
+            app.camera_controller
+                .frame(&app.terrain_bounds, &mut app.render_manager);
+        }
+
+        Ok(app)
+    }
+
+    pub fn cursor_mode(&self) -> CursorMode {
+        self.cursor_mode
+    }
+
+    pub fn set_cursor_mode(&mut self, cursor_mode: CursorMode) {
+        match cursor_mode {
+            CursorMode::Captured => {
+                // Locking isn't supported everywhere (e.g. X11), so fall back to confining
+                if self.window.set_cursor_grab(CursorGrabMode::Locked).is_err() {
+                    let _ = self.window.set_cursor_grab(CursorGrabMode::Confined);
+                }
+                self.window.set_cursor_visible(false);
+            }
+            CursorMode::Free => {
+                let _ = self.window.set_cursor_grab(CursorGrabMode::None);
+                self.window.set_cursor_visible(true);
+            }
+        }
+
+        self.cursor_mode = cursor_mode;
+        self.input_manager
+            .set_look_active(cursor_mode == CursorMode::Captured);
+    }
+
+    /// Gives the device and the target formats for creating custom renderers
+    pub fn render_manager(&self) -> &RenderManager<'a> {
+        &self.render_manager
+    }
+
+    /// Adds a custom renderer, e.g. between creating the app and running it
+    pub fn add_renderer(&mut self, renderer: Box<dyn Renderer>) -> RendererId {
+        self.render_manager.add_renderer(renderer)
+    }
+
+    pub fn debug_line_renderer(&self) -> &RefCell<DebugLineRenderer> {
+        &self.debug_line_renderer
+    }
+
+    /// Physical pixels per logical pixel of the window's current monitor
+    pub fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
+    pub fn terrain_bounds(&self) -> TerrainBounds {
+        self.terrain_bounds
+    }
+
+    /// Path of the last screenshot saved with the screenshot key
+    pub fn last_screenshot_path(&self) -> Option<&Path> {
+        self.last_screenshot_path.as_deref()
+    }
+
+    /// Why the last screenshot requested with the screenshot key failed, `None` when
+    /// it was saved
+    pub fn screenshot_error(&self) -> Option<&TerrainError> {
+        self.screenshot_error.as_ref()
+    }
+
+    /// Captures the current frame and saves it as a timestamped PNG in the screenshot
+    /// directory, returning the written path
+    pub fn save_screenshot(&mut self) -> Result<PathBuf, TerrainError> {
+        let image = self.render_manager.capture_frame()?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        fs::create_dir_all(&self.screenshot_dir)?;
+        let path = self
+            .screenshot_dir
+            .join(format!("screenshot_{timestamp}.png"));
+        image.save(&path)?;
+
+        self.last_screenshot_path = Some(path.clone());
+        Ok(path)
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
+    /// Registers a hook for window events, which runs before the app handles them.
+    /// Hooks run in the order they were registered.
+    pub fn on_event(&mut self, hook: EventHook) {
+        self.event_hooks.push(hook);
+    }
+
+    fn load_window_icon(bytes: &[u8]) -> Result<Icon, TerrainError> {
+        let image = image::load_from_memory(bytes)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Err(TerrainError::InvalidIcon(format!(
+                "image is {width}x{height} pixels"
+            )));
+        }
+
+        Icon::from_rgba(image.into_raw(), width, height)
+            .map_err(|error| TerrainError::InvalidIcon(error.to_string()))
+    }
+
+    fn create_controller(
+        settings: &AppSettings,
+        terrain_bounds: TerrainBounds,
+    ) -> Box<dyn Controller> {
+        match settings.controller_type {
+            ControllerType::FreeFly => {
+                let mut controller = CameraController::new(&settings.camera_settings);
+                if settings.camera_settings.collide_with_terrain {
+                    controller
+                        .set_terrain_heights(Some(TerrainHeights::new(&settings.terrain_settings)));
+                }
+                if settings.camera_settings.auto_fit_planes {
+                    controller.set_terrain_bounds(Some(terrain_bounds));
+                }
+
+                Box::new(controller)
+            }
+            ControllerType::Orbit => {
+                Box::new(OrbitCameraController::new(&settings.orbit_camera_settings))
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), TerrainError> {
         let event_loop = self.event_loop.take().unwrap();
 
-        event_loop
-            .run(move |event, elwt| {
-                self.handle_event(event, elwt);
-                self.update();
-            })
-            .map_err(|err| err.to_string())?;
+        let app = &mut *self;
+        event_loop.run(move |event, elwt| {
+            app.handle_event(event, elwt);
+            app.update(elwt);
+        })?;
 
-        Ok(())
+        match self.render_error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
     }
 
     fn handle_event(&mut self, event: Event<()>, elwt: &EventLoopWindowTarget<()>) {
+        if let Event::WindowEvent { event, .. } = &event {
+            if self.event_hooks.iter_mut().any(|hook| hook(event)) {
+                return;
+            }
+        }
+
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
@@ -128,10 +447,16 @@ impl<'a> App<'a> {
                 event: WindowEvent::Resized(size),
                 ..
             } => self.render_manager.handle_resize(size),
+            // Not every platform sends `Resized` after a scale factor change. When one
+            // follows, its size replaces this one before the next frame applies it.
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { .. },
+                ..
+            } => self.render_manager.handle_resize(self.window.inner_size()),
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput { event, .. },
                 ..
-            } => self.input_manager.handle_keyboard_input(event),
+            } => self.handle_keyboard_input(event),
             Event::WindowEvent {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
@@ -140,27 +465,130 @@ impl<'a> App<'a> {
                 event: WindowEvent::CursorEntered { .. },
                 ..
             } => self.input_manager.handle_cursor_enter(),
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { state, button, .. },
+                ..
+            } => self.input_manager.handle_mouse_input(state, button),
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => self.input_manager.handle_mouse_wheel(delta),
+            Event::WindowEvent {
+                event: WindowEvent::Focused(focused),
+                ..
+            } => self.handle_focus(focused),
+            // Raw device events arrive regardless of focus
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if !self.is_paused() => self.input_manager.handle_mouse_motion(delta),
             _ => (),
         }
     }
 
-    fn update(&mut self) {
+    fn handle_keyboard_input(&mut self, event: KeyEvent) {
+        if event.state == ElementState::Pressed && !event.repeat {
+            if event.physical_key == self.input_manager.key(InputAction::ToggleCursor) {
+                self.set_cursor_mode(match self.cursor_mode {
+                    CursorMode::Captured => CursorMode::Free,
+                    CursorMode::Free => CursorMode::Captured,
+                });
+            }
+            if event.physical_key == self.input_manager.key(InputAction::Screenshot) {
+                self.is_screenshot_requested = true;
+            }
+        }
+
+        self.input_manager.handle_keyboard_input(event);
+    }
+
+    fn handle_focus(&mut self, focused: bool) {
+        let was_paused = self.is_paused();
+        self.is_focused = focused;
+
+        // Keys released while unfocused never send their release events
+        if !focused {
+            self.input_manager.release_all();
+        }
+        if was_paused && !self.is_paused() {
+            self.time_manager.skip_elapsed();
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_when_unfocused && !self.is_focused
+    }
+
+    fn update(&mut self, elwt: &EventLoopWindowTarget<()>) {
+        // Focus events wake the loop up again
+        if self.is_paused() {
+            elwt.set_control_flow(ControlFlow::Wait);
+            return;
+        }
+
         let instant = Instant::now();
-        let t = instant.duration_since(self.last_render_time).as_secs_f32();
+        let t = instant.duration_since(self.last_render_time);
 
-        if t > self.min_render_time {
+        if self
+            .min_render_time
+            .is_none_or(|min_render_time| t >= min_render_time)
+            && !self.render_manager.is_minimized()
+            && self.render_error.is_none()
+        {
             self.last_render_time = instant;
             self.time_manager.update();
-            self.camera_controller.update(
-                &self.time_manager,
-                &self.input_manager,
-                &mut self.render_manager,
-            );
-            self.render_manager
-                .render(&self.time_manager)
-                .expect("Error occured while rendering");
 
-            self.input_manager.late_update();
+            while self.time_manager.consume_fixed_step() {
+                self.fixed_update();
+            }
+
+            if let Some(day_night_controller) = &mut self.day_night_controller {
+                day_night_controller.update(&self.time_manager, &mut self.render_manager);
+            }
+
+            match self.render_manager.render(&self.time_manager) {
+                Ok(()) => (),
+                Err(TerrainError::Surface(SurfaceError::Lost | SurfaceError::Outdated)) => {
+                    self.render_manager.reconfigure_surface();
+                }
+                Err(TerrainError::Surface(SurfaceError::Timeout)) => (),
+                Err(error) => {
+                    self.render_error = Some(error);
+                    elwt.exit();
+                }
+            }
+
+            if self.is_screenshot_requested {
+                self.is_screenshot_requested = false;
+
+                // A failed screenshot shouldn't stop the app
+                self.screenshot_error = self.save_screenshot().err();
+            }
         }
+
+        // Sleep until the next frame is due instead of spinning, a target frame rate
+        // of 0 keeps polling
+        if let Some(min_render_time) = self.min_render_time {
+            let now = Instant::now();
+            let mut next_frame = self.last_render_time + min_render_time;
+            if next_frame <= now {
+                next_frame = now + min_render_time;
+            }
+
+            elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+        } else {
+            // Pausing may have switched the loop to waiting
+            elwt.set_control_flow(ControlFlow::Poll);
+        }
+    }
+
+    fn fixed_update(&mut self) {
+        self.camera_controller.update(
+            &self.time_manager,
+            &self.input_manager,
+            &mut self.render_manager,
+        );
+
+        self.input_manager.late_update();
     }
 }
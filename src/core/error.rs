@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use image::ImageError;
+use thiserror::Error;
+use wgpu::{
+    BufferAsyncError, CreateSurfaceError, Features, PrimitiveTopology, RequestDeviceError,
+    SurfaceError,
+};
+use winit::error::{EventLoopError, OsError};
+
+#[derive(Error, Debug)]
+pub enum TerrainError {
+    #[error("{0}")]
+    SurfaceCreation(#[from] CreateSurfaceError),
+    #[error("{0}")]
+    Surface(#[from] SurfaceError),
+    #[error("Requested adapter was None, available adapters: [{}]", .0.join(", "))]
+    Adapter(Vec<String>),
+    #[error("{0}")]
+    Device(#[from] RequestDeviceError),
+    #[error("Adapter doesn't support required features: {0:?}")]
+    MissingFeatures(Features),
+    #[error("Adapter doesn't support required limits: {}", .0.join(", "))]
+    UnsupportedLimits(Vec<String>),
+    #[error("{0}")]
+    WindowCreation(#[from] OsError),
+    #[error("{0}")]
+    EventLoop(#[from] EventLoopError),
+    #[error("{0}")]
+    BufferMap(#[from] BufferAsyncError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Image(#[from] ImageError),
+    #[error("Invalid cubemap: {0}")]
+    InvalidCubemap(String),
+    #[error("Invalid color ramp: {0}")]
+    InvalidColorRamp(String),
+    #[error("Invalid terrain settings: {0}")]
+    InvalidTerrainSettings(String),
+    #[error("Mesh topology {0:?} doesn't match the pipeline topology {1:?}")]
+    MismatchedTopology(PrimitiveTopology, PrimitiveTopology),
+    #[error("Invalid app settings: {0}")]
+    InvalidAppSettings(String),
+    #[error("Invalid window icon: {0}")]
+    InvalidIcon(String),
+    #[error("Failed to compile shader {0}: {1}")]
+    ShaderCompilation(String, String),
+    #[error("Unknown language of shader {}", .0.display())]
+    UnknownShaderLanguage(PathBuf),
+    #[error("Unknown noise type: {0}")]
+    UnknownNoise(String),
+}
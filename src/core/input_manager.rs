@@ -1,31 +1,78 @@
+use std::collections::HashSet;
+
 use glam::{Vec2, Vec3};
 use winit::{
     dpi::PhysicalPosition,
-    event::{ElementState, KeyEvent},
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta},
     keyboard::{KeyCode, PhysicalKey},
 };
 
+const SCROLL_PIXELS_PER_LINE: f32 = 40.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InputAction {
+    MoveRight,
+    MoveLeft,
+    MoveUp,
+    MoveDown,
+    MoveForward,
+    MoveBackward,
+    RollLeft,
+    RollRight,
+    Sprint,
+    ToggleCursor,
+    Screenshot,
+}
+
+impl InputAction {
+    /// Every action, in declaration order
+    pub const ALL: &'static [InputAction] = &[
+        InputAction::MoveRight,
+        InputAction::MoveLeft,
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::MoveForward,
+        InputAction::MoveBackward,
+        InputAction::RollLeft,
+        InputAction::RollRight,
+        InputAction::Sprint,
+        InputAction::ToggleCursor,
+        InputAction::Screenshot,
+    ];
+
+    pub const COUNT: usize = InputAction::ALL.len();
+}
+
 #[derive(Clone, Copy)]
 pub struct InputSettings {
-    look_sensitivity: f32,
-    right_key: PhysicalKey,
-    left_key: PhysicalKey,
-    up_key: PhysicalKey,
-    down_key: PhysicalKey,
-    forward_key: PhysicalKey,
-    backward_key: PhysicalKey,
+    pub look_sensitivity: f32,
+    /// Share of the previous smoothed look delta kept each fixed step, in `[0, 1)`.
+    /// 0 disables smoothing.
+    pub look_smoothing: f32,
+    /// Look deltas shorter than this, after applying the sensitivity, are ignored
+    pub look_deadzone: f32,
+    pub key_bindings: [PhysicalKey; InputAction::COUNT],
 }
 
 impl Default for InputSettings {
     fn default() -> Self {
         Self {
             look_sensitivity: 0.2,
-            right_key: PhysicalKey::Code(KeyCode::KeyD),
-            left_key: PhysicalKey::Code(KeyCode::KeyA),
-            up_key: PhysicalKey::Code(KeyCode::Space),
-            down_key: PhysicalKey::Code(KeyCode::ControlLeft),
-            forward_key: PhysicalKey::Code(KeyCode::KeyW),
-            backward_key: PhysicalKey::Code(KeyCode::KeyS),
+            look_smoothing: 0.0,
+            look_deadzone: 0.0,
+            key_bindings: [
+                PhysicalKey::Code(KeyCode::KeyD),
+                PhysicalKey::Code(KeyCode::KeyA),
+                PhysicalKey::Code(KeyCode::Space),
+                PhysicalKey::Code(KeyCode::ControlLeft),
+                PhysicalKey::Code(KeyCode::KeyW),
+                PhysicalKey::Code(KeyCode::KeyS),
+                PhysicalKey::Code(KeyCode::KeyQ),
+                PhysicalKey::Code(KeyCode::KeyE),
+                PhysicalKey::Code(KeyCode::ShiftLeft),
+                PhysicalKey::Code(KeyCode::Escape),
+                PhysicalKey::Code(KeyCode::F12),
+            ],
         }
     }
 }
@@ -34,8 +81,13 @@ pub struct InputManager {
     settings: Box<InputSettings>,
     last_cursor_pos: Vec2,
     cursor_just_entered: bool,
-    move_vector: Vec3,
+    pressed_keys: HashSet<PhysicalKey>,
+    is_look_active: bool,
     look_delta: Vec2,
+    // Kept across steps, unlike the raw delta
+    smoothed_look_delta: Vec2,
+    scroll_delta: f32,
+    pressed_mouse_buttons: Vec<MouseButton>,
 }
 
 impl InputManager {
@@ -44,67 +96,207 @@ impl InputManager {
             settings: Box::new(*settings),
             last_cursor_pos: Default::default(),
             cursor_just_entered: true,
-            move_vector: Vec3::ZERO,
+            pressed_keys: HashSet::new(),
+            is_look_active: true,
             look_delta: Vec2::ZERO,
+            smoothed_look_delta: Vec2::ZERO,
+            scroll_delta: 0.0,
+            pressed_mouse_buttons: Vec::new(),
         }
     }
 
-    pub fn handle_keyboard_input(&mut self, event: KeyEvent) {
-        let key = event.physical_key;
+    pub fn key(&self, action: InputAction) -> PhysicalKey {
+        self.settings.key_bindings[action as usize]
+    }
+
+    pub fn rebind(&mut self, action: InputAction, key: PhysicalKey) {
+        self.settings.key_bindings[action as usize] = key;
+    }
 
+    pub fn handle_keyboard_input(&mut self, event: KeyEvent) {
         match event.state {
-            ElementState::Pressed => {
-                if self.settings.right_key == key {
-                    self.move_vector.x = 1.0;
-                } else if self.settings.left_key == key {
-                    self.move_vector.x = -1.0;
-                } else if self.settings.up_key == key {
-                    self.move_vector.y = 1.0;
-                } else if self.settings.down_key == key {
-                    self.move_vector.y = -1.0;
-                } else if self.settings.forward_key == key {
-                    self.move_vector.z = 1.0;
-                } else if self.settings.backward_key == key {
-                    self.move_vector.z = -1.0;
-                }
-            }
-            ElementState::Released => {
-                if self.settings.right_key == key || self.settings.left_key == key {
-                    self.move_vector.x = 0.0;
-                } else if self.settings.up_key == key || self.settings.down_key == key {
-                    self.move_vector.y = 0.0;
-                } else if self.settings.forward_key == key || self.settings.backward_key == key {
-                    self.move_vector.z = 0.0;
-                }
-            }
-        }
+            ElementState::Pressed => self.pressed_keys.insert(event.physical_key),
+            ElementState::Released => self.pressed_keys.remove(&event.physical_key),
+        };
     }
 
     pub fn handle_cursor_movement(&mut self, cursor_position: PhysicalPosition<f64>) {
         let cursor_pos: Vec2 = mint::Point2::from(cursor_position.cast::<f32>()).into();
 
-        self.look_delta = if self.cursor_just_entered {
-            Vec2::ZERO
-        } else {
-            (cursor_pos - self.last_cursor_pos) * self.settings.look_sensitivity
-        };
+        if !self.cursor_just_entered {
+            self.add_look_delta(cursor_pos - self.last_cursor_pos, false);
+        }
         self.last_cursor_pos = cursor_pos;
         self.cursor_just_entered = false;
     }
 
+    pub fn handle_mouse_motion(&mut self, delta: (f64, f64)) {
+        self.add_look_delta(Vec2::new(delta.0 as f32, delta.1 as f32), true);
+    }
+
+    /// A captured cursor doesn't move, so raw mouse motion is used while look is
+    /// active and cursor movement otherwise. The look delta gets the movement in both
+    /// modes, leaving it to controllers whether they follow it.
+    fn add_look_delta(&mut self, delta: Vec2, is_raw_motion: bool) {
+        if is_raw_motion == self.is_look_active {
+            self.look_delta += delta * self.settings.look_sensitivity;
+        }
+    }
+
     pub fn handle_cursor_enter(&mut self) {
         self.cursor_just_entered = true;
     }
 
+    pub fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        match state {
+            ElementState::Pressed => {
+                if !self.pressed_mouse_buttons.contains(&button) {
+                    self.pressed_mouse_buttons.push(button);
+                }
+            }
+            ElementState::Released => self.pressed_mouse_buttons.retain(|b| *b != button),
+        }
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        self.scroll_delta += match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / SCROLL_PIXELS_PER_LINE,
+        };
+    }
+
+    /// Forgets held keys and buttons, whose release events go to another window once
+    /// focus is lost
+    pub fn release_all(&mut self) {
+        self.pressed_keys.clear();
+        self.pressed_mouse_buttons.clear();
+    }
+
     pub fn late_update(&mut self) {
+        self.smoothed_look_delta = self.smoothed_look_delta();
         self.look_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_pressed(&self, key: PhysicalKey) -> bool {
+        self.pressed_keys.contains(&key)
     }
 
+    pub fn is_action_pressed(&self, action: InputAction) -> bool {
+        self.is_key_pressed(self.key(action))
+    }
+
+    /// Opposite keys held together cancel each other out
     pub fn move_vector(&self) -> Vec3 {
-        self.move_vector
+        let axis = |positive, negative| {
+            self.is_action_pressed(positive) as i32 as f32
+                - self.is_action_pressed(negative) as i32 as f32
+        };
+
+        Vec3::new(
+            axis(InputAction::MoveRight, InputAction::MoveLeft),
+            axis(InputAction::MoveUp, InputAction::MoveDown),
+            axis(InputAction::MoveForward, InputAction::MoveBackward),
+        )
+    }
+
+    /// 1 rolls clockwise, -1 counter-clockwise
+    pub fn roll_axis(&self) -> f32 {
+        self.is_action_pressed(InputAction::RollRight) as i32 as f32
+            - self.is_action_pressed(InputAction::RollLeft) as i32 as f32
+    }
+
+    pub fn is_sprinting(&self) -> bool {
+        self.is_action_pressed(InputAction::Sprint)
+    }
+
+    pub fn is_look_active(&self) -> bool {
+        self.is_look_active
+    }
+
+    pub fn set_look_active(&mut self, is_look_active: bool) {
+        self.is_look_active = is_look_active;
+        self.look_delta = Vec2::ZERO;
+        self.smoothed_look_delta = Vec2::ZERO;
+        self.cursor_just_entered = true;
     }
 
     pub fn look_delta(&self) -> Vec2 {
         self.look_delta
     }
+
+    /// Look delta with the deadzone and smoothing applied. Smoothing delays the
+    /// movement rather than dropping it, so the total rotation stays the same.
+    pub fn smoothed_look_delta(&self) -> Vec2 {
+        let delta = if self.look_delta.length() < self.settings.look_deadzone {
+            Vec2::ZERO
+        } else {
+            self.look_delta
+        };
+        let smoothing = self.settings.look_smoothing.clamp(0.0, 0.99);
+
+        self.smoothed_look_delta.lerp(delta, 1.0 - smoothing)
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_mouse_buttons.contains(&button)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releasing_opposite_key_resumes_movement() {
+        let mut input_manager = InputManager::new(&Default::default());
+        let forward = input_manager.key(InputAction::MoveForward);
+        let backward = input_manager.key(InputAction::MoveBackward);
+
+        input_manager.pressed_keys.insert(forward);
+        assert_eq!(input_manager.move_vector(), Vec3::Z);
+
+        input_manager.pressed_keys.insert(backward);
+        assert_eq!(input_manager.move_vector(), Vec3::ZERO);
+
+        input_manager.pressed_keys.remove(&backward);
+        assert_eq!(input_manager.move_vector(), Vec3::Z);
+    }
+
+    #[test]
+    fn look_delta_follows_the_pointer_in_both_modes() {
+        // Orbit controls read the delta whether or not look is active
+        for is_look_active in [false, true] {
+            let mut input_manager = InputManager::new(&Default::default());
+            input_manager.set_look_active(is_look_active);
+            let sensitivity = input_manager.settings.look_sensitivity;
+
+            input_manager.handle_cursor_movement(PhysicalPosition::new(10.0, 10.0));
+            input_manager.handle_cursor_movement(PhysicalPosition::new(20.0, 15.0));
+            input_manager.handle_mouse_motion((10.0, 5.0));
+
+            assert_eq!(
+                input_manager.look_delta(),
+                Vec2::new(10.0, 5.0) * sensitivity
+            );
+            assert_eq!(
+                input_manager.smoothed_look_delta(),
+                input_manager.look_delta()
+            );
+
+            input_manager.late_update();
+            assert_eq!(input_manager.look_delta(), Vec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn all_actions_index_key_bindings() {
+        for (index, action) in InputAction::ALL.iter().enumerate() {
+            assert_eq!(*action as usize, index);
+        }
+    }
 }
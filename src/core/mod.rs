@@ -1,3 +1,4 @@
 pub mod app;
+pub mod error;
 pub mod input_manager;
 pub mod time_manager;
@@ -1,15 +1,25 @@
 use std::time::Instant;
 
+const MAX_ACCUMULATED_TIME: f32 = 0.25;
+
 pub struct TimeManager {
+    start_instant: Instant,
     instant: Instant,
     delta: f32,
+    fixed_delta: f32,
+    accumulator: f32,
 }
 
 impl TimeManager {
-    pub fn new() -> TimeManager {
+    pub fn new(fixed_delta: f32) -> TimeManager {
+        let instant = Instant::now();
+
         TimeManager {
-            instant: Instant::now(),
+            start_instant: instant,
+            instant,
             delta: 0.0,
+            fixed_delta,
+            accumulator: 0.0,
         }
     }
 
@@ -17,9 +27,81 @@ impl TimeManager {
         let last_instant = self.instant;
         self.instant = Instant::now();
         self.delta = self.instant.duration_since(last_instant).as_secs_f32();
+
+        // Clamp accumulated time so a long stall doesn't trigger a burst of fixed steps
+        self.accumulator = (self.accumulator + self.delta).min(MAX_ACCUMULATED_TIME);
+    }
+
+    pub fn consume_fixed_step(&mut self) -> bool {
+        if self.accumulator < self.fixed_delta {
+            return false;
+        }
+
+        self.accumulator -= self.fixed_delta;
+        true
+    }
+
+    /// Seconds since the start or the last reset, measured at the last update. It's
+    /// derived from the start instant rather than accumulated, so it doesn't drift.
+    pub fn time(&self) -> f32 {
+        self.instant
+            .duration_since(self.start_instant)
+            .as_secs_f32()
+    }
+
+    pub fn reset_time(&mut self) {
+        self.start_instant = self.instant;
+    }
+
+    /// Drops the time passed since the last update, so a pause doesn't advance time
+    /// or make the next delta large
+    pub fn skip_elapsed(&mut self) {
+        let now = Instant::now();
+        self.start_instant += now.duration_since(self.instant);
+        self.instant = now;
     }
 
     pub fn delta(&self) -> f32 {
         self.delta
     }
+
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn time_is_monotonic() {
+        let mut time_manager = TimeManager::new(1.0 / 60.0);
+        let mut last_time = time_manager.time();
+
+        for _ in 0..5 {
+            thread::sleep(Duration::from_millis(2));
+            time_manager.update();
+
+            let time = time_manager.time();
+            assert!(time > last_time, "{time} <= {last_time}");
+            last_time = time;
+        }
+    }
+
+    #[test]
+    fn reset_time_starts_from_zero() {
+        let mut time_manager = TimeManager::new(1.0 / 60.0);
+        thread::sleep(Duration::from_millis(20));
+        time_manager.update();
+        assert!(time_manager.time() >= 0.02);
+
+        time_manager.reset_time();
+        assert_eq!(time_manager.time(), 0.0);
+
+        time_manager.update();
+        assert!(time_manager.time() < 0.01);
+    }
 }
@@ -0,0 +1,4 @@
+pub mod controllers;
+pub mod core;
+pub mod render;
+pub mod utils;
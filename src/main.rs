@@ -1,12 +1,13 @@
-use core::app::{App, AppSettings};
+use terrain_renderer::core::{
+    app::{App, AppSettings},
+    error::TerrainError,
+};
 
-mod controllers;
-mod core;
-mod render;
-mod utils;
-
-fn main() -> Result<(), String> {
-    let app_settings = Box::new(AppSettings::default());
+fn main() -> Result<(), TerrainError> {
+    let app_settings = Box::new(AppSettings {
+        title: "Terrain renderer".into(),
+        ..Default::default()
+    });
 
     let mut app = pollster::block_on(App::new(app_settings.as_ref()))?;
     app.run()?;
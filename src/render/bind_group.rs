@@ -1,9 +1,82 @@
 use std::rc::Rc;
 
-use wgpu::{BindGroup, BindGroupLayout, Device};
+use bytemuck::{bytes_of, Pod};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
+    BufferBinding, BufferUsages, Device, Queue,
+};
+
+use crate::utils::create_uniform_bind_group_layout;
 
 pub trait BindGroupHelper {
     fn layout(&self) -> &BindGroupLayout;
 
     fn bind_group(&mut self, device: &Device) -> Rc<BindGroup>;
 }
+
+/// Single uniform buffer at binding 0, visible to all stages
+pub struct UniformBindGroup<T: Pod> {
+    uniform: Box<T>,
+    buffer: Buffer,
+    layout: BindGroupLayout,
+    bind_group: Option<Rc<BindGroup>>,
+}
+
+impl<T: Pod> UniformBindGroup<T> {
+    pub fn new(device: &Device, uniform: T) -> UniformBindGroup<T> {
+        let uniform = Box::new(uniform);
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytes_of(uniform.as_ref()),
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+        });
+
+        UniformBindGroup {
+            uniform,
+            buffer,
+            layout: create_uniform_bind_group_layout(device, false, None),
+            bind_group: None,
+        }
+    }
+
+    pub fn uniform(&self) -> &T {
+        &self.uniform
+    }
+
+    pub fn update(&mut self, queue: &Queue, uniform: &T) {
+        *self.uniform.as_mut() = *uniform;
+        queue.write_buffer(&self.buffer, 0, bytes_of(uniform));
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+impl<T: Pod> BindGroupHelper for UniformBindGroup<T> {
+    fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    fn bind_group(&mut self, device: &Device) -> Rc<BindGroup> {
+        if self.bind_group.is_none() {
+            self.bind_group
+                .replace(Rc::new(device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout: &self.layout,
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &self.buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    }],
+                })));
+        }
+
+        self.bind_group.as_ref().unwrap().clone()
+    }
+}
@@ -0,0 +1,352 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, Buffer, Color, ColorTargetState, ColorWrites, Device,
+    FilterMode, FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderStages, StoreOp, Texture, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDimension, VertexState,
+};
+
+use crate::utils::{create_texture_2d, create_uniform_init};
+
+use super::renderer::RenderingContext;
+
+const BLOOM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const MAX_MIP_LEVELS: u32 = 5;
+
+const ADDITIVE_BLEND: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent::OVER,
+};
+
+#[derive(Clone, Copy)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub intensity: f32,
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.8,
+            intensity: 0.6,
+            radius: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct BloomUniform {
+    pub threshold: f32,
+    pub intensity: f32,
+    pub radius: f32,
+    _padding: f32,
+}
+
+struct BloomTarget {
+    _texture: Texture,
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+pub struct BloomPass {
+    _shader: ShaderModule,
+    prefilter_pipeline: RenderPipeline,
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+
+    _uniform_buffer: Buffer,
+    _uniform_bind_group_layout: BindGroupLayout,
+    uniform_bind_group: BindGroup,
+
+    sampler: Sampler,
+    source_bind_group_layout: BindGroupLayout,
+
+    // The scene has no HDR target yet, so the bright pixels are extracted from
+    // a copy of the final LDR surface
+    source_texture: Texture,
+    source_bind_group: BindGroup,
+    mip_chain: Vec<BloomTarget>,
+}
+
+impl BloomPass {
+    pub fn new(
+        settings: &BloomSettings,
+        device: &Device,
+        surface_format: TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> BloomPass {
+        let shader = device.create_shader_module(include_wgsl!("../shaders/bloom.wgsl"));
+
+        let uniform = BloomUniform {
+            threshold: settings.threshold,
+            intensity: settings.intensity,
+            radius: settings.radius,
+            ..Default::default()
+        };
+        let (uniform_buffer, uniform_bind_group_layout, uniform_bind_group) =
+            create_uniform_init(&uniform, device);
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let source_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&uniform_bind_group_layout, &source_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point, format, blend| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: ColorWrites::COLOR,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        let prefilter_pipeline = create_pipeline("fs_prefilter", BLOOM_FORMAT, BlendState::REPLACE);
+        let downsample_pipeline =
+            create_pipeline("fs_downsample", BLOOM_FORMAT, BlendState::REPLACE);
+        let upsample_pipeline = create_pipeline("fs_upsample", BLOOM_FORMAT, ADDITIVE_BLEND);
+        let composite_pipeline = create_pipeline("fs_composite", surface_format, ADDITIVE_BLEND);
+
+        let source_texture = create_texture_2d(
+            device,
+            surface_format,
+            width,
+            height,
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        );
+        let source_bind_group = Self::create_source_bind_group(
+            device,
+            &source_bind_group_layout,
+            &sampler,
+            &source_texture.create_view(&Default::default()),
+        );
+        let mip_chain =
+            Self::create_mip_chain(device, &source_bind_group_layout, &sampler, width, height);
+
+        BloomPass {
+            _shader: shader,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+
+            _uniform_buffer: uniform_buffer,
+            _uniform_bind_group_layout: uniform_bind_group_layout,
+            uniform_bind_group,
+
+            sampler,
+            source_bind_group_layout,
+
+            source_texture,
+            source_bind_group,
+            mip_chain,
+        }
+    }
+
+    pub fn source_texture(&self) -> &Texture {
+        &self.source_texture
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.source_texture = create_texture_2d(
+            device,
+            self.source_texture.format(),
+            width,
+            height,
+            self.source_texture.usage(),
+        );
+        self.source_bind_group = Self::create_source_bind_group(
+            device,
+            &self.source_bind_group_layout,
+            &self.sampler,
+            &self.source_texture.create_view(&Default::default()),
+        );
+        self.mip_chain = Self::create_mip_chain(
+            device,
+            &self.source_bind_group_layout,
+            &self.sampler,
+            width,
+            height,
+        );
+    }
+
+    pub fn render(&self, context: &RenderingContext) {
+        if self.mip_chain.is_empty() {
+            return;
+        }
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut draw = |pipeline, source: &BindGroup, target: &TextureView, load| {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            pass.set_bind_group(1, source, &[]);
+            pass.draw(0..3, 0..1);
+        };
+
+        draw(
+            &self.prefilter_pipeline,
+            &self.source_bind_group,
+            &self.mip_chain[0].view,
+            LoadOp::Clear(Color::BLACK),
+        );
+
+        for i in 1..self.mip_chain.len() {
+            draw(
+                &self.downsample_pipeline,
+                &self.mip_chain[i - 1].bind_group,
+                &self.mip_chain[i].view,
+                LoadOp::Clear(Color::BLACK),
+            );
+        }
+
+        for i in (1..self.mip_chain.len()).rev() {
+            draw(
+                &self.upsample_pipeline,
+                &self.mip_chain[i].bind_group,
+                &self.mip_chain[i - 1].view,
+                LoadOp::Load,
+            );
+        }
+
+        draw(
+            &self.composite_pipeline,
+            &self.mip_chain[0].bind_group,
+            context.surface_view(),
+            LoadOp::Load,
+        );
+    }
+
+    fn create_mip_chain(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        width: u32,
+        height: u32,
+    ) -> Vec<BloomTarget> {
+        let mut mip_chain = Vec::new();
+        let (mut width, mut height) = (width / 2, height / 2);
+
+        while mip_chain.len() < MAX_MIP_LEVELS as usize && width > 0 && height > 0 {
+            let texture = create_texture_2d(
+                device,
+                BLOOM_FORMAT,
+                width,
+                height,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            );
+            let view = texture.create_view(&Default::default());
+            let bind_group = Self::create_source_bind_group(device, layout, sampler, &view);
+
+            mip_chain.push(BloomTarget {
+                _texture: texture,
+                view,
+                bind_group,
+            });
+
+            width /= 2;
+            height /= 2;
+        }
+
+        mip_chain
+    }
+
+    fn create_source_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        view: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+}
@@ -0,0 +1,252 @@
+use glam::Vec3;
+use noise::NoiseFn;
+use wgpu::{
+    include_wgsl, BlendState, ColorTargetState, ColorWrites, DepthBiasState, DepthStencilState,
+    Face, FragmentState, IndexFormat, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor,
+    PolygonMode, PrimitiveState, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState,
+    StencilState, StoreOp, VertexState,
+};
+
+use crate::utils::terrain_generator::{
+    generate_grid_mesh, generate_height_grid, GridRegion, TerrainSettings,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    mesh::Mesh,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+#[derive(Clone)]
+pub struct ChunkedTerrainSettings {
+    /// Tiles along a chunk side. Should be divisible by the step of the coarsest LOD,
+    /// i.e. `2^lod_distances.len()`
+    pub chunk_tiles: u32,
+    /// Camera distances at which chunks switch to the next, twice coarser LOD
+    pub lod_distances: Box<[f32]>,
+    pub skirt_depth: f32,
+}
+
+impl Default for ChunkedTerrainSettings {
+    fn default() -> Self {
+        Self {
+            chunk_tiles: 16,
+            lod_distances: vec![15.0, 30.0].into_boxed_slice(),
+            skirt_depth: 0.5,
+        }
+    }
+}
+
+struct Chunk {
+    center: Vec3,
+    min: Vec3,
+    max: Vec3,
+    // From the finest to the coarsest
+    lods: Vec<Mesh>,
+}
+
+pub struct ChunkedTerrainRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    lod_distances: Box<[f32]>,
+    chunks: Vec<Chunk>,
+    triangles_drawn: u32,
+    sample_count: u32,
+}
+
+impl ChunkedTerrainRenderer {
+    pub fn new<T>(
+        terrain_settings: &TerrainSettings<T>,
+        settings: &ChunkedTerrainSettings,
+        render_manager: &RenderManager,
+    ) -> ChunkedTerrainRenderer
+    where
+        T: NoiseFn<f64, 2> + Sync,
+    {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/chunked_terrain.wgsl"));
+
+        let heights = generate_height_grid(terrain_settings);
+        let chunk_tiles = settings.chunk_tiles.max(1);
+
+        let mut chunks = Vec::new();
+        for x in (0..terrain_settings.tiles_count).step_by(chunk_tiles as usize) {
+            for z in (0..terrain_settings.tiles_count).step_by(chunk_tiles as usize) {
+                let tiles_count = chunk_tiles
+                    .min(terrain_settings.tiles_count - x)
+                    .min(terrain_settings.tiles_count - z);
+
+                let lods: Vec<Mesh> = (0..=settings.lod_distances.len())
+                    .map(|lod| {
+                        generate_grid_mesh(
+                            device,
+                            terrain_settings,
+                            &heights,
+                            &GridRegion {
+                                x,
+                                z,
+                                tiles_count,
+                                step: (1 << lod).min(tiles_count),
+                                skirt_depth: Some(settings.skirt_depth),
+                            },
+                        )
+                    })
+                    .collect();
+
+                // The finest LOD bounds the coarser ones, skirts included
+                let (min, max) = lods[0].vertices().iter().fold(
+                    (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+                    |(min, max), v| (min.min(v.position), max.max(v.position)),
+                );
+
+                chunks.push(Chunk {
+                    center: (min + max) * 0.5,
+                    min,
+                    max,
+                    lods,
+                });
+            }
+        }
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[render_manager.scene_bind_group().borrow().layout()],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: terrain_settings.topology,
+                strip_index_format: terrain_settings
+                    .topology
+                    .is_strip()
+                    .then_some(IndexFormat::Uint32),
+                front_face: render_manager.coordinate_system().front_face(),
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        ChunkedTerrainRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            lod_distances: settings.lod_distances.clone(),
+            chunks,
+            triangles_drawn: 0,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    /// Number of triangles drawn during the last render, after frustum culling
+    pub fn triangles_drawn(&self) -> u32 {
+        self.triangles_drawn
+    }
+
+    fn select_lod(&self, distance: f32) -> usize {
+        self.lod_distances
+            .iter()
+            .position(|d| distance < *d)
+            .unwrap_or(self.lod_distances.len())
+    }
+}
+
+impl Renderer for ChunkedTerrainRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut camera = context.camera().borrow_mut();
+        let camera_pos = camera.position();
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+
+        self.triangles_drawn = 0;
+        for chunk in &self.chunks {
+            if !camera.intersects_aabb(chunk.min, chunk.max) {
+                continue;
+            }
+
+            let lod = self.select_lod(chunk.center.distance(camera_pos));
+            let mesh = &chunk.lods[lod];
+
+            pass.set_vertex_buffer(0, mesh.vertex_buffer().slice(..));
+            pass.set_index_buffer(mesh.index_buffer().slice(..), IndexFormat::Uint32);
+            pass.draw_indexed(0..(mesh.indices().len() as u32), 0, 0..1);
+            context.record_draw(mesh.indices().len() as u32, mesh.triangles_count());
+
+            self.triangles_drawn += mesh.indices().len() as u32 / 3;
+        }
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
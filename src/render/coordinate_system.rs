@@ -0,0 +1,69 @@
+use glam::{Mat4, Quat, Vec3};
+use wgpu::FrontFace;
+
+/// World up axis, which terrain heights, the sky gradient and the camera controllers
+/// are built around
+pub const WORLD_UP: Vec3 = Vec3::Y;
+
+/// Handedness of the Y-up world coordinates. Cameras look along +Z of their local
+/// space in left-handed coordinates and along -Z in right-handed ones.
+///
+/// Built-in geometry (terrain, water, skybox) keeps its coordinates in both systems,
+/// so right-handed worlds see it mirrored along Z.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CoordinateSystem {
+    #[default]
+    LeftHanded,
+    RightHanded,
+}
+
+impl CoordinateSystem {
+    pub fn camera_forward(self) -> Vec3 {
+        self.from_forward_z(Vec3::Z)
+    }
+
+    /// Converts a camera local vector given with +Z pointing forward, like the input
+    /// movement, to the camera local space of the system
+    pub fn from_forward_z(self, v: Vec3) -> Vec3 {
+        match self {
+            CoordinateSystem::LeftHanded => v,
+            CoordinateSystem::RightHanded => Vec3::new(v.x, v.y, -v.z),
+        }
+    }
+
+    /// Camera rotation from the yaw around the up axis and the pitch, in degrees.
+    /// Positive yaw turns right and positive pitch looks down in both systems.
+    pub fn yaw_pitch_rotation(self, yaw: f32, pitch: f32) -> Quat {
+        let (yaw, pitch) = match self {
+            CoordinateSystem::LeftHanded => (yaw, pitch),
+            CoordinateSystem::RightHanded => (-yaw, -pitch),
+        };
+
+        Quat::from_rotation_y(yaw.to_radians()) * Quat::from_rotation_x(pitch.to_radians())
+    }
+
+    pub fn perspective(self, fov_y: f32, aspect_ratio: f32, near: f32, far: f32) -> Mat4 {
+        match self {
+            CoordinateSystem::LeftHanded => Mat4::perspective_lh(fov_y, aspect_ratio, near, far),
+            CoordinateSystem::RightHanded => Mat4::perspective_rh(fov_y, aspect_ratio, near, far),
+        }
+    }
+
+    /// Winding of the front faces of built-in geometry on screen. Its triangles are
+    /// wound for left-handed coordinates, which mirroring flips.
+    pub fn front_face(self) -> FrontFace {
+        match self {
+            CoordinateSystem::LeftHanded => FrontFace::Ccw,
+            CoordinateSystem::RightHanded => FrontFace::Cw,
+        }
+    }
+
+    /// Maps the view space of the system to the left-handed one, which screen space
+    /// effects work in
+    pub fn to_left_handed(self) -> Mat4 {
+        match self {
+            CoordinateSystem::LeftHanded => Mat4::IDENTITY,
+            CoordinateSystem::RightHanded => Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0)),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::mem::size_of;
+
+use bytemuck::cast_slice;
+use glam::{Mat4, Vec3};
+use wgpu::{
+    include_wgsl, BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState,
+    ColorWrites, DepthBiasState, DepthStencilState, FragmentState, FrontFace, LoadOp, Operations,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState, StencilState,
+    StoreOp, VertexState,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+#[derive(Clone, Copy)]
+pub struct DebugLineRendererSettings {
+    pub max_lines: usize,
+}
+
+impl Default for DebugLineRendererSettings {
+    fn default() -> Self {
+        Self { max_lines: 16384 }
+    }
+}
+
+pub struct DebugLineRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    max_lines: usize,
+    vertices: Vec<Vertex>,
+    vertex_buffer: Buffer,
+    sample_count: u32,
+}
+
+impl DebugLineRenderer {
+    pub fn new(
+        settings: &DebugLineRendererSettings,
+        render_manager: &RenderManager,
+    ) -> DebugLineRenderer {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/debug_line.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[render_manager.scene_bind_group().borrow().layout()],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: false,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: (settings.max_lines * 2 * size_of::<Vertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        DebugLineRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            max_lines: settings.max_lines,
+            vertices: Vec::new(),
+            vertex_buffer,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    pub fn draw_line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        // Lines above the buffer capacity are dropped until the next frame
+        if self.vertices.len() / 2 >= self.max_lines {
+            return;
+        }
+
+        self.vertices.push(Vertex::new(a, Vec3::ZERO, color));
+        self.vertices.push(Vertex::new(b, Vec3::ZERO, color));
+    }
+
+    pub fn draw_aabb(&mut self, min: Vec3, max: Vec3, color: Vec3) {
+        let corners = [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(max.x, max.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+        ];
+
+        self.draw_box_edges(&corners, color);
+    }
+
+    pub fn draw_frustum(&mut self, view_proj_matrix: Mat4, color: Vec3) {
+        let inv_matrix = view_proj_matrix.inverse();
+
+        // wgpu clip space has depth in the [0, 1] range
+        let corners = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, 1.0, 1.0),
+        ]
+        .map(|p| inv_matrix.project_point3(p));
+
+        self.draw_box_edges(&corners, color);
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    fn draw_box_edges(&mut self, corners: &[Vec3; 8], color: Vec3) {
+        for i in 0..4 {
+            self.draw_line(corners[i], corners[(i + 1) % 4], color);
+            self.draw_line(corners[i + 4], corners[(i + 1) % 4 + 4], color);
+            self.draw_line(corners[i], corners[i + 4], color);
+        }
+    }
+}
+
+impl Renderer for DebugLineRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        context
+            .queue()
+            .borrow()
+            .write_buffer(&self.vertex_buffer, 0, cast_slice(&self.vertices));
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+
+        pass.draw(0..(self.vertices.len() as u32), 0..1);
+        context.record_draw(self.vertices.len() as u32, 0);
+
+        self.vertices.clear();
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::TRANSPARENT
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
@@ -0,0 +1,141 @@
+use bytemuck::{bytes_of, Pod, Zeroable};
+use glam::Vec4;
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState, ColorWrites,
+    Device, FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor,
+    PrimitiveState, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, StoreOp, TextureFormat, VertexState,
+};
+
+use crate::utils::create_uniform_init;
+
+use super::{bind_group::BindGroupHelper, renderer::RenderingContext, scene::SceneBindGroup};
+
+/// Replaces the picture with a visualization of the opaque depth snapshot
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DebugView {
+    #[default]
+    None,
+    /// Linear distance from the near plane (black) to the far plane (white)
+    Depth,
+    /// View space normals reconstructed from the depth
+    Normals,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct DebugViewUniform {
+    pub viewport: Vec4,
+    pub reversed_z: u32,
+    _padding: [u32; 3],
+}
+
+pub struct DebugViewPass {
+    _shader: ShaderModule,
+    pipeline: RenderPipeline,
+
+    uniform: Box<DebugViewUniform>,
+    uniform_buffer: Buffer,
+    _uniform_bind_group_layout: BindGroupLayout,
+    uniform_bind_group: BindGroup,
+}
+
+impl DebugViewPass {
+    /// Returns `None` for `DebugView::None`
+    pub fn new(
+        view: DebugView,
+        device: &Device,
+        scene_bind_group: &SceneBindGroup,
+        reversed_z: bool,
+        surface_format: TextureFormat,
+        sample_count: u32,
+    ) -> Option<DebugViewPass> {
+        let entry_point = match view {
+            DebugView::None => return None,
+            DebugView::Depth => "fs_depth",
+            DebugView::Normals => "fs_normals",
+        };
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/debug_view.wgsl"));
+
+        let uniform = Box::new(DebugViewUniform {
+            reversed_z: reversed_z as u32,
+            ..Default::default()
+        });
+        let (uniform_buffer, uniform_bind_group_layout, uniform_bind_group) =
+            create_uniform_init(uniform.as_ref(), device);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[scene_bind_group.layout(), &uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(ColorTargetState {
+                    format: surface_format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Some(DebugViewPass {
+            _shader: shader,
+            pipeline,
+
+            uniform,
+            uniform_buffer,
+            _uniform_bind_group_layout: uniform_bind_group_layout,
+            uniform_bind_group,
+        })
+    }
+
+    pub fn update(&mut self, queue: &Queue, viewport_rect: Vec4) {
+        self.uniform.viewport = viewport_rect;
+        queue.write_buffer(&self.uniform_buffer, 0, bytes_of(self.uniform.as_ref()));
+    }
+
+    pub fn render(&self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,139 @@
+use wgpu::{
+    include_wgsl, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, StoreOp, Texture,
+    TextureAspect, TextureFormat, TextureSampleType, TextureViewDescriptor, TextureViewDimension,
+    VertexState,
+};
+
+use super::renderer::RenderingContext;
+
+/// Copies depth between textures by drawing it, for formats whose depth can't be
+/// copied with a transfer, like `Depth24Plus`. Multisampled sources are copied from
+/// their first sample into a single sampled target.
+pub struct DepthCopyPass {
+    _shader: ShaderModule,
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl DepthCopyPass {
+    pub fn new(device: &Device, format: TextureFormat, source_sample_count: u32) -> DepthCopyPass {
+        let multisampled = source_sample_count > 1;
+        let shader = if multisampled {
+            device.create_shader_module(ShaderModuleDescriptor {
+                label: None,
+                source: ShaderSource::Wgsl(multisampled_shader_source().into()),
+            })
+        } else {
+            device.create_shader_module(include_wgsl!("../shaders/depth_copy.wgsl"))
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(DepthStencilState {
+                format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            multiview: None,
+        });
+
+        DepthCopyPass {
+            _shader: shader,
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// `source` must be usable as a texture binding and `target` as a render
+    /// attachment
+    pub fn render(
+        &self,
+        device: &Device,
+        context: &RenderingContext,
+        source: &Texture,
+        target: &Texture,
+    ) {
+        let source_view = source.create_view(&TextureViewDescriptor {
+            aspect: TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        let target_view = target.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&source_view),
+            }],
+        });
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &target_view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(0.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// The shader loading the first sample of a multisampled source, which takes the
+/// sample index in place of the mip level
+fn multisampled_shader_source() -> String {
+    include_str!("../shaders/depth_copy.wgsl")
+        .replace("texture_depth_2d", "texture_depth_multisampled_2d")
+}
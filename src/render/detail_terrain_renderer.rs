@@ -0,0 +1,237 @@
+use std::mem::size_of;
+
+use bytemuck::cast_slice;
+use glam::Vec2;
+use noise::NoiseFn;
+use wgpu::{
+    include_wgsl, BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState,
+    ColorWrites, DepthBiasState, DepthStencilState, Face, FragmentState, LoadOp, Operations,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState, StencilState,
+    StoreOp, VertexState,
+};
+
+use crate::utils::terrain_generator::{
+    generate_detail_vertices, generate_height_grid, TerrainSettings,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+#[derive(Clone, Copy)]
+pub struct DetailTerrainSettings {
+    /// Tiles with centers closer than this to the camera, horizontally, are subdivided
+    pub detail_radius: f32,
+    /// Smaller tiles along a side of a subdivided tile
+    pub detail_subdivisions: u32,
+    /// Horizontal distance the camera moves before the mesh is rebuilt
+    pub update_distance: f32,
+}
+
+impl Default for DetailTerrainSettings {
+    fn default() -> Self {
+        Self {
+            detail_radius: 4.0,
+            detail_subdivisions: 4,
+            update_distance: 0.5,
+        }
+    }
+}
+
+/// Terrain drawn as a single mesh, which is denser around the camera. Unlike
+/// `ChunkedTerrainRenderer` it samples the noise for the extra detail, so it's
+/// rebuilt on the CPU as the camera moves.
+pub struct DetailTerrainRenderer<T>
+where
+    T: NoiseFn<f64, 2>,
+{
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    terrain_settings: TerrainSettings<T>,
+    heights: Vec<f32>,
+    settings: DetailTerrainSettings,
+
+    vertex_buffer: Buffer,
+    vertices_count: u32,
+    detail_center: Option<Vec2>,
+    sample_count: u32,
+}
+
+impl<T> DetailTerrainRenderer<T>
+where
+    T: NoiseFn<f64, 2> + Clone + Sync,
+{
+    pub fn new(
+        terrain_settings: &TerrainSettings<T>,
+        settings: &DetailTerrainSettings,
+        render_manager: &RenderManager,
+    ) -> DetailTerrainRenderer<T> {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/chunked_terrain.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[render_manager.scene_bind_group().borrow().layout()],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: render_manager.coordinate_system().front_face(),
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: (Self::max_vertices_count(terrain_settings, settings) * size_of::<Vertex>())
+                as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        DetailTerrainRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            heights: generate_height_grid(terrain_settings),
+            terrain_settings: terrain_settings.clone(),
+            settings: *settings,
+
+            vertex_buffer,
+            vertices_count: 0,
+            detail_center: None,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    /// Upper bound of the mesh size, reached when every tile within the detail radius
+    /// of some point is subdivided
+    fn max_vertices_count(
+        terrain_settings: &TerrainSettings<T>,
+        settings: &DetailTerrainSettings,
+    ) -> usize {
+        let tiles_count = terrain_settings.tiles_count as usize;
+        let n = settings.detail_subdivisions.max(1) as usize;
+        let detail_side = (2.0 * settings.detail_radius / terrain_settings.effective_tile_size())
+            .ceil() as usize
+            + 1;
+        let detail_tiles = (detail_side * detail_side).min(tiles_count * tiles_count);
+
+        (tiles_count * tiles_count + detail_tiles * (n * n - 1)) * 6
+    }
+}
+
+impl<T> Renderer for DetailTerrainRenderer<T>
+where
+    T: NoiseFn<f64, 2>,
+{
+    fn render(&mut self, context: &RenderingContext) {
+        let camera_pos = context.camera().borrow().position();
+        let center = Vec2::new(camera_pos.x, camera_pos.z);
+
+        let is_outdated = self
+            .detail_center
+            .is_none_or(|c| c.distance(center) > self.settings.update_distance);
+        if is_outdated {
+            let vertices = generate_detail_vertices(
+                &self.terrain_settings,
+                &self.heights,
+                center,
+                self.settings.detail_radius,
+                self.settings.detail_subdivisions,
+            );
+            context
+                .queue()
+                .borrow()
+                .write_buffer(&self.vertex_buffer, 0, cast_slice(&vertices));
+
+            self.vertices_count = vertices.len() as u32;
+            self.detail_center = Some(center);
+        }
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.vertices_count, 0..1);
+        context.record_draw(self.vertices_count, self.vertices_count / 3);
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
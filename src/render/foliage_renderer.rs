@@ -0,0 +1,382 @@
+use std::{
+    f32::consts::TAU,
+    mem::{offset_of, size_of},
+    ops::Range,
+};
+
+use bytemuck::{cast_slice, Pod, Zeroable};
+use glam::{Vec2, Vec3};
+use noise::NoiseFn;
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupLayout, BlendState, Buffer, BufferUsages, ColorTargetState, ColorWrites,
+    DepthBiasState, DepthStencilState, FragmentState, FrontFace, LoadOp, Operations,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState, StencilState,
+    StoreOp, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+};
+
+use crate::utils::{
+    create_uniform_init, srgb_to_linear,
+    terrain_generator::{TerrainHeights, TerrainSettings},
+    XorShift,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+};
+
+#[derive(Clone, Copy)]
+pub struct FoliageSettings {
+    pub enabled: bool,
+    /// Blades per square world unit
+    pub density: f32,
+    pub min_height: f32,
+    pub max_height: f32,
+    pub blade_width: f32,
+    /// No blades are placed on the terrain below this height
+    pub water_level: f32,
+    pub base_color: Vec3,
+    pub tip_color: Vec3,
+    pub wind_strength: f32,
+    pub wind_direction: Vec2,
+    pub wind_speed: f32,
+    /// Tiles along a side of the square cells which blades are culled by
+    pub cell_tiles: u32,
+    pub seed: u32,
+    pub linear_colors: bool,
+}
+
+impl Default for FoliageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 40.0,
+            min_height: 0.15,
+            max_height: 0.3,
+            blade_width: 0.03,
+            water_level: -0.25,
+            base_color: Vec3::new(0.2, 0.45, 0.12),
+            tip_color: Vec3::new(0.62, 0.85, 0.3),
+            wind_strength: 0.08,
+            wind_direction: Vec2::new(1.0, 0.3),
+            wind_speed: 1.5,
+            cell_tiles: 4,
+            seed: 0,
+            linear_colors: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct FoliageUniform {
+    pub base_color: Vec3,
+    pub blade_width: f32,
+    pub tip_color: Vec3,
+    pub wind_strength: f32,
+    pub wind_direction: Vec2,
+    pub wind_speed: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct FoliageInstance {
+    pub position: Vec3,
+    pub rotation: f32,
+    pub height: f32,
+    pub phase: f32,
+}
+
+static INSTANCE_ATTRIBUTES: [VertexAttribute; 4] = [
+    VertexAttribute {
+        format: VertexFormat::Float32x3,
+        offset: offset_of!(FoliageInstance, position) as u64,
+        shader_location: 0,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32,
+        offset: offset_of!(FoliageInstance, rotation) as u64,
+        shader_location: 1,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32,
+        offset: offset_of!(FoliageInstance, height) as u64,
+        shader_location: 2,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32,
+        offset: offset_of!(FoliageInstance, phase) as u64,
+        shader_location: 3,
+    },
+];
+
+// Tapered blade of two quads and a tip triangle, generated in the vertex shader
+const BLADE_VERTICES_COUNT: u32 = 15;
+
+struct Cell {
+    min: Vec3,
+    max: Vec3,
+    instances: Range<u32>,
+}
+
+pub struct FoliageRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    _uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    instance_buffer: Buffer,
+    cells: Vec<Cell>,
+    instances_drawn: u32,
+    sample_count: u32,
+}
+
+impl FoliageRenderer {
+    pub fn new<T>(
+        terrain_settings: &TerrainSettings<T>,
+        settings: &FoliageSettings,
+        render_manager: &RenderManager,
+    ) -> FoliageRenderer
+    where
+        T: NoiseFn<f64, 2> + Sync,
+    {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/foliage.wgsl"));
+
+        let convert_color = |color| {
+            if settings.linear_colors {
+                srgb_to_linear(color)
+            } else {
+                color
+            }
+        };
+
+        let uniform = FoliageUniform {
+            base_color: convert_color(settings.base_color),
+            blade_width: settings.blade_width,
+            tip_color: convert_color(settings.tip_color),
+            wind_strength: settings.wind_strength,
+            wind_direction: settings.wind_direction.normalize_or_zero(),
+            wind_speed: settings.wind_speed,
+            ..Default::default()
+        };
+        let (uniform_buffer, bind_group_layout, bind_group) = create_uniform_init(&uniform, device);
+
+        let (instances, cells) = Self::scatter_instances(terrain_settings, settings);
+        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&instances),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                render_manager.scene_bind_group().borrow().layout(),
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: size_of::<FoliageInstance>() as u64,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &INSTANCE_ATTRIBUTES,
+                }],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                // Blades are seen from both sides
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        FoliageRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            _uniform_buffer: uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+
+            instance_buffer,
+            cells,
+            instances_drawn: 0,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    /// Number of blades drawn during the last render, after frustum culling
+    pub fn instances_drawn(&self) -> u32 {
+        self.instances_drawn
+    }
+
+    fn scatter_instances<T>(
+        terrain_settings: &TerrainSettings<T>,
+        settings: &FoliageSettings,
+    ) -> (Vec<FoliageInstance>, Vec<Cell>)
+    where
+        T: NoiseFn<f64, 2> + Sync,
+    {
+        let heights = TerrainHeights::new(terrain_settings);
+        let cell_tiles = settings.cell_tiles.max(1);
+        let tile_size = terrain_settings.effective_tile_size();
+        let cell_size = cell_tiles as f32 * tile_size;
+        let terrain_size = terrain_settings.tiles_count as f32 * tile_size;
+        // Bounds grow by the wind sway so swaying blades aren't culled early
+        let margin = settings.wind_strength.abs() + settings.blade_width;
+
+        let mut random = XorShift::new(settings.seed);
+        let mut instances = Vec::new();
+        let mut cells = Vec::new();
+
+        for x in (0..terrain_settings.tiles_count).step_by(cell_tiles as usize) {
+            for z in (0..terrain_settings.tiles_count).step_by(cell_tiles as usize) {
+                let offset = Vec2::new(x as f32, z as f32) * tile_size;
+                let size = (Vec2::splat(terrain_size) - offset).min(Vec2::splat(cell_size));
+                let origin =
+                    Vec2::new(terrain_settings.origin.x, terrain_settings.origin.z) + offset;
+                let count = (settings.density * size.x * size.y).round() as u32;
+
+                let start = instances.len() as u32;
+                let (mut min, mut max) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+                for _ in 0..count {
+                    let p = origin + Vec2::new(random.next_f32(), random.next_f32()) * size;
+                    let h = heights.sample(p.x, p.y);
+                    let rotation = random.next_f32() * TAU;
+                    let height = settings.min_height
+                        + random.next_f32() * (settings.max_height - settings.min_height);
+                    let phase = random.next_f32() * TAU;
+                    if h < settings.water_level {
+                        continue;
+                    }
+
+                    let position = Vec3::new(p.x, h, p.y);
+                    min = min.min(position);
+                    max = max.max(position + Vec3::Y * height);
+                    instances.push(FoliageInstance {
+                        position,
+                        rotation,
+                        height,
+                        phase,
+                    });
+                }
+
+                let end = instances.len() as u32;
+                if end > start {
+                    cells.push(Cell {
+                        min: min - Vec3::splat(margin),
+                        max: max + Vec3::splat(margin),
+                        instances: start..end,
+                    });
+                }
+            }
+        }
+
+        (instances, cells)
+    }
+}
+
+impl Renderer for FoliageRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut camera = context.camera().borrow_mut();
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+
+        self.instances_drawn = 0;
+        for cell in &self.cells {
+            if !camera.intersects_aabb(cell.min, cell.max) {
+                continue;
+            }
+
+            pass.draw(0..BLADE_VERTICES_COUNT, cell.instances.clone());
+            context.record_draw(
+                BLADE_VERTICES_COUNT * cell.instances.len() as u32,
+                BLADE_VERTICES_COUNT / 3 * cell.instances.len() as u32,
+            );
+            self.instances_drawn += cell.instances.len() as u32;
+        }
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
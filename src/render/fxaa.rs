@@ -0,0 +1,144 @@
+use wgpu::{
+    include_wgsl, AddressMode, BindGroup, BindGroupLayout, BlendState, Color, ColorTargetState,
+    ColorWrites, Device, FilterMode, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderModule, StoreOp,
+    Texture, TextureFormat, TextureUsages, VertexState,
+};
+
+use crate::utils::{
+    create_texture_2d, create_texture_bind_group, create_texture_bind_group_layout,
+};
+
+use super::renderer::RenderingContext;
+
+/// Fast approximate anti-aliasing over the finished frame. It smooths edges found
+/// by luminance contrast, so it also catches aliasing coming from shaders.
+pub struct FxaaPass {
+    _shader: ShaderModule,
+    pipeline: RenderPipeline,
+
+    sampler: Sampler,
+    source_bind_group_layout: BindGroupLayout,
+
+    source_texture: Texture,
+    source_bind_group: BindGroup,
+}
+
+impl FxaaPass {
+    pub fn new(
+        device: &Device,
+        surface_format: TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> FxaaPass {
+        let shader = device.create_shader_module(include_wgsl!("../shaders/fxaa.wgsl"));
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let source_bind_group_layout = create_texture_bind_group_layout(device);
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&source_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: surface_format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let source_texture = create_texture_2d(
+            device,
+            surface_format,
+            width,
+            height,
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        );
+        let source_bind_group = create_texture_bind_group(
+            device,
+            &source_bind_group_layout,
+            &source_texture.create_view(&Default::default()),
+            &sampler,
+        );
+
+        FxaaPass {
+            _shader: shader,
+            pipeline,
+
+            sampler,
+            source_bind_group_layout,
+
+            source_texture,
+            source_bind_group,
+        }
+    }
+
+    pub fn source_texture(&self) -> &Texture {
+        &self.source_texture
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.source_texture = create_texture_2d(
+            device,
+            self.source_texture.format(),
+            width,
+            height,
+            self.source_texture.usage(),
+        );
+        self.source_bind_group = create_texture_bind_group(
+            device,
+            &self.source_bind_group_layout,
+            &self.source_texture.create_view(&Default::default()),
+            &self.sampler,
+        );
+    }
+
+    pub fn render(&self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.source_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    sync::{Arc, Mutex},
+};
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, Device, Maintain, MapMode, QuerySet,
+    QuerySetDescriptor, QueryType, Queue,
+};
+
+use super::renderer::RenderingContext;
+
+pub type RendererId = usize;
+
+const MAX_TIMED_RENDERERS: u32 = 64;
+
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    timestamp_period: f32,
+
+    // Renderers timed in the frame currently being read back
+    pending_renderers: Vec<RendererId>,
+    is_frame_timed: bool,
+    is_mapping: bool,
+    map_result: Arc<Mutex<Option<bool>>>,
+
+    last_frame_timings: HashMap<RendererId, f32>,
+}
+
+impl GpuTimer {
+    pub fn new(device: &Device, queue: &Queue) -> GpuTimer {
+        let query_count = MAX_TIMED_RENDERERS * 2;
+        let buffer_size = (query_count as usize * size_of::<u64>()) as u64;
+
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: None,
+            ty: QueryType::Timestamp,
+            count: query_count,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: buffer_size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: buffer_size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        GpuTimer {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            timestamp_period: queue.get_timestamp_period(),
+
+            pending_renderers: Vec::new(),
+            is_frame_timed: false,
+            is_mapping: false,
+            map_result: Arc::new(Mutex::new(None)),
+
+            last_frame_timings: HashMap::new(),
+        }
+    }
+
+    pub fn last_frame_timings(&self) -> &HashMap<RendererId, f32> {
+        &self.last_frame_timings
+    }
+
+    /// Collects the results of a previous frame if they are ready. Returns whether
+    /// the current frame can be timed, which isn't the case while the readback
+    /// buffer is still in use.
+    pub fn begin_frame(&mut self, device: &Device) -> bool {
+        self.is_frame_timed = self.try_collect(device);
+        self.is_frame_timed
+    }
+
+    pub fn time<R>(
+        &mut self,
+        context: &RenderingContext,
+        id: RendererId,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let index = self.pending_renderers.len() as u32;
+        if !self.is_frame_timed || index >= MAX_TIMED_RENDERERS {
+            return f();
+        }
+        self.pending_renderers.push(id);
+
+        Self::write_timestamp(context, &self.query_set, index * 2);
+        let result = f();
+        Self::write_timestamp(context, &self.query_set, index * 2 + 1);
+
+        result
+    }
+
+    pub fn resolve(&self, context: &RenderingContext) {
+        if !self.is_frame_timed || self.pending_renderers.is_empty() {
+            return;
+        }
+
+        let query_count = self.pending_renderers.len() as u32 * 2;
+        let size = (query_count as usize * size_of::<u64>()) as u64;
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+    }
+
+    /// Must be called after the frame's commands are submitted.
+    pub fn end_frame(&mut self) {
+        if !self.is_frame_timed || self.pending_renderers.is_empty() {
+            return;
+        }
+
+        let map_result = self.map_result.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                *map_result.lock().unwrap() = Some(result.is_ok());
+            });
+        self.is_mapping = true;
+        self.is_frame_timed = false;
+    }
+
+    fn try_collect(&mut self, device: &Device) -> bool {
+        if !self.is_mapping {
+            self.pending_renderers.clear();
+            return true;
+        }
+
+        device.poll(Maintain::Poll);
+        let is_mapped = match self.map_result.lock().unwrap().take() {
+            Some(is_mapped) => is_mapped,
+            None => return false,
+        };
+
+        if is_mapped {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+
+            self.last_frame_timings.clear();
+            for (i, id) in self.pending_renderers.iter().enumerate() {
+                let ticks = timestamps[i * 2 + 1].saturating_sub(timestamps[i * 2]);
+                let ms = ticks as f32 * self.timestamp_period / 1_000_000.0;
+                // Renderers run once per viewport, so their timings are summed
+                *self.last_frame_timings.entry(*id).or_default() += ms;
+            }
+            self.readback_buffer.unmap();
+        }
+
+        self.is_mapping = false;
+        self.pending_renderers.clear();
+
+        true
+    }
+
+    fn write_timestamp(context: &RenderingContext, query_set: &QuerySet, index: u32) {
+        context
+            .encoder()
+            .borrow_mut()
+            .as_mut()
+            .unwrap()
+            .write_timestamp(query_set, index);
+    }
+}
@@ -0,0 +1,200 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState, ColorWrites,
+    DepthBiasState, DepthStencilState, FragmentState, FrontFace, LoadOp, Operations,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState, StencilState,
+    StoreOp, VertexState,
+};
+
+use crate::utils::{create_uniform_init, srgb_to_linear};
+
+use super::{
+    bind_group::BindGroupHelper,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+};
+
+#[derive(Clone, Copy)]
+pub struct GridSettings {
+    pub enabled: bool,
+    pub cell_size: f32,
+    pub height: f32,
+    pub fade_distance: f32,
+    pub color: Vec3,
+    pub x_axis_color: Vec3,
+    pub z_axis_color: Vec3,
+    pub linear_colors: bool,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cell_size: 1.0,
+            height: 0.0,
+            fade_distance: 50.0,
+            color: Vec3::new(0.5, 0.5, 0.5),
+            x_axis_color: Vec3::new(0.9, 0.2, 0.2),
+            z_axis_color: Vec3::new(0.2, 0.4, 0.9),
+            linear_colors: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct GridUniform {
+    pub color: Vec3,
+    pub cell_size: f32,
+    pub x_axis_color: Vec3,
+    pub fade_distance: f32,
+    pub z_axis_color: Vec3,
+    pub height: f32,
+}
+
+pub struct GridRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    _uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    sample_count: u32,
+}
+
+impl GridRenderer {
+    pub fn new(settings: &GridSettings, render_manager: &RenderManager) -> GridRenderer {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/grid.wgsl"));
+
+        let convert_color = |color| {
+            if settings.linear_colors {
+                srgb_to_linear(color)
+            } else {
+                color
+            }
+        };
+
+        let uniform = GridUniform {
+            color: convert_color(settings.color),
+            cell_size: settings.cell_size,
+            x_axis_color: convert_color(settings.x_axis_color),
+            fade_distance: settings.fade_distance,
+            z_axis_color: convert_color(settings.z_axis_color),
+            height: settings.height,
+        };
+        let (uniform_buffer, bind_group_layout, bind_group) = create_uniform_init(&uniform, device);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                render_manager.scene_bind_group().borrow().layout(),
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: false,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        GridRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            _uniform_buffer: uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+}
+
+impl Renderer for GridRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+
+        pass.draw(0..6, 0..1);
+        context.record_draw(6, 2);
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::TRANSPARENT
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
@@ -0,0 +1,173 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use bytemuck::{bytes_of, Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Device, FilterMode, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, Texture, TextureFormat, TextureSampleType,
+    TextureViewDimension,
+};
+
+use crate::{core::error::TerrainError, utils::create_texture_2d_init};
+
+use super::render_manager::RenderManager;
+
+/// Surface shading of a `MeshRenderer`. The renderer shares the vertex stage and the
+/// pipeline between materials, which differ in bindings and fragment logic.
+pub trait Material {
+    /// Used in shader compilation errors
+    fn name(&self) -> &str;
+
+    /// WGSL code appended to the mesh shader. It defines `fs_main`, taking the mesh
+    /// `VertexOutput`, and keeps its bindings in group 2. `surface_normal` and
+    /// `shade_surface` of the mesh shader do the common lighting.
+    fn fragment_source(&self) -> Result<Cow<'static, str>, TerrainError>;
+
+    fn bind_group_layout(&self) -> &BindGroupLayout;
+
+    fn bind_group(&self) -> &BindGroup;
+
+    /// Files the fragment source is read from, reloaded with the mesh shader
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct VertexColorMaterialUniform {
+    pub use_normal_map: u32,
+    _padding: [u32; 3],
+}
+
+/// Shades meshes by their vertex colors, optionally perturbing normals by a tangent
+/// space normal map
+pub struct VertexColorMaterial {
+    _uniform_buffer: Buffer,
+    _normal_map_texture: Texture,
+    _normal_map_sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl VertexColorMaterial {
+    pub fn new(render_manager: &RenderManager) -> VertexColorMaterial {
+        // Flat tangent-space normal, bound when there's no normal map
+        let texture = create_texture_2d_init(
+            render_manager.device(),
+            &render_manager.queue().borrow(),
+            TextureFormat::Rgba8Unorm,
+            1,
+            1,
+            &[128, 128, 255, 255],
+        );
+
+        Self::create(render_manager.device(), texture, false)
+    }
+
+    pub fn with_normal_map(texture: Texture, render_manager: &RenderManager) -> Self {
+        Self::create(render_manager.device(), texture, true)
+    }
+
+    fn create(device: &Device, normal_map: Texture, use_normal_map: bool) -> Self {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytes_of(&VertexColorMaterialUniform {
+                use_normal_map: use_normal_map as u32,
+                ..Default::default()
+            }),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let normal_map_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(&normal_map_sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(
+                        &normal_map.create_view(&Default::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        VertexColorMaterial {
+            _uniform_buffer: uniform_buffer,
+            _normal_map_texture: normal_map,
+            _normal_map_sampler: normal_map_sampler,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+}
+
+impl Material for VertexColorMaterial {
+    fn name(&self) -> &str {
+        "vertex color material"
+    }
+
+    fn fragment_source(&self) -> Result<Cow<'static, str>, TerrainError> {
+        Ok(Cow::Borrowed(include_str!(
+            "../shaders/vertex_color_material.wgsl"
+        )))
+    }
+
+    fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
@@ -1,34 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use glam::Vec3;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Buffer, BufferUsages, Device,
+    Buffer, BufferDescriptor, BufferUsages, Device, IndexFormat, PrimitiveTopology, Queue,
 };
 
 use crate::render::vertex::Vertex;
 
+const MIN_POOLED_BUFFER_SIZE: u64 = 256;
+
+/// Index restarting strips of strip topology meshes
+pub const STRIP_RESTART_INDEX: u32 = u32::MAX;
+
+/// Mesh contents on the CPU, which can be built on any thread and uploaded later
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub topology: PrimitiveTopology,
+}
+
 pub struct Mesh {
     vertices: Box<[Vertex]>,
-    indices: Box<[u16]>,
+    indices: Box<[u32]>,
+    topology: PrimitiveTopology,
 
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    vertex_buffer: MeshBuffer,
+    index_buffer: MeshBuffer,
 }
 
 impl Mesh {
-    pub fn new(device: &Device, vertices: Box<[Vertex]>, indices: Box<[u16]>) -> Mesh {
-        let vertex_buffer = Self::create_vertex_buffer(device, &vertices);
-        let index_buffer = Self::create_index_buffer(device, &indices);
+    pub fn new(device: &Device, vertices: Box<[Vertex]>, indices: Box<[u32]>) -> Mesh {
+        Self::with_usages(device, vertices, indices, BufferUsages::empty())
+    }
+
+    /// Adds `extra_usages` to both buffers, e.g. `COPY_SRC` for reading the mesh back
+    /// or `STORAGE` for writing it from compute shaders
+    pub fn with_usages(
+        device: &Device,
+        vertices: Box<[Vertex]>,
+        indices: Box<[u32]>,
+        extra_usages: BufferUsages,
+    ) -> Mesh {
+        let vertex_buffer = Self::create_vertex_buffer(device, &vertices, extra_usages);
+        let index_buffer = Self::create_index_buffer(device, &indices, extra_usages);
 
         Mesh {
             vertices,
             indices,
-            vertex_buffer,
-            index_buffer,
+            topology: PrimitiveTopology::TriangleList,
+            vertex_buffer: MeshBuffer::new(vertex_buffer, None),
+            index_buffer: MeshBuffer::new(index_buffer, None),
         }
     }
 
-    pub fn from_slices(device: &Device, vertices: &[Vertex], indices: &[u16]) -> Mesh {
+    /// Takes the buffers from the pool, which gets them back when the mesh is dropped
+    pub fn new_pooled(
+        device: &Device,
+        queue: &Queue,
+        vertices: Box<[Vertex]>,
+        indices: Box<[u32]>,
+        pool: &Arc<MeshBufferPool>,
+    ) -> Mesh {
+        let vertex_data = bytemuck::cast_slice(&vertices);
+        let vertex_buffer = pool.acquire(
+            device,
+            vertex_data.len() as u64,
+            BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        );
+        queue.write_buffer(&vertex_buffer, 0, vertex_data);
+
+        let index_data = bytemuck::cast_slice(&indices);
+        let index_buffer = pool.acquire(
+            device,
+            index_data.len() as u64,
+            BufferUsages::INDEX | BufferUsages::COPY_DST,
+        );
+        queue.write_buffer(&index_buffer, 0, index_data);
+
+        Mesh {
+            vertices,
+            indices,
+            topology: PrimitiveTopology::TriangleList,
+            vertex_buffer: MeshBuffer::new(vertex_buffer, Some(pool.clone())),
+            index_buffer: MeshBuffer::new(index_buffer, Some(pool.clone())),
+        }
+    }
+
+    pub fn from_data(device: &Device, data: MeshData) -> Mesh {
+        Self::new(
+            device,
+            data.vertices.into_boxed_slice(),
+            data.indices.into_boxed_slice(),
+        )
+        .with_topology(data.topology)
+    }
+
+    pub fn from_slices(device: &Device, vertices: &[Vertex], indices: &[u32]) -> Mesh {
         let mut vertices_vec = Vec::<Vertex>::new();
-        let mut indices_vec = Vec::<u16>::new();
+        let mut indices_vec = Vec::<u32>::new();
 
         vertices_vec.extend_from_slice(vertices);
         indices_vec.extend_from_slice(indices);
@@ -40,35 +117,241 @@ impl Mesh {
         )
     }
 
+    /// Strip meshes use `STRIP_RESTART_INDEX` indices to restart strips
+    pub fn with_topology(mut self, topology: PrimitiveTopology) -> Mesh {
+        self.topology = topology;
+        self
+    }
+
+    pub fn topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    /// Index format pipelines drawing the mesh must set for strip topologies
+    pub fn strip_index_format(&self) -> Option<IndexFormat> {
+        self.topology.is_strip().then_some(IndexFormat::Uint32)
+    }
+
+    /// Triangles drawn with the whole index buffer, none for line and point meshes
+    pub fn triangles_count(&self) -> u32 {
+        let count = match self.topology {
+            PrimitiveTopology::TriangleList => self.indices.len() / 3,
+            PrimitiveTopology::TriangleStrip => self
+                .indices
+                .split(|i| *i == STRIP_RESTART_INDEX)
+                .map(|strip| strip.len().saturating_sub(2))
+                .sum(),
+            _ => 0,
+        };
+
+        count as u32
+    }
+
     pub fn vertices(&self) -> &[Vertex] {
         &self.vertices
     }
 
-    pub fn indices(&self) -> &[u16] {
+    pub fn indices(&self) -> &[u32] {
         &self.indices
     }
 
+    /// Rebuilds smooth per-vertex normals from the faces sharing each vertex and
+    /// uploads them. Uses the same winding convention as `create_triangle_plane`.
+    pub fn recalculate_normals(&mut self, queue: &Queue) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+
+        for [i0, i1, i2] in self.triangles() {
+            let p0 = self.vertices[i0].position;
+
+            // Not normalized, so larger faces contribute more
+            let n = (self.vertices[i2].position - p0).cross(self.vertices[i1].position - p0);
+            for i in [i0, i1, i2] {
+                normals[i] += n;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or_zero();
+        }
+
+        queue.write_buffer(
+            self.vertex_buffer(),
+            0,
+            bytemuck::cast_slice(&self.vertices),
+        );
+    }
+
+    /// Pooled buffers may be larger than the mesh data
     pub fn vertex_buffer(&self) -> &Buffer {
-        &self.vertex_buffer
+        self.vertex_buffer.get()
     }
 
     pub fn index_buffer(&self) -> &Buffer {
-        &self.index_buffer
+        self.index_buffer.get()
     }
 
-    fn create_vertex_buffer(device: &Device, vertices: &[Vertex]) -> Buffer {
+    /// Triangles of the mesh with their original winding
+    fn triangles(&self) -> Vec<[usize; 3]> {
+        let mut triangles = Vec::new();
+
+        match self.topology {
+            PrimitiveTopology::TriangleList => {
+                for triangle in self.indices.chunks_exact(3) {
+                    triangles.push([0, 1, 2].map(|i| triangle[i] as usize));
+                }
+            }
+            PrimitiveTopology::TriangleStrip => {
+                for strip in self.indices.split(|i| *i == STRIP_RESTART_INDEX) {
+                    for (k, w) in strip.windows(3).enumerate() {
+                        let [a, b, c] = [0, 1, 2].map(|i| w[i] as usize);
+                        // Every odd triangle of a strip has its winding flipped
+                        triangles.push(if k % 2 == 0 { [a, b, c] } else { [b, a, c] });
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        triangles
+    }
+
+    fn create_vertex_buffer(
+        device: &Device,
+        vertices: &[Vertex],
+        extra_usages: BufferUsages,
+    ) -> Buffer {
         device.create_buffer_init(&BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(vertices),
-            usage: BufferUsages::VERTEX,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | extra_usages,
         })
     }
 
-    fn create_index_buffer(device: &Device, indices: &[u16]) -> Buffer {
+    fn create_index_buffer(device: &Device, indices: &[u32], extra_usages: BufferUsages) -> Buffer {
         device.create_buffer_init(&BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&indices),
-            usage: BufferUsages::INDEX,
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX | extra_usages,
         })
     }
 }
+
+/// Recycles mesh buffers, so meshes created and dropped every frame, e.g. while
+/// streaming terrain, don't allocate new ones. Buffer sizes are rounded up to powers
+/// of two, which lets meshes of similar sizes share them.
+#[derive(Default)]
+pub struct MeshBufferPool {
+    free_buffers: Mutex<HashMap<(u64, BufferUsages), Vec<Buffer>>>,
+    allocated_count: AtomicUsize,
+}
+
+impl MeshBufferPool {
+    pub fn new() -> Arc<MeshBufferPool> {
+        Arc::new(MeshBufferPool::default())
+    }
+
+    /// Number of buffers the pool has created, reused ones are counted once
+    pub fn allocated_count(&self) -> usize {
+        self.allocated_count.load(Ordering::Relaxed)
+    }
+
+    /// Number of buffers waiting to be reused
+    pub fn free_count(&self) -> usize {
+        self.free_buffers
+            .lock()
+            .unwrap()
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Destroys the buffers waiting to be reused
+    pub fn clear(&self) {
+        self.free_buffers.lock().unwrap().clear();
+    }
+
+    fn acquire(&self, device: &Device, size: u64, usage: BufferUsages) -> Buffer {
+        let size = size.max(MIN_POOLED_BUFFER_SIZE).next_power_of_two();
+
+        let reused = self
+            .free_buffers
+            .lock()
+            .unwrap()
+            .get_mut(&(size, usage))
+            .and_then(Vec::pop);
+        reused.unwrap_or_else(|| {
+            self.allocated_count.fetch_add(1, Ordering::Relaxed);
+            device.create_buffer(&BufferDescriptor {
+                label: None,
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        })
+    }
+
+    fn release(&self, buffer: Buffer) {
+        self.free_buffers
+            .lock()
+            .unwrap()
+            .entry((buffer.size(), buffer.usage()))
+            .or_default()
+            .push(buffer);
+    }
+}
+
+/// Buffer going back to its pool on drop, if it came from one
+struct MeshBuffer {
+    buffer: Option<Buffer>,
+    pool: Option<Arc<MeshBufferPool>>,
+}
+
+impl MeshBuffer {
+    fn new(buffer: Buffer, pool: Option<Arc<MeshBufferPool>>) -> MeshBuffer {
+        MeshBuffer {
+            buffer: Some(buffer),
+            pool,
+        }
+    }
+
+    fn get(&self) -> &Buffer {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl Drop for MeshBuffer {
+    fn drop(&mut self) {
+        if let (Some(pool), Some(buffer)) = (&self.pool, self.buffer.take()) {
+            pool.release(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::utils::test_device;
+
+    use super::*;
+
+    #[test]
+    fn pool_reuses_buffers() {
+        let Some((device, queue)) = test_device() else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+
+        let pool = MeshBufferPool::new();
+        let vertices = [Vertex::new(Vec3::ZERO, Vec3::Y, Vec3::ONE); 3];
+        for _ in 0..1000 {
+            let mesh =
+                Mesh::new_pooled(&device, &queue, vertices.into(), Box::new([0, 1, 2]), &pool);
+            drop(mesh);
+        }
+
+        // One vertex and one index buffer, reused by every mesh
+        assert_eq!(pool.allocated_count(), 2);
+        assert_eq!(pool.free_count(), 2);
+    }
+}
@@ -1,60 +1,362 @@
+use std::path::{Path, PathBuf};
+
+use bytemuck::{bytes_of, Pod, Zeroable};
+use glam::{Mat4, Quat, Vec3};
 use wgpu::{
-    include_wgsl, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
-    DepthStencilState, Face, FragmentState, FrontFace, IndexFormat, LoadOp, MultisampleState,
-    Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+    BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState, ColorWrites, DepthBiasState,
+    DepthStencilState, Device, Face, FragmentState, FrontFace, IndexFormat, LoadOp,
+    MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
     PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
     RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState,
     StencilState, StoreOp, VertexState,
 };
 
+use crate::{
+    core::error::TerrainError,
+    utils::{create_uniform_init, pipeline::primitive_state},
+};
+
 use super::{
     bind_group::BindGroupHelper,
+    material::{Material, VertexColorMaterial},
     mesh::Mesh,
     render_manager::RenderManager,
-    renderer::{RenderStage, Renderer, RenderingContext},
+    renderer::{RenderStage, RenderTargets, Renderer, RenderingContext},
+    scene::Transform,
+    shader::{catch_validation_errors, create_shader_module, ShaderSource},
     vertex::Vertex,
 };
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MeshRenderMode {
+    Opaque,
+    AlphaBlend,
+    AlphaTest(f32),
+}
+
+/// Animated light pattern added to the parts of the mesh below the water level
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CausticsSettings {
+    pub water_level: f32,
+    pub intensity: f32,
+    pub scale: f32,
+    pub speed: f32,
+    /// How fast caustics fade with the depth below the water surface
+    pub falloff: f32,
+}
+
+impl Default for CausticsSettings {
+    fn default() -> Self {
+        Self {
+            water_level: 0.0,
+            intensity: 0.6,
+            scale: 1.5,
+            speed: 1.0,
+            falloff: 1.5,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MeshRendererSettings {
+    pub render_mode: MeshRenderMode,
+    pub opacity: f32,
+    pub caustics: Option<CausticsSettings>,
+    /// `None` draws both sides, flipping normals of back faces towards the viewer
+    pub cull_mode: Option<Face>,
+    pub front_face: FrontFace,
+    /// Anything but `Fill` needs the matching feature, see `RenderSettings::wireframe`
+    pub polygon_mode: PolygonMode,
+    /// WGSL code of the vertex stage, which the material appends its fragment stage to
+    pub shader: ShaderSource,
+}
+
+impl Default for MeshRendererSettings {
+    fn default() -> Self {
+        Self {
+            render_mode: MeshRenderMode::Opaque,
+            opacity: 1.0,
+            caustics: None,
+            cull_mode: Some(Face::Back),
+            front_face: FrontFace::Ccw,
+            polygon_mode: PolygonMode::Fill,
+            shader: ShaderSource::Builtin,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub(super) struct MeshUniform {
+    pub opacity: f32,
+    pub alpha_cutoff: f32,
+    pub use_caustics: u32,
+    pub caustics_level: f32,
+    pub caustics_intensity: f32,
+    pub caustics_scale: f32,
+    pub caustics_speed: f32,
+    pub caustics_falloff: f32,
+    pub double_sided: u32,
+    _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub(super) struct ModelUniform {
+    pub model_matrix: Mat4,
+    pub normal_matrix: Mat4,
+}
+
 pub struct MeshRenderer {
     _shader: ShaderModule,
-    _pipeline_layout: PipelineLayout,
+    pipeline_layout: PipelineLayout,
     pipeline: RenderPipeline,
     mesh: Mesh,
+    settings: MeshRendererSettings,
+    targets: RenderTargets,
+
+    _uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    material: Box<dyn Material>,
+
+    transform: Transform,
+    is_transform_dirty: bool,
+    model_uniform_buffer: Buffer,
+    model_bind_group_layout: BindGroupLayout,
+    model_bind_group: BindGroup,
 }
 
 impl MeshRenderer {
-    pub fn new(mesh: Mesh, render_manager: &RenderManager) -> MeshRenderer {
+    /// Shades the mesh with a `VertexColorMaterial`
+    pub fn new(
+        mesh: Mesh,
+        settings: &MeshRendererSettings,
+        render_manager: &RenderManager,
+    ) -> Result<MeshRenderer, TerrainError> {
+        Self::with_material(
+            mesh,
+            Box::new(VertexColorMaterial::new(render_manager)),
+            settings,
+            render_manager,
+        )
+    }
+
+    pub fn with_material(
+        mesh: Mesh,
+        material: Box<dyn Material>,
+        settings: &MeshRendererSettings,
+        render_manager: &RenderManager,
+    ) -> Result<MeshRenderer, TerrainError> {
         let device = render_manager.device();
 
-        let shader = device.create_shader_module(include_wgsl!("../shaders/mesh.wgsl"));
+        let shader = Self::create_shader(device, &settings.shader, material.as_ref())?;
 
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[render_manager.scene_bind_group().borrow().layout()],
-            push_constant_ranges: &[],
+        let (uniform_buffer, bind_group_layout, bind_group) =
+            create_uniform_init(&Self::mesh_uniform(settings), device);
+
+        let transform = Transform::default();
+        let (model_uniform_buffer, model_bind_group_layout, model_bind_group) =
+            create_uniform_init(&Self::model_uniform(&transform), device);
+
+        let pipeline_layout = Self::create_pipeline_layout(
+            render_manager,
+            &bind_group_layout,
+            material.as_ref(),
+            &model_bind_group_layout,
+        );
+
+        let targets = render_manager.render_targets();
+        let pipeline = catch_validation_errors(device, "mesh", || {
+            Self::create_pipeline(
+                device,
+                &shader,
+                &pipeline_layout,
+                mesh.topology(),
+                settings,
+                &targets,
+            )
+        })?;
+
+        Ok(MeshRenderer {
+            _shader: shader,
+            pipeline_layout,
+            pipeline,
+            mesh,
+            settings: settings.clone(),
+            targets,
+
+            _uniform_buffer: uniform_buffer,
+            bind_group_layout,
+            bind_group,
+
+            material,
+
+            transform,
+            is_transform_dirty: false,
+            model_uniform_buffer,
+            model_bind_group_layout,
+            model_bind_group,
+        })
+    }
+
+    /// Swaps the material, rebuilding the shader and the pipeline for its bindings
+    pub fn set_material(
+        &mut self,
+        material: Box<dyn Material>,
+        render_manager: &RenderManager,
+    ) -> Result<(), TerrainError> {
+        let device = render_manager.device();
+
+        let shader = Self::create_shader(device, &self.settings.shader, material.as_ref())?;
+        let pipeline_layout = Self::create_pipeline_layout(
+            render_manager,
+            &self.bind_group_layout,
+            material.as_ref(),
+            &self.model_bind_group_layout,
+        );
+        self.pipeline = catch_validation_errors(device, "mesh", || {
+            Self::create_pipeline(
+                device,
+                &shader,
+                &pipeline_layout,
+                self.mesh.topology(),
+                &self.settings,
+                &self.targets,
+            )
+        })?;
+        self._shader = shader;
+        self.pipeline_layout = pipeline_layout;
+        self.material = material;
+
+        Ok(())
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.is_transform_dirty = true;
+    }
+
+    pub fn set_translation(&mut self, translation: Vec3) {
+        self.set_transform(Transform {
+            translation,
+            ..self.transform
+        });
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        self.set_transform(Transform {
+            rotation,
+            ..self.transform
+        });
+    }
+
+    pub fn set_scale(&mut self, scale: Vec3) {
+        self.set_transform(Transform {
+            scale,
+            ..self.transform
         });
+    }
+
+    pub(super) fn mesh_uniform(settings: &MeshRendererSettings) -> MeshUniform {
+        let mut uniform = MeshUniform {
+            opacity: settings.opacity,
+            alpha_cutoff: match settings.render_mode {
+                MeshRenderMode::AlphaTest(cutoff) => cutoff,
+                _ => 0.0,
+            },
+            double_sided: settings.cull_mode.is_none() as u32,
+            ..Default::default()
+        };
+        if let Some(caustics) = &settings.caustics {
+            uniform.use_caustics = 1;
+            uniform.caustics_level = caustics.water_level;
+            uniform.caustics_intensity = caustics.intensity;
+            uniform.caustics_scale = caustics.scale;
+            uniform.caustics_speed = caustics.speed;
+            uniform.caustics_falloff = caustics.falloff;
+        }
+
+        uniform
+    }
+
+    pub(super) fn model_uniform(transform: &Transform) -> ModelUniform {
+        ModelUniform {
+            model_matrix: transform.matrix(),
+            normal_matrix: transform.normal_matrix(),
+        }
+    }
+
+    /// Compiles the vertex stage followed by the fragment stage of the material
+    pub(super) fn create_shader(
+        device: &Device,
+        source: &ShaderSource,
+        material: &dyn Material,
+    ) -> Result<ShaderModule, TerrainError> {
+        let mut code = source
+            .load(include_str!("../shaders/mesh.wgsl"))?
+            .into_owned();
+        code.push_str(&material.fragment_source()?);
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        create_shader_module(
+            device,
+            &format!("mesh with {}", material.name()),
+            &ShaderSource::Source(code),
+            "",
+        )
+    }
+
+    pub(super) fn create_pipeline_layout(
+        render_manager: &RenderManager,
+        bind_group_layout: &BindGroupLayout,
+        material: &dyn Material,
+        model_bind_group_layout: &BindGroupLayout,
+    ) -> PipelineLayout {
+        render_manager
+            .device()
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    render_manager.scene_bind_group().borrow().layout(),
+                    bind_group_layout,
+                    material.bind_group_layout(),
+                    model_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            })
+    }
+
+    pub(super) fn create_pipeline(
+        device: &Device,
+        shader: &ShaderModule,
+        pipeline_layout: &PipelineLayout,
+        topology: PrimitiveTopology,
+        settings: &MeshRendererSettings,
+        targets: &RenderTargets,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 buffers: &[Vertex::buffer_layout()],
             },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
+            primitive: primitive_state(
+                topology,
+                settings.cull_mode,
+                settings.polygon_mode,
+                settings.front_face,
+            ),
             depth_stencil: Some(DepthStencilState {
-                format: render_manager.depth_texture().format(),
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::Less,
+                format: targets.depth_format,
+                depth_write_enabled: settings.render_mode != MeshRenderMode::AlphaBlend,
+                depth_compare: targets.depth_compare,
                 stencil: StencilState {
                     front: StencilFaceState::IGNORE,
                     back: StencilFaceState::IGNORE,
@@ -64,33 +366,37 @@ impl MeshRenderer {
                 bias: DepthBiasState::default(),
             }),
             multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+                count: targets.sample_count,
+                ..Default::default()
             },
             fragment: Some(FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(ColorTargetState {
-                    format: render_manager.surface_format(),
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::COLOR,
+                    format: targets.color_format,
+                    blend: Some(match settings.render_mode {
+                        MeshRenderMode::AlphaBlend => BlendState::ALPHA_BLENDING,
+                        _ => BlendState::REPLACE,
+                    }),
+                    write_mask: ColorWrites::ALL,
                 })],
             }),
             multiview: None,
-        });
-
-        MeshRenderer {
-            _shader: shader,
-            _pipeline_layout: pipeline_layout,
-            pipeline,
-            mesh,
-        }
+        })
     }
 }
 
 impl Renderer for MeshRenderer {
     fn render(&mut self, context: &RenderingContext) {
+        if self.is_transform_dirty {
+            context.queue().borrow().write_buffer(
+                &self.model_uniform_buffer,
+                0,
+                bytes_of(&Self::model_uniform(&self.transform)),
+            );
+            self.is_transform_dirty = false;
+        }
+
         let mut encoder_ref = context.encoder().borrow_mut();
         let encoder = encoder_ref.as_mut().unwrap();
 
@@ -116,15 +422,57 @@ impl Renderer for MeshRenderer {
             occlusion_query_set: None,
         });
 
+        context.apply_viewport(&mut pass);
         pass.set_pipeline(&self.pipeline);
         pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
-        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint16);
+        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint32);
         pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_bind_group(2, self.material.bind_group(), &[]);
+        pass.set_bind_group(3, &self.model_bind_group, &[]);
 
         pass.draw_indexed(0..(self.mesh.indices().len() as u32), 0, 0..1);
+        context.record_draw(
+            self.mesh.indices().len() as u32,
+            self.mesh.triangles_count(),
+        );
     }
 
     fn stage(&self) -> RenderStage {
-        RenderStage::OPAQUE
+        match self.settings.render_mode {
+            MeshRenderMode::AlphaBlend => RenderStage::TRANSPARENT,
+            _ => RenderStage::OPAQUE,
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.targets.sample_count
+    }
+
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        self.settings
+            .shader
+            .path()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(self.material.shader_paths())
+            .collect()
+    }
+
+    fn reload_shaders(&mut self, device: &Device) -> Result<(), TerrainError> {
+        let shader = Self::create_shader(device, &self.settings.shader, self.material.as_ref())?;
+        self.pipeline = catch_validation_errors(device, "mesh", || {
+            Self::create_pipeline(
+                device,
+                &shader,
+                &self.pipeline_layout,
+                self.mesh.topology(),
+                &self.settings,
+                &self.targets,
+            )
+        })?;
+        self._shader = shader;
+
+        Ok(())
     }
 }
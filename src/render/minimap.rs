@@ -0,0 +1,420 @@
+use bytemuck::{bytes_of, Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use wgpu::{
+    include_wgsl, AddressMode, BindGroup, BindGroupLayout, BlendState, Buffer, Color,
+    ColorTargetState, ColorWrites, DepthBiasState, DepthStencilState, Face, FilterMode,
+    FragmentState, FrontFace, IndexFormat, LoadOp, MultisampleState, Operations, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderModule, StencilFaceState,
+    StencilState, StoreOp, Texture, TextureUsages, TextureView, VertexState,
+};
+
+use crate::utils::{
+    create_texture_2d, create_texture_bind_group, create_texture_bind_group_layout,
+    create_uniform_init, srgb_to_linear,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    mesh::Mesh,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MinimapCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Copy)]
+pub struct MinimapSettings {
+    pub enabled: bool,
+    /// Side of the minimap in pixels
+    pub size: u32,
+    pub corner: MinimapCorner,
+    /// Distance from the window edges in pixels
+    pub margin: u32,
+    pub marker_color: Vec3,
+    /// Marker length in fractions of the minimap size
+    pub marker_size: f32,
+    pub linear_colors: bool,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            size: 200,
+            corner: MinimapCorner::TopRight,
+            margin: 16,
+            marker_color: Vec3::new(0.9, 0.15, 0.15),
+            marker_size: 0.08,
+            linear_colors: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct MinimapUniform {
+    pub view_proj_matrix: Mat4,
+    pub marker_pos: Vec2,
+    pub marker_dir: Vec2,
+    pub marker_color: Vec3,
+    pub marker_size: f32,
+}
+
+/// Top-down overview of the terrain drawn in a corner of the window.
+///
+/// The terrain is rendered each frame with an orthographic camera fitted to the mesh
+/// bounds, then composited over the surface together with a marker showing the
+/// position and facing of the main camera.
+pub struct Minimap {
+    settings: MinimapSettings,
+
+    _shader: ShaderModule,
+    _terrain_pipeline_layout: PipelineLayout,
+    terrain_pipeline: RenderPipeline,
+    _overlay_pipeline_layout: PipelineLayout,
+    overlay_pipeline: RenderPipeline,
+
+    mesh: Mesh,
+    depth_clear_value: f32,
+
+    uniform: Box<MinimapUniform>,
+    uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    _color_texture: Texture,
+    color_view: TextureView,
+    _depth_texture: Texture,
+    depth_view: TextureView,
+    _sampler: Sampler,
+    _texture_bind_group_layout: BindGroupLayout,
+    texture_bind_group: BindGroup,
+}
+
+impl Minimap {
+    pub fn new(
+        terrain_mesh: &Mesh,
+        settings: &MinimapSettings,
+        render_manager: &RenderManager,
+    ) -> Minimap {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/minimap.wgsl"));
+
+        let mesh = Mesh::from_slices(device, terrain_mesh.vertices(), terrain_mesh.indices())
+            .with_topology(terrain_mesh.topology());
+
+        let uniform = Box::new(MinimapUniform {
+            view_proj_matrix: Self::calc_view_proj_matrix(
+                mesh.vertices(),
+                render_manager.reversed_z(),
+            ),
+            marker_color: if settings.linear_colors {
+                srgb_to_linear(settings.marker_color)
+            } else {
+                settings.marker_color
+            },
+            marker_size: settings.marker_size,
+            ..Default::default()
+        });
+        let (uniform_buffer, bind_group_layout, bind_group) =
+            create_uniform_init(uniform.as_ref(), device);
+
+        let color_texture = create_texture_2d(
+            device,
+            render_manager.surface_format(),
+            settings.size,
+            settings.size,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        );
+        let color_view = color_texture.create_view(&Default::default());
+        let depth_texture = create_texture_2d(
+            device,
+            render_manager.depth_texture().format(),
+            settings.size,
+            settings.size,
+            TextureUsages::RENDER_ATTACHMENT,
+        );
+        let depth_view = depth_texture.create_view(&Default::default());
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let texture_bind_group_layout = create_texture_bind_group_layout(device);
+        let texture_bind_group =
+            create_texture_bind_group(device, &texture_bind_group_layout, &color_view, &sampler);
+
+        let scene_bind_group = render_manager.scene_bind_group().borrow();
+
+        let terrain_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[scene_bind_group.layout(), &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let overlay_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                scene_bind_group.layout(),
+                &bind_group_layout,
+                &texture_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let terrain_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&terrain_pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_terrain",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: mesh.topology(),
+                strip_index_format: mesh.strip_index_format(),
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: depth_texture.format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_terrain",
+                targets: &[Some(ColorTargetState {
+                    format: color_texture.format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let overlay_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&overlay_pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_overlay",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_overlay",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        drop(scene_bind_group);
+
+        Minimap {
+            settings: *settings,
+
+            _shader: shader,
+            _terrain_pipeline_layout: terrain_pipeline_layout,
+            terrain_pipeline,
+            _overlay_pipeline_layout: overlay_pipeline_layout,
+            overlay_pipeline,
+
+            mesh,
+            depth_clear_value: render_manager.depth_clear_value(),
+
+            uniform,
+            uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+
+            _color_texture: color_texture,
+            color_view,
+            _depth_texture: depth_texture,
+            depth_view,
+            _sampler: sampler,
+            _texture_bind_group_layout: texture_bind_group_layout,
+            texture_bind_group,
+        }
+    }
+
+    /// Returns `(x, y, size)` of the minimap on the surface in pixels
+    fn calc_overlay_rect(&self, viewport_rect: Vec4) -> Option<(f32, f32, f32)> {
+        let size = self.settings.size as f32;
+        let margin = self.settings.margin as f32;
+        if size + margin > viewport_rect.z || size + margin > viewport_rect.w {
+            return None;
+        }
+
+        let left = viewport_rect.x + margin;
+        let right = viewport_rect.x + viewport_rect.z - size - margin;
+        let top = viewport_rect.y + margin;
+        let bottom = viewport_rect.y + viewport_rect.w - size - margin;
+
+        Some(match self.settings.corner {
+            MinimapCorner::TopLeft => (left, top, size),
+            MinimapCorner::TopRight => (right, top, size),
+            MinimapCorner::BottomLeft => (left, bottom, size),
+            MinimapCorner::BottomRight => (right, bottom, size),
+        })
+    }
+
+    fn update_marker(&mut self, context: &RenderingContext) {
+        let mut camera = context.camera().borrow_mut();
+
+        let p = self
+            .uniform
+            .view_proj_matrix
+            .project_point3(camera.position());
+        self.uniform.marker_pos = Vec2::new(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
+
+        // The minimap looks down with world +Z pointing up, while uv's Y axis points down
+        let look_dir = camera.look_dir();
+        self.uniform.marker_dir = Vec2::new(look_dir.x, -look_dir.z)
+            .try_normalize()
+            .unwrap_or(Vec2::NEG_Y);
+
+        context.queue().borrow_mut().write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytes_of(self.uniform.as_ref()),
+        );
+    }
+
+    fn calc_view_proj_matrix(vertices: &[Vertex], reversed_z: bool) -> Mat4 {
+        if vertices.is_empty() {
+            return Mat4::IDENTITY;
+        }
+
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| (min.min(v.position), max.max(v.position)),
+        );
+
+        let center = (min + max) * 0.5;
+        let extent = ((max.x - min.x).max(max.z - min.z) * 0.5).max(f32::EPSILON);
+        let eye = Vec3::new(center.x, max.y + 1.0, center.z);
+
+        let view = Mat4::look_at_lh(eye, Vec3::new(center.x, min.y, center.z), Vec3::Z);
+        let (near, far) = (0.0, max.y - min.y + 2.0);
+        let (near, far) = if reversed_z { (far, near) } else { (near, far) };
+        let proj = Mat4::orthographic_lh(-extent, extent, -extent, extent, near, far);
+
+        proj * view
+    }
+}
+
+impl Renderer for Minimap {
+    fn render(&mut self, context: &RenderingContext) {
+        let Some((x, y, size)) = self.calc_overlay_rect(context.viewport_rect()) else {
+            return;
+        };
+
+        self.update_marker(context);
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &self.color_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(self.depth_clear_value),
+                        store: StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&self.terrain_pipeline);
+            pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
+            pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint32);
+            pass.set_bind_group(0, context.scene_bind_group(), &[]);
+            pass.set_bind_group(1, &self.bind_group, &[]);
+
+            pass.draw_indexed(0..(self.mesh.indices().len() as u32), 0, 0..1);
+            context.record_draw(
+                self.mesh.indices().len() as u32,
+                self.mesh.triangles_count(),
+            );
+        }
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_viewport(x, y, size, size, 0.0, 1.0);
+        pass.set_pipeline(&self.overlay_pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_bind_group(2, &self.texture_bind_group, &[]);
+
+        pass.draw(0..3, 0..1);
+        context.record_draw(3, 1);
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OVERLAY
+    }
+
+    fn sample_count(&self) -> u32 {
+        1
+    }
+}
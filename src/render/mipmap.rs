@@ -0,0 +1,118 @@
+use wgpu::{
+    include_wgsl, AddressMode, BlendState, Color, ColorTargetState, ColorWrites, Device,
+    FilterMode, FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderModule, StoreOp, Texture,
+    TextureFormat, TextureViewDescriptor, VertexState,
+};
+
+use crate::utils::{create_texture_bind_group, create_texture_bind_group_layout};
+
+use super::renderer::RenderingContext;
+
+/// Fills the mip chain of a texture by repeatedly downsampling the previous level
+pub struct MipmapGenerator {
+    _shader: ShaderModule,
+    pipeline: RenderPipeline,
+    sampler: Sampler,
+}
+
+impl MipmapGenerator {
+    /// Textures passed to `generate` must have this format and be usable both as
+    /// a render attachment and a texture binding
+    pub fn new(device: &Device, format: TextureFormat) -> MipmapGenerator {
+        let shader = device.create_shader_module(include_wgsl!("../shaders/mipmap.wgsl"));
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = create_texture_bind_group_layout(device);
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        MipmapGenerator {
+            _shader: shader,
+            pipeline,
+            sampler,
+        }
+    }
+
+    pub fn generate(&self, device: &Device, context: &RenderingContext, texture: &Texture) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let bind_group_layout = self.pipeline.get_bind_group_layout(0);
+        let level_view = |level| {
+            texture.create_view(&TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        for level in 1..texture.mip_level_count() {
+            let source = create_texture_bind_group(
+                device,
+                &bind_group_layout,
+                &level_view(level - 1),
+                &self.sampler,
+            );
+            let target = level_view(level);
+
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &source, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Levels in a full mip chain of a texture of the given size
+pub fn mip_levels_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
@@ -1,9 +1,30 @@
 pub mod bind_group;
+pub mod bloom;
+pub mod chunked_terrain_renderer;
+pub mod coordinate_system;
+pub mod debug_line_renderer;
+pub mod debug_view;
+pub mod depth_copy;
+pub mod detail_terrain_renderer;
+pub mod foliage_renderer;
+pub mod fxaa;
+pub mod gpu_timer;
+pub mod grid_renderer;
+pub mod material;
 pub mod mesh;
 pub mod mesh_renderer;
+pub mod minimap;
+pub mod mipmap;
+pub mod point_cloud_renderer;
 pub mod render_manager;
 pub mod renderer;
 pub mod scene;
+pub mod scene_objects;
+pub mod shader;
 pub mod skybox_renderer;
+pub mod solid_fill;
+pub mod splat_terrain_renderer;
+pub mod ssao;
+pub mod textured_mesh_renderer;
 pub mod vertex;
 pub mod water_renderer;
@@ -0,0 +1,279 @@
+use std::{collections::HashSet, mem::size_of, rc::Rc};
+
+use bytemuck::{cast_slice, Pod, Zeroable};
+use glam::{Vec2, Vec3, Vec4};
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BlendState, Buffer, BufferUsages, ColorTargetState, ColorWrites, DepthBiasState,
+    DepthStencilState, Face, FragmentState, FrontFace, LoadOp, Operations, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveTopology, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, StencilState, StoreOp, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+};
+
+use crate::utils::{color_ramp::ColorRamp, pipeline::primitive_state, srgb_to_linear};
+
+use super::{
+    bind_group::{BindGroupHelper, UniformBindGroup},
+    mesh::Mesh,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+};
+
+#[derive(Clone)]
+pub struct PointCloudSettings {
+    /// Width of the points in pixels
+    pub point_size: f32,
+    /// Sampled by the height normalized between the lowest and the highest point
+    pub colors: ColorRamp,
+    pub linear_colors: bool,
+}
+
+impl Default for PointCloudSettings {
+    fn default() -> Self {
+        Self {
+            point_size: 3.0,
+            colors: ColorRamp::builder()
+                .stop(0.0, Vec3::new(0.1, 0.2, 0.8))
+                .stop(0.5, Vec3::new(0.2, 0.8, 0.3))
+                .stop(1.0, Vec3::new(1.0, 1.0, 1.0))
+                .smooth(true)
+                .build()
+                .unwrap(),
+            linear_colors: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct PointCloudUniform {
+    pub viewport_size: Vec2,
+    pub point_size: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct PointInstance {
+    pub position: Vec3,
+    _padding: f32,
+    pub color: Vec4,
+}
+
+static INSTANCE_ATTRIBUTES: [VertexAttribute; 2] = [
+    VertexAttribute {
+        format: VertexFormat::Float32x3,
+        offset: 0,
+        shader_location: 0,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32x4,
+        offset: 16,
+        shader_location: 1,
+    },
+];
+
+/// Draws the vertices of a mesh as points colored by height, ignoring its indices
+/// and shading, e.g. to look at the raw noise output of a terrain.
+///
+/// Point primitives are always one pixel wide in WebGPU, so the points are drawn as
+/// squares facing the camera instead.
+pub struct PointCloudRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+
+    instance_buffer: Buffer,
+    instances_count: u32,
+
+    uniform: UniformBindGroup<PointCloudUniform>,
+    bind_group: Rc<BindGroup>,
+    sample_count: u32,
+}
+
+impl PointCloudRenderer {
+    pub fn new(
+        mesh: &Mesh,
+        settings: &PointCloudSettings,
+        render_manager: &RenderManager,
+    ) -> PointCloudRenderer {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/point_cloud.wgsl"));
+
+        let instances = Self::create_instances(mesh, settings);
+        let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&instances),
+            usage: BufferUsages::VERTEX,
+        });
+
+        let mut uniform = UniformBindGroup::new(
+            device,
+            PointCloudUniform {
+                point_size: settings.point_size,
+                ..Default::default()
+            },
+        );
+        let bind_group = uniform.bind_group(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                render_manager.scene_bind_group().borrow().layout(),
+                uniform.layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: size_of::<PointInstance>() as u64,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &INSTANCE_ATTRIBUTES,
+                }],
+            },
+            primitive: primitive_state(
+                PrimitiveTopology::TriangleList,
+                Some(Face::Back),
+                PolygonMode::Fill,
+                FrontFace::Ccw,
+            ),
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        PointCloudRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+
+            instance_buffer,
+            instances_count: instances.len() as u32,
+
+            uniform,
+            bind_group,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    pub fn set_point_size(&mut self, point_size: f32, render_manager: &RenderManager) {
+        let mut uniform = *self.uniform.uniform();
+        uniform.point_size = point_size;
+        self.uniform
+            .update(&render_manager.queue().borrow(), &uniform);
+    }
+
+    /// Vertices shared between triangles of flat shaded meshes are duplicated, so
+    /// points at the same position are merged
+    fn create_instances(mesh: &Mesh, settings: &PointCloudSettings) -> Vec<PointInstance> {
+        let mut seen = HashSet::new();
+        let positions: Vec<Vec3> = mesh
+            .vertices()
+            .iter()
+            .map(|vertex| vertex.position)
+            .filter(|position| seen.insert(position.to_array().map(f32::to_bits)))
+            .collect();
+
+        let min_height = positions.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_height = positions
+            .iter()
+            .map(|p| p.y)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let height_range = (max_height - min_height).max(f32::EPSILON);
+
+        positions
+            .into_iter()
+            .map(|position| {
+                let color = settings
+                    .colors
+                    .sample((position.y - min_height) / height_range);
+                PointInstance {
+                    position,
+                    color: if settings.linear_colors {
+                        srgb_to_linear(color)
+                    } else {
+                        color
+                    }
+                    .extend(1.0),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Renderer for PointCloudRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut uniform = *self.uniform.uniform();
+        let viewport_rect = context.viewport_rect();
+        uniform.viewport_size = Vec2::new(viewport_rect.z, viewport_rect.w);
+        self.uniform.update(&context.queue().borrow(), &uniform);
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+
+        pass.draw(0..6, 0..self.instances_count);
+        context.record_draw(6 * self.instances_count, 2 * self.instances_count);
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
@@ -1,46 +1,233 @@
-use std::{cell::RefCell, collections::HashMap, iter, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, iter, path::PathBuf, sync::Arc};
 
-use glam::{Quat, Vec2, Vec3};
+use glam::{Quat, Vec2, Vec3, Vec4};
+use image::RgbaImage;
+use log::{info, warn};
 use wgpu::{
-    Adapter, Color, Device, DeviceDescriptor, Instance, Operations, PresentMode, Queue,
-    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RequestAdapterOptions, Surface, SurfaceConfiguration, Texture, TextureFormat, TextureUsages,
-    TextureView,
+    Adapter, Backends, Color, CompareFunction, CompositeAlphaMode, Device, DeviceDescriptor,
+    DownlevelFlags, Features, Instance, Limits, MultisampleState, Operations, PowerPreference,
+    PresentMode, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RequestAdapterOptions, Surface, SurfaceConfiguration, Texture,
+    TextureFormat, TextureUsages, TextureView,
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
 use crate::{
-    core::time_manager::TimeManager,
-    utils::{copy_textures_2d, create_texture_2d},
+    core::{error::TerrainError, time_manager::TimeManager},
+    utils::{
+        clear_depth_texture, copy_textures_2d, create_multisampled_texture_2d, create_texture_2d,
+        create_texture_2d_with_mips, f16_to_f32, linear_to_srgb, read_texture_2d,
+    },
 };
 
 use super::{
     bind_group::BindGroupHelper,
-    renderer::{RenderStage, Renderer, RenderingContext},
-    scene::{Camera, SceneBindGroup},
+    bloom::{BloomPass, BloomSettings},
+    coordinate_system::CoordinateSystem,
+    debug_view::{DebugView, DebugViewPass},
+    depth_copy::DepthCopyPass,
+    fxaa::FxaaPass,
+    gpu_timer::{GpuTimer, RendererId},
+    mipmap::{mip_levels_count, MipmapGenerator},
+    renderer::{RenderStage, RenderStats, RenderTargets, Renderer, RenderingContext, Viewport},
+    scene::{Camera, FogSettings, GlobalLight, SceneBindGroup, SceneUniform},
+    solid_fill::SolidFillPass,
+    ssao::{SsaoPass, SsaoSettings},
 };
 
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+use super::shader::ShaderWatcher;
+
+const HDR_SURFACE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Push constant bytes enabled whenever the adapter supports them, the minimum Vulkan
+/// guarantees
+pub const OPTIONAL_PUSH_CONSTANT_SIZE: u32 = 128;
+
+type RendererEntry = (RendererId, Box<dyn Renderer>);
+type ViewportEntry = (Viewport, Box<RefCell<Camera>>);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BackgroundMode {
+    Skybox,
+    SolidColor(Color),
+    /// Leaves the background with zero alpha, for compositing the image elsewhere
+    Transparent,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResizeMode {
+    /// The picture fills the whole surface, changing its aspect ratio with it
+    Stretch,
+    /// Keeps the picture at the aspect ratio (width / height), centered between black
+    /// bars. Viewports are laid out in the picture rather than the whole surface.
+    Letterbox(f32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntiAliasMode {
+    None,
+    /// Draws the opaque and transparent stages with the sample count, resolving them
+    /// before the snapshots and post-processing. Falls back to `None` when the adapter
+    /// doesn't support the count for the surface or depth format.
+    Msaa(u32),
+    Fxaa,
+}
+
+/// Multisampled attachments of the opaque and transparent stages
+struct MsaaTargets {
+    _color_texture: Texture,
+    color_view: TextureView,
+    depth_texture: Texture,
+    depth_view: TextureView,
+    // Copies the first depth sample to the single sampled depth texture
+    depth_resolve_pass: DepthCopyPass,
+}
+
+impl MsaaTargets {
+    fn new(
+        device: &Device,
+        color_format: TextureFormat,
+        depth_format: TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+    ) -> MsaaTargets {
+        let color_texture = create_multisampled_texture_2d(
+            device,
+            color_format,
+            width,
+            height,
+            sample_count,
+            TextureUsages::RENDER_ATTACHMENT,
+        );
+        let depth_texture = create_multisampled_texture_2d(
+            device,
+            depth_format,
+            width,
+            height,
+            sample_count,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        );
+
+        MsaaTargets {
+            color_view: color_texture.create_view(&Default::default()),
+            _color_texture: color_texture,
+            depth_view: depth_texture.create_view(&Default::default()),
+            depth_texture,
+            depth_resolve_pass: DepthCopyPass::new(device, depth_format, sample_count),
+        }
+    }
+
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        let sample_count = self.depth_texture.sample_count();
+        let color_texture = create_multisampled_texture_2d(
+            device,
+            self._color_texture.format(),
+            width,
+            height,
+            sample_count,
+            self._color_texture.usage(),
+        );
+        self.depth_texture = create_multisampled_texture_2d(
+            device,
+            self.depth_texture.format(),
+            width,
+            height,
+            sample_count,
+            self.depth_texture.usage(),
+        );
+
+        self.color_view = color_texture.create_view(&Default::default());
+        self._color_texture = color_texture;
+        self.depth_view = self.depth_texture.create_view(&Default::default());
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct RenderSettings {
-    clear_color: Color,
+    pub background_mode: BackgroundMode,
+    pub resize_mode: ResizeMode,
+
+    pub power_preference: PowerPreference,
+    pub force_fallback_adapter: bool,
+    /// Picks a floating point surface format, when supported, so colors brighter than
+    /// white reach HDR displays instead of being clamped
+    pub prefer_hdr: bool,
 
-    camera_fov: f32,
-    camera_near_plane: f32,
-    camera_far_plane: f32,
+    pub camera_fov: f32,
+    pub camera_near_plane: f32,
+    pub camera_far_plane: f32,
+    pub reversed_z: bool,
+    /// Handedness applied to every camera, see [`CoordinateSystem`]
+    pub coordinate_system: CoordinateSystem,
+    /// Falls back to `Depth32Float` when the adapter can't render to and sample the
+    /// format
+    pub depth_format: TextureFormat,
+
+    pub ambient_light: Vec3,
+    pub global_light: GlobalLight,
+    pub fog_settings: FogSettings,
+
+    /// Builds a mip chain for the opaque snapshot after it's captured, so transparent
+    /// passes can sample it blurred
+    pub generate_opaque_mips: bool,
+
+    pub ssao_settings: SsaoSettings,
+    pub bloom_settings: BloomSettings,
+    pub anti_alias_mode: AntiAliasMode,
+    /// Replaces the picture with a visualization of the opaque depth
+    pub debug_view: DebugView,
+    /// Anisotropic filtering of material textures, from 1 (disabled) to 16. Falls back
+    /// to 1 when the adapter doesn't support it.
+    pub max_anisotropy: u16,
+
+    /// Draws the default terrain as lines
+    pub wireframe: bool,
+    /// Push constant bytes renderers require, 0 doesn't require the feature. Up to
+    /// `OPTIONAL_PUSH_CONSTANT_SIZE` bytes are enabled anyway when the adapter supports
+    /// them, letting builtin renderers skip some uniform buffer writes.
+    pub max_push_constant_size: u32,
+    /// Additional features for custom renderers. Device creation fails when some
+    /// required feature isn't supported.
+    pub required_features: Features,
+
+    pub gpu_timing: bool,
 }
 
 impl Default for RenderSettings {
     fn default() -> Self {
         Self {
-            clear_color: Color {
-                r: 0.0,
-                g: 0.0,
-                b: 0.0,
-                a: 1.0,
-            },
+            background_mode: BackgroundMode::Skybox,
+            resize_mode: ResizeMode::Stretch,
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            prefer_hdr: false,
+
             camera_fov: 60.0,
             camera_near_plane: 0.1,
             camera_far_plane: 100.0,
+            reversed_z: false,
+            coordinate_system: CoordinateSystem::LeftHanded,
+            depth_format: TextureFormat::Depth32Float,
+
+            ambient_light: Vec3::new(0.085, 0.245, 0.494),
+            global_light: Default::default(),
+            fog_settings: Default::default(),
+
+            generate_opaque_mips: false,
+
+            ssao_settings: Default::default(),
+            bloom_settings: Default::default(),
+            anti_alias_mode: AntiAliasMode::None,
+            debug_view: DebugView::None,
+
+            max_anisotropy: 1,
+
+            wireframe: false,
+            max_push_constant_size: 0,
+            required_features: Features::empty(),
+
+            gpu_timing: false,
         }
     }
 }
@@ -51,69 +238,210 @@ pub struct RenderManager<'a> {
     surface: Surface<'a>,
     device: Device,
     queue: RefCell<Queue>,
+    // Single sampled, holding the resolved depth with MSAA
     depth_texture: Texture,
     depth_view: TextureView,
+    // Only for depth formats which can't be copied with a transfer
+    depth_copy_pass: Option<DepthCopyPass>,
+    sample_count: u32,
+    msaa_targets: Option<MsaaTargets>,
+    // Clears can't leave the letterbox bars black around a solid color background
+    background_fill_pass: Option<SolidFillPass>,
+    is_minimized: bool,
+    pending_size: Option<PhysicalSize<u32>>,
 
-    camera: Box<RefCell<Camera>>,
+    // The first viewport belongs to the main camera
+    viewports: Vec<ViewportEntry>,
 
     scene_bind_group: Box<RefCell<SceneBindGroup>>,
+    ssao_pass: Option<SsaoPass>,
+    debug_view_pass: Option<DebugViewPass>,
+    bloom_pass: Option<BloomPass>,
+    fxaa_pass: Option<FxaaPass>,
+    opaque_mipmap_generator: Option<MipmapGenerator>,
+    gpu_timer: Option<GpuTimer>,
+    last_frame_stats: RenderStats,
+    max_anisotropy: u16,
 
-    renderers_by_stage: HashMap<RenderStage, Vec<Box<dyn Renderer>>>,
+    renderers_by_stage: HashMap<RenderStage, Vec<RendererEntry>>,
+    next_renderer_id: RendererId,
+
+    #[cfg(all(feature = "hot-reload", debug_assertions))]
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl<'a> RenderManager<'a> {
     pub async fn new(
         settings: &RenderSettings,
         window: Arc<Window>,
-    ) -> Result<RenderManager<'a>, String> {
+    ) -> Result<RenderManager<'a>, TerrainError> {
         let instance: Instance = Instance::new(Default::default());
 
         let (surface_width, surface_height) = window.inner_size().into();
-        let surface = instance
-            .create_surface(window.clone())
-            .map_err(|err| err.to_string())?;
+        let surface = instance.create_surface(window.clone())?;
 
-        let (adapter, device, queue) = Self::create_wgpu_objects(&instance, &surface).await?;
+        let (adapter, device, queue) =
+            Self::create_wgpu_objects(settings, &instance, &surface).await?;
 
-        let surface_config =
-            Self::create_surface_config(&surface, &adapter, surface_width, surface_height);
+        let surface_config = Self::create_surface_config(
+            settings,
+            &surface,
+            &adapter,
+            surface_width,
+            surface_height,
+        );
 
         surface.configure(&device, &surface_config);
 
+        let depth_format = Self::select_depth_format(settings, &adapter, &device);
+        let max_anisotropy = Self::select_max_anisotropy(settings, &adapter);
+        let sample_count = Self::select_sample_count(
+            settings,
+            &adapter,
+            &device,
+            surface_config.format,
+            depth_format,
+        );
+        let depth_copy_pass = (!matches!(
+            depth_format,
+            TextureFormat::Depth32Float | TextureFormat::Depth16Unorm
+        ))
+        .then(|| DepthCopyPass::new(&device, depth_format, 1));
+        let msaa_targets = (sample_count > 1).then(|| {
+            MsaaTargets::new(
+                &device,
+                surface_config.format,
+                depth_format,
+                sample_count,
+                surface_width,
+                surface_height,
+            )
+        });
+
+        let mut depth_usage = TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC;
+        if depth_copy_pass.is_some() {
+            depth_usage |= TextureUsages::TEXTURE_BINDING;
+        }
+        // The depth snapshot is cleared when created, as it's only written where
+        // viewports are
+        let opaque_depth_usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC
+            | TextureUsages::COPY_DST
+            | TextureUsages::RENDER_ATTACHMENT;
+
         let depth_texture = create_texture_2d(
             &device,
-            TextureFormat::Depth32Float,
+            depth_format,
             surface_width,
             surface_height,
-            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            depth_usage,
         );
         let depth_view = depth_texture.create_view(&Default::default());
 
-        let opaque_texture = create_texture_2d(
+        let opaque_mipmap_generator = settings
+            .generate_opaque_mips
+            .then(|| MipmapGenerator::new(&device, surface_config.format));
+
+        // Readable for inspecting the snapshots
+        let mut opaque_usage =
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST;
+        if opaque_mipmap_generator.is_some() {
+            opaque_usage |= TextureUsages::RENDER_ATTACHMENT;
+        }
+        let opaque_texture = create_texture_2d_with_mips(
             &device,
             surface_config.format,
             surface_width,
             surface_height,
-            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            Self::opaque_mip_levels_count(settings, surface_width, surface_height),
+            opaque_usage,
         );
         let opaque_depth_texture = create_texture_2d(
             &device,
-            TextureFormat::Depth32Float,
+            depth_format,
             surface_width,
             surface_height,
-            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            opaque_depth_usage,
+        );
+        clear_depth_texture(
+            &device,
+            &queue,
+            &opaque_depth_texture,
+            far_depth(settings.reversed_z),
         );
 
-        let camera = Camera::new(
+        let background_fill_pass = (matches!(settings.resize_mode, ResizeMode::Letterbox(_))
+            && matches!(settings.background_mode, BackgroundMode::SolidColor(_)))
+        .then(|| SolidFillPass::new(&device, surface_config.format, sample_count));
+
+        let viewport = Viewport::default();
+        let picture_rect = Self::calc_picture_rect(settings, surface_width, surface_height);
+        let mut camera = Camera::new(
             Vec3::ZERO,
             Quat::IDENTITY,
             settings.camera_fov,
-            (surface_width as f32) / (surface_height as f32),
+            viewport.aspect_ratio_in(picture_rect),
             settings.camera_near_plane,
             settings.camera_far_plane,
         );
+        camera.set_reversed_z(settings.reversed_z);
+        camera.set_coordinate_system(settings.coordinate_system);
 
-        let scene_bind_group = SceneBindGroup::new(&device, opaque_texture, opaque_depth_texture);
+        let scene_bind_group = SceneBindGroup::new(
+            &device,
+            SceneUniform::new(
+                camera.view_proj_matrix(),
+                settings.global_light,
+                settings.ambient_light,
+                (&settings.fog_settings).into(),
+            ),
+            opaque_texture,
+            opaque_depth_texture,
+        );
+
+        let ssao_pass = settings.ssao_settings.enabled.then(|| {
+            SsaoPass::new(
+                &settings.ssao_settings,
+                &device,
+                &scene_bind_group,
+                settings.reversed_z,
+                surface_config.format,
+                sample_count,
+                surface_width,
+                surface_height,
+            )
+        });
+        let debug_view_pass = DebugViewPass::new(
+            settings.debug_view,
+            &device,
+            &scene_bind_group,
+            settings.reversed_z,
+            surface_config.format,
+            sample_count,
+        );
+        let bloom_pass = settings.bloom_settings.enabled.then(|| {
+            BloomPass::new(
+                &settings.bloom_settings,
+                &device,
+                surface_config.format,
+                surface_width,
+                surface_height,
+            )
+        });
+
+        let fxaa_pass = (settings.anti_alias_mode == AntiAliasMode::Fxaa).then(|| {
+            FxaaPass::new(
+                &device,
+                surface_config.format,
+                surface_width,
+                surface_height,
+            )
+        });
+
+        let gpu_timer = device
+            .features()
+            .contains(Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(&device, &queue));
 
         Ok(RenderManager {
             settings: Box::new(*settings),
@@ -123,55 +451,584 @@ impl<'a> RenderManager<'a> {
             queue: RefCell::new(queue),
             depth_texture,
             depth_view,
+            depth_copy_pass,
+            sample_count,
+            msaa_targets,
+            background_fill_pass,
+            is_minimized: false,
+            pending_size: None,
 
-            camera: Box::new(RefCell::new(camera)),
+            viewports: vec![(viewport, Box::new(RefCell::new(camera)))],
 
             scene_bind_group: Box::new(RefCell::new(scene_bind_group)),
+            ssao_pass,
+            debug_view_pass,
+            bloom_pass,
+            fxaa_pass,
+            opaque_mipmap_generator,
+            gpu_timer,
+            last_frame_stats: RenderStats::default(),
+            max_anisotropy,
 
             renderers_by_stage: HashMap::from([
                 (RenderStage::OPAQUE, Vec::new()),
                 (RenderStage::TRANSPARENT, Vec::new()),
+                (RenderStage::OVERLAY, Vec::new()),
             ]),
+            next_renderer_id: 0,
+
+            #[cfg(all(feature = "hot-reload", debug_assertions))]
+            shader_watcher: ShaderWatcher::new()
+                .inspect_err(|error| warn!("Failed to watch shaders: {error}"))
+                .ok(),
         })
     }
 
-    pub fn add_renderer(&mut self, renderer: Box<dyn Renderer>) {
+    pub fn add_renderer(&mut self, renderer: Box<dyn Renderer>) -> RendererId {
+        debug_assert_eq!(
+            renderer.sample_count(),
+            self.stage_sample_count(&renderer.stage()),
+            "renderer pipelines must match the sample count of their stage"
+        );
+
         let v = self.renderers_by_stage.get(&renderer.stage());
         if v.is_none() {
             self.renderers_by_stage.insert(renderer.stage(), Vec::new());
         }
 
+        let id = self.next_renderer_id;
+        self.next_renderer_id += 1;
+
+        #[cfg(all(feature = "hot-reload", debug_assertions))]
+        if let Some(shader_watcher) = &mut self.shader_watcher {
+            for path in renderer.shader_paths() {
+                if let Err(error) = shader_watcher.watch(&path) {
+                    warn!("Failed to watch shader {}: {error}", path.display());
+                }
+            }
+        }
+
         self.renderers_by_stage
             .get_mut(&renderer.stage())
             .unwrap()
-            .push(renderer);
+            .push((id, renderer));
+
+        id
+    }
+
+    pub fn last_frame_timings(&self) -> HashMap<RendererId, f32> {
+        self.gpu_timer
+            .as_ref()
+            .map(|gpu_timer| gpu_timer.last_frame_timings().clone())
+            .unwrap_or_default()
+    }
+
+    /// Draws recorded by renderers in the last rendered frame or screenshot
+    pub fn last_frame_stats(&self) -> RenderStats {
+        self.last_frame_stats
     }
 
     pub fn device(&self) -> &Device {
         &self.device
     }
 
+    pub fn queue(&self) -> &RefCell<Queue> {
+        &self.queue
+    }
+
     pub fn surface_format(&self) -> TextureFormat {
         self.surface_config.format
     }
 
+    pub fn is_hdr(&self) -> bool {
+        self.surface_config.format == HDR_SURFACE_FORMAT
+    }
+
+    pub fn generates_opaque_mips(&self) -> bool {
+        self.opaque_mipmap_generator.is_some()
+    }
+
     pub fn depth_texture(&self) -> &Texture {
         &self.depth_texture
     }
 
+    pub fn background_mode(&self) -> BackgroundMode {
+        self.settings.background_mode
+    }
+
+    /// Anisotropy clamp for samplers of material textures. All their filters must be
+    /// linear when it's above 1.
+    pub fn max_anisotropy(&self) -> u16 {
+        self.max_anisotropy
+    }
+
+    /// Push constant bytes available to pipelines, 0 when they aren't supported
+    pub fn push_constant_size(&self) -> u32 {
+        if self.device.features().contains(Features::PUSH_CONSTANTS) {
+            self.device.limits().max_push_constant_size
+        } else {
+            0
+        }
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.settings.reversed_z
+    }
+
+    pub fn coordinate_system(&self) -> CoordinateSystem {
+        self.settings.coordinate_system
+    }
+
+    /// Depth comparison which renderers must use to pass fragments closer to the camera
+    pub fn depth_compare(&self) -> CompareFunction {
+        if self.settings.reversed_z {
+            CompareFunction::Greater
+        } else {
+            CompareFunction::Less
+        }
+    }
+
+    pub fn render_targets(&self) -> RenderTargets {
+        RenderTargets {
+            color_format: self.surface_format(),
+            depth_format: self.depth_texture.format(),
+            depth_compare: self.depth_compare(),
+            sample_count: self.sample_count(),
+        }
+    }
+
+    /// Samples per pixel of the surface and depth texture the opaque and transparent
+    /// stages draw into. Their pipelines must be created with the same count, while
+    /// overlays draw into the resolved picture with a single sample.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Overlays draw into the resolved picture
+    fn stage_sample_count(&self, stage: &RenderStage) -> u32 {
+        match stage {
+            RenderStage::OVERLAY => 1,
+            _ => self.sample_count(),
+        }
+    }
+
+    pub fn multisample_state(&self) -> MultisampleState {
+        MultisampleState {
+            count: self.sample_count(),
+            ..Default::default()
+        }
+    }
+
+    pub fn depth_clear_value(&self) -> f32 {
+        far_depth(self.settings.reversed_z)
+    }
+
     pub fn scene_bind_group(&self) -> &RefCell<SceneBindGroup> {
         self.scene_bind_group.as_ref()
     }
 
+    pub fn global_light(&self) -> GlobalLight {
+        self.scene_bind_group.borrow().uniform().global_light
+    }
+
+    pub fn set_global_light(&self, global_light: GlobalLight) {
+        self.scene_bind_group
+            .borrow_mut()
+            .set_global_light(&self.queue.borrow(), global_light);
+    }
+
+    pub fn ambient_light(&self) -> Vec3 {
+        self.scene_bind_group.borrow().uniform().ambient_light
+    }
+
+    pub fn set_ambient_light(&self, ambient_light: Vec3) {
+        self.scene_bind_group
+            .borrow_mut()
+            .set_ambient_light(&self.queue.borrow(), ambient_light);
+    }
+
     pub fn camera(&self) -> &RefCell<Camera> {
-        &self.camera
+        &self.viewports[0].1
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        self.viewports[0].1.get_mut()
+    }
+
+    /// Replaces the main camera. Its aspect ratio, depth direction and handedness are
+    /// overridden to match the surface and the settings.
+    pub fn set_camera(&mut self, mut camera: Camera) {
+        camera.set_reversed_z(self.settings.reversed_z);
+        camera.set_coordinate_system(self.settings.coordinate_system);
+        *self.camera_mut() = camera;
+        self.update_viewport_aspect_ratio(0);
+    }
+
+    /// Adds a camera rendered into its own part of the surface and returns its index.
+    /// The camera's aspect ratio is kept in sync with the viewport.
+    pub fn add_viewport(&mut self, viewport: Viewport, mut camera: Camera) -> usize {
+        camera.set_reversed_z(self.settings.reversed_z);
+        camera.set_coordinate_system(self.settings.coordinate_system);
+        self.viewports
+            .push((viewport, Box::new(RefCell::new(camera))));
+
+        let index = self.viewports.len() - 1;
+        self.update_viewport_aspect_ratio(index);
+
+        index
+    }
+
+    pub fn viewports_count(&self) -> usize {
+        self.viewports.len()
+    }
+
+    pub fn viewport(&self, index: usize) -> Viewport {
+        self.viewports[index].0
+    }
+
+    pub fn set_viewport(&mut self, index: usize, viewport: Viewport) {
+        self.viewports[index].0 = viewport;
+        self.update_viewport_aspect_ratio(index);
+    }
+
+    pub fn viewport_camera(&self, index: usize) -> &RefCell<Camera> {
+        &self.viewports[index].1
+    }
+
+    /// Area of the surface the viewports are laid out in, as `(x, y, width, height)`
+    /// in pixels. It's smaller than the surface only in the letterbox mode.
+    pub fn picture_rect(&self) -> Vec4 {
+        Self::calc_picture_rect(
+            &self.settings,
+            self.surface_config.width,
+            self.surface_config.height,
+        )
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.is_minimized
     }
 
     pub fn handle_resize(&mut self, size: PhysicalSize<u32>) {
         if size.width == 0 || size.height == 0 {
+            self.is_minimized = true;
             return;
         }
 
+        // Resizes are applied on the next frame, so a burst of events during a drag
+        // recreates the surface textures only once
+        self.is_minimized = false;
+        self.pending_size = Some(size);
+    }
+
+    pub fn reconfigure_surface(&mut self) {
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+
+    pub fn render(&mut self, time_manager: &TimeManager) -> Result<(), TerrainError> {
+        if let Some(size) = self.pending_size.take() {
+            self.resize(size);
+        }
+
+        #[cfg(all(feature = "hot-reload", debug_assertions))]
+        if let Some(shader_watcher) = &self.shader_watcher {
+            let changed_paths = shader_watcher.changed_paths();
+            if !changed_paths.is_empty() {
+                self.reload_renderer_shaders(|paths| {
+                    paths.iter().any(|path| {
+                        path.canonicalize()
+                            .is_ok_and(|path| changed_paths.contains(&path))
+                    })
+                });
+            }
+        }
+
+        let surface = self.surface.get_current_texture()?;
+        self.render_to(&surface.texture, time_manager.time());
+        surface.present();
+
+        Ok(())
+    }
+
+    /// Renders the current frame again into an offscreen texture of the surface size
+    /// and reads it back as RGBA, keeping the background alpha
+    pub fn capture_frame(&mut self) -> Result<RgbaImage, TerrainError> {
+        if let Some(size) = self.pending_size.take() {
+            self.resize(size);
+        }
+
+        let texture = create_texture_2d(
+            &self.device,
+            self.surface_config.format,
+            self.surface_config.width,
+            self.surface_config.height,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+        // Keeps animations on the frame already shown
+        let time = self.scene_bind_group.borrow().uniform().time;
+        self.render_to(&texture, time);
+
+        let mut data = read_texture_2d(&self.device, &self.queue.borrow(), &texture)?;
+        match texture.format() {
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+                data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+            }
+            // Linear HDR values are clamped and encoded to fit the PNG
+            TextureFormat::Rgba16Float => {
+                data = data
+                    .chunks_exact(8)
+                    .flat_map(|pixel| {
+                        let channel = |i: usize| {
+                            f16_to_f32(u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]))
+                        };
+                        let color = linear_to_srgb(
+                            Vec3::new(channel(0), channel(1), channel(2)).max(Vec3::ZERO),
+                        );
+                        color
+                            .extend(channel(3))
+                            .clamp(Vec4::ZERO, Vec4::ONE)
+                            .to_array()
+                            .map(|c| (c * 255.0).round() as u8)
+                    })
+                    .collect();
+            }
+            _ => (),
+        }
+
+        Ok(RgbaImage::from_raw(texture.width(), texture.height(), data).unwrap())
+    }
+
+    /// Recreates pipelines of all renderers from their shader files, logging the
+    /// renderers which failed to compile
+    pub fn reload_shaders(&mut self) {
+        self.reload_renderer_shaders(|paths| !paths.is_empty());
+    }
+
+    fn render_to(&mut self, target: &Texture, time: f32) {
+        debug_assert_eq!(target.sample_count(), 1);
+
+        let surface_view = target.create_view(&Default::default());
+        // The stages before post-processing draw into the multisampled targets
+        let (scene_view, scene_depth_view) = match &self.msaa_targets {
+            Some(msaa_targets) => (&msaa_targets.color_view, &msaa_targets.depth_view),
+            None => (&surface_view, &self.depth_view),
+        };
+
+        let picture_rect = self.picture_rect();
+
+        let mut scene_bind_group = self.scene_bind_group.borrow_mut();
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin_frame(&self.device);
+        }
+
+        let mut uniform = *scene_bind_group.uniform();
+        uniform.time = time;
+
+        let mut stats = RenderStats::default();
+
+        let needs_opaque_snapshot = self.renderers_by_stage[&RenderStage::TRANSPARENT]
+            .iter()
+            .any(|(_, renderer)| renderer.needs_opaque_snapshot());
+
+        // Each viewport is submitted separately, so the scene uniform written before
+        // the submission is the one its commands see
+        let mut is_cleared = false;
+        for (viewport, camera) in &self.viewports {
+            let viewport_rect = viewport.pixel_rect_in(picture_rect);
+            if viewport_rect.z == 0.0 || viewport_rect.w == 0.0 {
+                continue;
+            }
+
+            {
+                let mut camera_ref = camera.borrow_mut();
+                // Screen space effects reconstruct left-handed view positions
+                let proj_matrix =
+                    camera_ref.proj_matrix() * camera_ref.coordinate_system().to_left_handed();
+
+                uniform.view_proj_matrix = camera_ref.view_proj_matrix();
+                uniform.inv_proj_matrix = proj_matrix.inverse();
+                uniform.inv_view_proj_matrix = camera_ref.inv_view_proj_matrix();
+                uniform.camera_dir = camera_ref.look_dir();
+                uniform.camera_pos = camera_ref.position();
+                uniform.camera_near = camera_ref.near_plane();
+                uniform.camera_far = camera_ref.far_plane();
+
+                scene_bind_group.update_uniform(&self.queue.borrow(), &uniform);
+
+                if let Some(ssao_pass) = &mut self.ssao_pass {
+                    ssao_pass.update(&self.queue.borrow(), proj_matrix, viewport_rect);
+                }
+                if let Some(debug_view_pass) = &mut self.debug_view_pass {
+                    debug_view_pass.update(&self.queue.borrow(), viewport_rect);
+                }
+            }
+
+            let encoder = RefCell::new(Some(
+                self.device.create_command_encoder(&Default::default()),
+            ));
+            let wgpu_bind_group = scene_bind_group.bind_group(&self.device);
+
+            let context = RenderingContext::new(
+                camera,
+                scene_view,
+                scene_depth_view,
+                wgpu_bind_group.as_ref(),
+                &self.queue,
+                &encoder,
+                viewport_rect,
+            );
+
+            if !is_cleared {
+                self.clear_surface(picture_rect, &context);
+                is_cleared = true;
+            }
+
+            Self::render_stage(
+                self.renderers_by_stage
+                    .get_mut(&RenderStage::OPAQUE)
+                    .unwrap(),
+                &mut self.gpu_timer,
+                &context,
+            );
+
+            // SSAO and debug views read the depth snapshot as well
+            if needs_opaque_snapshot || self.ssao_pass.is_some() || self.debug_view_pass.is_some() {
+                self.resolve_msaa(&context, &surface_view);
+                match &self.depth_copy_pass {
+                    Some(depth_copy_pass) => depth_copy_pass.render(
+                        &self.device,
+                        &context,
+                        &self.depth_texture,
+                        scene_bind_group.opaque_depth_texture(),
+                    ),
+                    None => copy_textures_2d(
+                        &context,
+                        &self.depth_texture,
+                        scene_bind_group.opaque_depth_texture(),
+                    ),
+                }
+            }
+
+            if let Some(ssao_pass) = &self.ssao_pass {
+                ssao_pass.render(&context);
+            }
+
+            if needs_opaque_snapshot {
+                copy_textures_2d(&context, target, scene_bind_group.opaque_texture());
+                if let Some(generator) = &self.opaque_mipmap_generator {
+                    generator.generate(&self.device, &context, scene_bind_group.opaque_texture());
+                }
+            }
+
+            Self::render_stage(
+                self.renderers_by_stage
+                    .get_mut(&RenderStage::TRANSPARENT)
+                    .unwrap(),
+                &mut self.gpu_timer,
+                &context,
+            );
+            stats += context.stats();
+
+            if let Some(debug_view_pass) = &self.debug_view_pass {
+                debug_view_pass.render(&context);
+            }
+
+            self.queue
+                .borrow()
+                .submit(iter::once(encoder.replace(None).unwrap().finish()));
+        }
+
+        let encoder = RefCell::new(Some(
+            self.device.create_command_encoder(&Default::default()),
+        ));
+        let wgpu_bind_group = scene_bind_group.bind_group(&self.device);
+
+        if !is_cleared {
+            let context = RenderingContext::new(
+                &self.viewports[0].1,
+                scene_view,
+                scene_depth_view,
+                wgpu_bind_group.as_ref(),
+                &self.queue,
+                &encoder,
+                picture_rect,
+            );
+            self.clear_surface(picture_rect, &context);
+        }
+
+        let context = RenderingContext::new(
+            &self.viewports[0].1,
+            &surface_view,
+            &self.depth_view,
+            wgpu_bind_group.as_ref(),
+            &self.queue,
+            &encoder,
+            picture_rect,
+        );
+        self.resolve_msaa(&context, &surface_view);
+
+        if let Some(bloom_pass) = &self.bloom_pass {
+            copy_textures_2d(&context, target, bloom_pass.source_texture());
+            bloom_pass.render(&context);
+        }
+
+        if let Some(fxaa_pass) = &self.fxaa_pass {
+            copy_textures_2d(&context, target, fxaa_pass.source_texture());
+            fxaa_pass.render(&context);
+        }
+
+        Self::render_stage(
+            self.renderers_by_stage
+                .get_mut(&RenderStage::OVERLAY)
+                .unwrap(),
+            &mut self.gpu_timer,
+            &context,
+        );
+
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.resolve(&context);
+        }
+        stats += context.stats();
+
+        self.queue
+            .borrow()
+            .submit(iter::once(encoder.replace(None).unwrap().finish()));
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end_frame();
+        }
+
+        self.last_frame_stats = stats;
+    }
+
+    fn render_stage(
+        renderers: &mut [RendererEntry],
+        gpu_timer: &mut Option<GpuTimer>,
+        context: &RenderingContext,
+    ) {
+        for (id, renderer) in renderers {
+            match gpu_timer {
+                Some(gpu_timer) => gpu_timer.time(context, *id, || renderer.render(context)),
+                None => renderer.render(context),
+            }
+        }
+    }
+
+    fn reload_renderer_shaders(&mut self, filter: impl Fn(&[PathBuf]) -> bool) {
+        for (id, renderer) in self.renderers_by_stage.values_mut().flatten() {
+            if !filter(&renderer.shader_paths()) {
+                continue;
+            }
+
+            match renderer.reload_shaders(&self.device) {
+                Ok(()) => info!("Reloaded shaders of renderer {id}"),
+                Err(error) => warn!("Failed to reload shaders of renderer {id}: {error}"),
+            }
+        }
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>) {
         self.surface_config.width = size.width;
         self.surface_config.height = size.height;
         self.surface.configure(&self.device, &self.surface_config);
@@ -191,11 +1048,12 @@ impl<'a> RenderManager<'a> {
         );
         self.depth_view = self.depth_texture.create_view(&Default::default());
 
-        let opaque_texture = create_texture_2d(
+        let opaque_texture = create_texture_2d_with_mips(
             &self.device,
             self.surface_format(),
             size.width,
             size.height,
+            Self::opaque_mip_levels_count(&self.settings, size.width, size.height),
             scene_bind_group.opaque_texture().usage(),
         );
 
@@ -206,136 +1064,287 @@ impl<'a> RenderManager<'a> {
             size.height,
             scene_bind_group.opaque_depth_texture().usage(),
         );
+        clear_depth_texture(
+            &self.device,
+            &self.queue.borrow(),
+            &opaque_depth_texture,
+            self.depth_clear_value(),
+        );
 
         scene_bind_group.update_textures(opaque_texture, opaque_depth_texture);
 
-        self.camera
+        if let Some(ssao_pass) = &mut self.ssao_pass {
+            ssao_pass.resize(&self.device, size.width, size.height);
+        }
+        if let Some(bloom_pass) = &mut self.bloom_pass {
+            bloom_pass.resize(&self.device, size.width, size.height);
+        }
+        if let Some(fxaa_pass) = &mut self.fxaa_pass {
+            fxaa_pass.resize(&self.device, size.width, size.height);
+        }
+        if let Some(msaa_targets) = &mut self.msaa_targets {
+            msaa_targets.resize(&self.device, size.width, size.height);
+        }
+
+        drop(scene_bind_group);
+
+        for index in 0..self.viewports.len() {
+            self.update_viewport_aspect_ratio(index);
+        }
+    }
+
+    fn update_viewport_aspect_ratio(&self, index: usize) {
+        let (viewport, camera) = &self.viewports[index];
+        camera
             .borrow_mut()
-            .set_aspect_ratio((size.width as f32) / (size.height as f32));
+            .set_aspect_ratio(viewport.aspect_ratio_in(self.picture_rect()));
     }
 
-    pub fn render(&mut self, time_manager: &TimeManager) -> Result<(), String> {
-        let surface = self
-            .surface
-            .get_current_texture()
-            .map_err(|err| err.to_string())?;
-        let surface_view = surface.texture.create_view(&Default::default());
+    fn calc_picture_rect(settings: &RenderSettings, width: u32, height: u32) -> Vec4 {
+        let (w, h) = (width as f32, height as f32);
+        match settings.resize_mode {
+            ResizeMode::Letterbox(aspect_ratio) if aspect_ratio > 0.0 && h > 0.0 => {
+                if w / h > aspect_ratio {
+                    let picture_width = (h * aspect_ratio).round();
+                    Vec4::new(((w - picture_width) * 0.5).floor(), 0.0, picture_width, h)
+                } else {
+                    let picture_height = (w / aspect_ratio).round();
+                    Vec4::new(0.0, ((h - picture_height) * 0.5).floor(), w, picture_height)
+                }
+            }
+            _ => Vec4::new(0.0, 0.0, w, h),
+        }
+    }
 
-        let mut scene_bind_group = self.scene_bind_group.borrow_mut();
+    async fn create_wgpu_objects(
+        settings: &RenderSettings,
+        instance: &Instance,
+        surface: &Surface<'a>,
+    ) -> Result<(Adapter, Device, Queue), TerrainError> {
+        let request_adapter = |force_fallback_adapter| {
+            instance.request_adapter(&RequestAdapterOptions {
+                power_preference: settings.power_preference,
+                force_fallback_adapter,
+                compatible_surface: Some(surface),
+            })
+        };
 
-        let encoder = RefCell::new(Some(
-            self.device.create_command_encoder(&Default::default()),
-        ));
+        let mut adapter = None;
+        if !settings.force_fallback_adapter {
+            adapter = request_adapter(false).await;
+        }
+        if adapter.is_none() {
+            adapter = request_adapter(true).await;
+        }
 
-        {
-            let mut camera_ref = self.camera.borrow_mut();
-            let mut uniform = *scene_bind_group.uniform();
+        let adapter = adapter.ok_or_else(|| {
+            TerrainError::Adapter(
+                instance
+                    .enumerate_adapters(Backends::all())
+                    .iter()
+                    .map(|a| {
+                        let info = a.get_info();
+                        format!("{} ({:?})", info.name, info.backend)
+                    })
+                    .collect(),
+            )
+        })?;
 
-            uniform.view_proj_matrix = camera_ref.view_proj_matrix();
-            uniform.camera_dir = camera_ref.look_dir();
-            uniform.camera_pos = camera_ref.position();
-            uniform.camera_near = camera_ref.near_plane();
-            uniform.camera_far = camera_ref.far_plane();
-            uniform.time += time_manager.delta();
+        let required_features = Self::required_features(settings);
+        let missing_features = required_features - adapter.features();
+        if !missing_features.is_empty() {
+            return Err(TerrainError::MissingFeatures(missing_features));
+        }
 
-            scene_bind_group.update_uniform(&self.queue.borrow(), &uniform);
+        let mut required_limits = Self::required_limits(settings);
+        let mut exceeded_limits = Vec::new();
+        required_limits.check_limits_with_fail_fn(
+            &adapter.limits(),
+            false,
+            |name, requested, allowed| {
+                exceeded_limits.push(format!("{name} ({requested} > {allowed})"))
+            },
+        );
+        if !exceeded_limits.is_empty() {
+            return Err(TerrainError::UnsupportedLimits(exceeded_limits));
         }
 
-        let wgpu_bind_group = scene_bind_group.bind_group(&self.device);
+        if adapter.features().contains(Features::PUSH_CONSTANTS) {
+            required_limits.max_push_constant_size = required_limits
+                .max_push_constant_size
+                .max(OPTIONAL_PUSH_CONSTANT_SIZE.min(adapter.limits().max_push_constant_size));
+        }
 
-        let mut context = RenderingContext::new(
-            &self.camera,
-            &surface_view,
-            &self.depth_view,
-            wgpu_bind_group.as_ref(),
-            &self.queue,
-            &encoder,
-        );
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    required_features: required_features
+                        | (adapter.features() & Self::optional_features(settings)),
+                    required_limits,
+                },
+                None,
+            )
+            .await?;
 
-        self.clear_surface(&context);
+        Ok((adapter, device, queue))
+    }
 
-        for renderer in self
-            .renderers_by_stage
-            .get_mut(&RenderStage::OPAQUE)
-            .unwrap()
-        {
-            renderer.render(&mut context);
+    /// Features the settings can't work without
+    fn required_features(settings: &RenderSettings) -> Features {
+        let mut features = settings.required_features;
+        if settings.wireframe {
+            features |= Features::POLYGON_MODE_LINE;
+        }
+        if settings.max_push_constant_size > 0 {
+            features |= Features::PUSH_CONSTANTS;
         }
 
-        copy_textures_2d(
-            &context,
-            &surface.texture,
-            scene_bind_group.opaque_texture(),
-        );
-        copy_textures_2d(
-            &context,
-            &self.depth_texture,
-            scene_bind_group.opaque_depth_texture(),
-        );
+        features
+    }
 
-        for renderer in self
-            .renderers_by_stage
-            .get_mut(&RenderStage::TRANSPARENT)
-            .unwrap()
+    /// Features enabled only when the adapter supports them, the settings using them
+    /// fall back otherwise
+    fn optional_features(settings: &RenderSettings) -> Features {
+        let mut features = settings.depth_format.required_features() | Features::PUSH_CONSTANTS;
+        if settings.gpu_timing {
+            features |= Features::TIMESTAMP_QUERY;
+        }
+
+        features
+    }
+
+    fn required_limits(settings: &RenderSettings) -> Limits {
+        Limits {
+            max_push_constant_size: settings.max_push_constant_size,
+            ..Default::default()
+        }
+    }
+
+    fn select_max_anisotropy(settings: &RenderSettings, adapter: &Adapter) -> u16 {
+        let max_anisotropy = settings.max_anisotropy.clamp(1, 16);
+        if max_anisotropy > 1
+            && !adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(DownlevelFlags::ANISOTROPIC_FILTERING)
         {
-            renderer.render(&mut context);
+            warn!("Anisotropic filtering isn't supported, falling back to 1");
+            return 1;
         }
 
-        self.queue
-            .borrow()
-            .submit(iter::once(encoder.replace(None).unwrap().finish()));
+        max_anisotropy
+    }
 
-        surface.present();
+    fn select_sample_count(
+        settings: &RenderSettings,
+        adapter: &Adapter,
+        device: &Device,
+        color_format: TextureFormat,
+        depth_format: TextureFormat,
+    ) -> u32 {
+        let AntiAliasMode::Msaa(sample_count) = settings.anti_alias_mode else {
+            return 1;
+        };
 
-        Ok(())
+        let is_supported = [color_format, depth_format].iter().all(|format| {
+            let features = if device
+                .features()
+                .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            {
+                adapter.get_texture_format_features(*format)
+            } else {
+                format.guaranteed_format_features(device.features())
+            };
+            features.flags.sample_count_supported(sample_count)
+        });
+
+        if is_supported {
+            sample_count
+        } else {
+            warn!("{sample_count}x MSAA isn't supported, falling back to no anti-aliasing");
+            1
+        }
     }
 
-    async fn create_wgpu_objects(
-        instance: &Instance,
-        surface: &Surface<'a>,
-    ) -> Result<(Adapter, Device, Queue), String> {
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .await
-            .ok_or("Requested adapter was None")?;
+    fn select_depth_format(
+        settings: &RenderSettings,
+        adapter: &Adapter,
+        device: &Device,
+    ) -> TextureFormat {
+        let format = settings.depth_format;
+        let is_supported = format.has_depth_aspect()
+            && device.features().contains(format.required_features())
+            && adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+                .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING);
 
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    ..Default::default()
-                },
-                None,
-            )
-            .await
-            .map_err(|err| err.to_string())?;
+        if is_supported {
+            format
+        } else {
+            warn!("Depth format {format:?} isn't supported, falling back to Depth32Float");
+            TextureFormat::Depth32Float
+        }
+    }
 
-        Ok((adapter, device, queue))
+    fn opaque_mip_levels_count(settings: &RenderSettings, width: u32, height: u32) -> u32 {
+        if settings.generate_opaque_mips {
+            mip_levels_count(width, height)
+        } else {
+            1
+        }
     }
 
     fn create_surface_config(
+        settings: &RenderSettings,
         surface: &Surface,
         adapter: &Adapter,
         width: u32,
         height: u32,
     ) -> SurfaceConfiguration {
         let surface_capabilities = surface.get_capabilities(adapter);
-        let surface_format = surface_capabilities
+        let srgb_format = surface_capabilities
             .formats
             .iter()
             .copied()
-            .filter(|f| f.is_srgb())
-            .next()
+            .find(|f| f.is_srgb())
             .unwrap_or(surface_capabilities.formats[0]);
+        // Shaders output linear colors, which the float format keeps as is, like sRGB
+        // formats do before encoding
+        let surface_format = if !settings.prefer_hdr {
+            srgb_format
+        } else if surface_capabilities.formats.contains(&HDR_SURFACE_FORMAT) {
+            HDR_SURFACE_FORMAT
+        } else {
+            warn!("HDR surface format isn't supported, falling back to {srgb_format:?}");
+            srgb_format
+        };
         let present_mode = surface_capabilities
             .present_modes
             .iter()
             .copied()
-            .filter(|m| *m == PresentMode::AutoVsync)
-            .next()
+            .find(|m| *m == PresentMode::AutoVsync)
             .unwrap_or(surface_capabilities.present_modes[0]);
+        let alpha_mode = if settings.background_mode == BackgroundMode::Transparent {
+            // Opaque alpha modes would ignore the background alpha
+            surface_capabilities
+                .alpha_modes
+                .iter()
+                .copied()
+                .find(|m| *m != CompositeAlphaMode::Opaque && *m != CompositeAlphaMode::Auto)
+                .unwrap_or(surface_capabilities.alpha_modes[0])
+        } else if surface_format == HDR_SURFACE_FORMAT {
+            // Compositors may treat the extended range differently with blending modes
+            surface_capabilities
+                .alpha_modes
+                .iter()
+                .copied()
+                .find(|m| *m == CompositeAlphaMode::Opaque)
+                .unwrap_or(surface_capabilities.alpha_modes[0])
+        } else {
+            surface_capabilities.alpha_modes[0]
+        };
 
         SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
@@ -344,12 +1353,46 @@ impl<'a> RenderManager<'a> {
             height,
             present_mode,
             desired_maximum_frame_latency: 0,
-            alpha_mode: surface_capabilities.alpha_modes[0],
+            alpha_mode,
             view_formats: vec![],
         }
     }
 
-    fn clear_surface(&self, context: &RenderingContext) {
+    /// Resolves the multisampled color into `target_view` and depth into the depth
+    /// texture, does nothing without MSAA
+    fn resolve_msaa(&self, context: &RenderingContext, target_view: &TextureView) {
+        let Some(msaa_targets) = &self.msaa_targets else {
+            return;
+        };
+
+        context
+            .encoder()
+            .borrow_mut()
+            .as_mut()
+            .unwrap()
+            .begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &msaa_targets.color_view,
+                    resolve_target: Some(target_view),
+                    ops: Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+        msaa_targets.depth_resolve_pass.render(
+            &self.device,
+            context,
+            &msaa_targets.depth_texture,
+            &self.depth_texture,
+        );
+    }
+
+    fn clear_surface(&self, picture_rect: Vec4, context: &RenderingContext) {
+        let settings = &self.settings;
+
         context
             .encoder()
             .borrow_mut()
@@ -357,22 +1400,159 @@ impl<'a> RenderManager<'a> {
             .unwrap()
             .begin_render_pass(&RenderPassDescriptor {
                 color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &context.surface_view(),
+                    view: context.surface_view(),
                     resolve_target: None,
                     ops: Operations {
-                        load: wgpu::LoadOp::Clear(self.settings.clear_color),
+                        load: wgpu::LoadOp::Clear(match settings.background_mode {
+                            BackgroundMode::Skybox => Color::BLACK,
+                            BackgroundMode::SolidColor(_)
+                                if self.background_fill_pass.is_some() =>
+                            {
+                                Color::BLACK
+                            }
+                            BackgroundMode::SolidColor(color) => color,
+                            BackgroundMode::Transparent => Color::TRANSPARENT,
+                        }),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &self.depth_view,
+                    view: context.depth_view(),
                     depth_ops: Some(Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(far_depth(settings.reversed_z)),
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
                 }),
                 ..Default::default()
             });
+
+        if let (Some(fill_pass), BackgroundMode::SolidColor(color)) =
+            (&self.background_fill_pass, settings.background_mode)
+        {
+            fill_pass.render(context, picture_rect, color);
+        }
+    }
+}
+
+/// Depth value of the far plane, which depth buffers are cleared to
+fn far_depth(reversed_z: bool) -> f32 {
+    if reversed_z {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::Perlin;
+    use winit::{
+        event_loop::{EventLoop, EventLoopBuilder},
+        window::WindowBuilder,
+    };
+
+    use crate::{
+        render::{
+            mesh_renderer::{MeshRenderer, MeshRendererSettings},
+            water_renderer::WaterRenderer,
+        },
+        utils::terrain_generator::{generate_terrain_mesh, TerrainSettings},
+    };
+
+    use super::*;
+
+    const SIZE: u32 = 64;
+
+    /// The event loop has to outlive the window of the render manager
+    fn render_manager(settings: &RenderSettings) -> (EventLoop<()>, RenderManager<'static>) {
+        let mut builder = EventLoopBuilder::new();
+        #[cfg(all(unix, not(target_os = "macos")))]
+        winit::platform::x11::EventLoopBuilderExtX11::with_any_thread(&mut builder, true);
+        let event_loop = builder.build().expect("no display available");
+        let window = WindowBuilder::new()
+            .with_inner_size(PhysicalSize::new(SIZE, SIZE))
+            .with_visible(false)
+            .build(&event_loop)
+            .unwrap();
+
+        let render_manager = pollster::block_on(RenderManager::new(settings, Arc::new(window)))
+            .expect("no adapter available");
+
+        (event_loop, render_manager)
+    }
+
+    /// Terrain in the lower half of the picture, with the sky above it
+    fn add_terrain(render_manager: &mut RenderManager) {
+        let (mesh, bounds) = generate_terrain_mesh(
+            render_manager.device(),
+            &TerrainSettings::<Perlin>::default(),
+        )
+        .unwrap();
+        let renderer =
+            MeshRenderer::new(mesh, &MeshRendererSettings::default(), render_manager).unwrap();
+        render_manager.add_renderer(Box::new(renderer));
+
+        let camera = render_manager.camera_mut();
+        camera.set_position(bounds.center() + Vec3::new(0.0, bounds.size().y, -bounds.size().z));
+        camera.set_look_at(bounds.center());
+    }
+
+    fn read_opaque_snapshots(render_manager: &RenderManager) -> (Vec<u8>, Vec<u8>) {
+        let scene_bind_group = render_manager.scene_bind_group().borrow();
+        let queue = render_manager.queue().borrow();
+
+        (
+            read_texture_2d(
+                render_manager.device(),
+                &queue,
+                scene_bind_group.opaque_texture(),
+            )
+            .unwrap(),
+            read_texture_2d(
+                render_manager.device(),
+                &queue,
+                scene_bind_group.opaque_depth_texture(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    #[ignore = "needs a display and an adapter"]
+    fn opaque_snapshots_of_static_scene_are_identical() {
+        let (_event_loop, mut render_manager) = render_manager(&Default::default());
+        add_terrain(&mut render_manager);
+        // Samples the snapshots, so they're captured
+        let water = WaterRenderer::new(&Default::default(), &render_manager).unwrap();
+        render_manager.add_renderer(Box::new(water));
+
+        render_manager.capture_frame().unwrap();
+        let (first_color, first_depth) = read_opaque_snapshots(&render_manager);
+        render_manager.capture_frame().unwrap();
+        let (second_color, second_depth) = read_opaque_snapshots(&render_manager);
+
+        // The terrain is in the depth snapshot, which isn't left at the far plane
+        let far_depth = far_depth(false).to_le_bytes();
+        assert!(first_depth.chunks_exact(4).any(|depth| depth != far_depth));
+
+        assert!(first_color == second_color);
+        assert!(first_depth == second_depth);
+    }
+
+    #[test]
+    #[ignore = "needs a display and an adapter"]
+    fn transparent_background_keeps_sky_alpha() {
+        let (_event_loop, mut render_manager) = render_manager(&RenderSettings {
+            background_mode: BackgroundMode::Transparent,
+            ..Default::default()
+        });
+        add_terrain(&mut render_manager);
+
+        let image = render_manager.capture_frame().unwrap();
+
+        assert_eq!(image.dimensions(), (SIZE, SIZE));
+        assert_eq!(image.get_pixel(SIZE / 2, 0)[3], 0);
+        assert_eq!(image.get_pixel(SIZE / 2, SIZE - 1)[3], 255);
     }
 }
@@ -1,6 +1,17 @@
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    ops::AddAssign,
+    path::PathBuf,
+    rc::Rc,
+};
 
-use wgpu::{BindGroup, CommandEncoder, Queue, TextureView};
+use glam::Vec4;
+use wgpu::{
+    BindGroup, CommandEncoder, CompareFunction, Device, Queue, RenderPass, TextureFormat,
+    TextureView,
+};
+
+use crate::core::error::TerrainError;
 
 use super::scene::Camera;
 
@@ -8,6 +19,91 @@ use super::scene::Camera;
 pub enum RenderStage {
     OPAQUE,
     TRANSPARENT,
+    // Drawn over the final image, after post-processing
+    OVERLAY,
+}
+
+/// Rectangle of the surface a camera is rendered into, in fractions of the surface size
+#[derive(Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Viewport {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns `(x, y, width, height)` of the viewport in pixels
+    pub fn pixel_rect(&self, surface_width: u32, surface_height: u32) -> Vec4 {
+        self.pixel_rect_in(Vec4::new(
+            0.0,
+            0.0,
+            surface_width as f32,
+            surface_height as f32,
+        ))
+    }
+
+    /// Same as `pixel_rect`, taking the fractions of the `(x, y, width, height)` area
+    /// instead of the whole surface
+    pub fn pixel_rect_in(&self, area: Vec4) -> Vec4 {
+        let (w, h) = (area.z, area.w);
+        let x = (self.x * w).round().clamp(0.0, w);
+        let y = (self.y * h).round().clamp(0.0, h);
+
+        Vec4::new(
+            area.x + x,
+            area.y + y,
+            (self.width * w).round().clamp(0.0, w - x),
+            (self.height * h).round().clamp(0.0, h - y),
+        )
+    }
+
+    pub fn aspect_ratio(&self, surface_width: u32, surface_height: u32) -> f32 {
+        let rect = self.pixel_rect(surface_width, surface_height);
+        rect.z / rect.w.max(1.0)
+    }
+
+    pub fn aspect_ratio_in(&self, area: Vec4) -> f32 {
+        let rect = self.pixel_rect_in(area);
+        rect.z / rect.w.max(1.0)
+    }
+}
+
+/// Work recorded by renderers while drawing a frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    /// Vertices processed, i.e. indices of indexed draws, over all instances
+    pub vertices: u64,
+}
+
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, other: RenderStats) {
+        self.draw_calls += other.draw_calls;
+        self.triangles += other.triangles;
+        self.vertices += other.vertices;
+    }
 }
 
 pub struct RenderingContext<'a> {
@@ -17,6 +113,8 @@ pub struct RenderingContext<'a> {
     scene_bind_group: &'a BindGroup,
     queue: &'a RefCell<Queue>,
     encoder: &'a RefCell<Option<CommandEncoder>>,
+    viewport_rect: Vec4,
+    stats: Cell<RenderStats>,
 }
 
 impl<'a> RenderingContext<'a> {
@@ -27,6 +125,7 @@ impl<'a> RenderingContext<'a> {
         scene_bind_group: &'a BindGroup,
         queue: &'a RefCell<Queue>,
         encoder: &'a RefCell<Option<CommandEncoder>>,
+        viewport_rect: Vec4,
     ) -> RenderingContext<'a> {
         RenderingContext {
             camera,
@@ -35,23 +134,25 @@ impl<'a> RenderingContext<'a> {
             scene_bind_group,
             queue,
             encoder,
+            viewport_rect,
+            stats: Cell::default(),
         }
     }
 
     pub fn camera(&self) -> &RefCell<Camera> {
-        &self.camera
+        self.camera
     }
 
     pub fn surface_view(&self) -> &TextureView {
-        &self.surface_view
+        self.surface_view
     }
 
     pub fn depth_view(&self) -> &TextureView {
-        &self.depth_view
+        self.depth_view
     }
 
     pub fn scene_bind_group(&self) -> &BindGroup {
-        &self.scene_bind_group
+        self.scene_bind_group
     }
 
     pub fn queue(&self) -> &RefCell<Queue> {
@@ -61,10 +162,97 @@ impl<'a> RenderingContext<'a> {
     pub fn encoder(&self) -> &RefCell<Option<CommandEncoder>> {
         self.encoder
     }
+
+    /// Viewport of the current camera as `(x, y, width, height)` in pixels
+    pub fn viewport_rect(&self) -> Vec4 {
+        self.viewport_rect
+    }
+
+    /// Draws recorded with this context
+    pub fn stats(&self) -> RenderStats {
+        self.stats.get()
+    }
+
+    /// Adds a draw call to the frame statistics. Renderers should record every draw
+    /// they issue, with the totals over all instances.
+    pub fn record_draw(&self, vertices: u32, triangles: u32) {
+        let mut stats = self.stats.get();
+        stats.draw_calls += 1;
+        stats.vertices += vertices as u64;
+        stats.triangles += triangles as u64;
+        self.stats.set(stats);
+    }
+
+    /// Restricts the pass to the viewport of the current camera. Renderers must call
+    /// this on every pass that draws into the surface.
+    pub fn apply_viewport(&self, pass: &mut RenderPass) {
+        let rect = self.viewport_rect;
+        pass.set_viewport(rect.x, rect.y, rect.z, rect.w, 0.0, 1.0);
+    }
+}
+
+/// Attachments renderers draw into, kept by renderers which recreate their pipelines
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderTargets {
+    pub color_format: TextureFormat,
+    pub depth_format: TextureFormat,
+    pub depth_compare: CompareFunction,
+    pub sample_count: u32,
 }
 
 pub trait Renderer {
     fn render(&mut self, context: &RenderingContext);
 
     fn stage(&self) -> RenderStage;
+
+    /// Sample count of the pipelines drawing into the surface, which must match
+    /// `RenderManager::sample_count` for the opaque and transparent stages and be 1 for
+    /// overlays
+    fn sample_count(&self) -> u32;
+
+    /// Whether the renderer samples the opaque color and depth snapshots of the scene
+    /// bind group. They're only captured when a transparent renderer needs them.
+    fn needs_opaque_snapshot(&self) -> bool {
+        false
+    }
+
+    /// Files the renderer loaded its shaders from
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Compiles the shaders again and recreates the pipelines using them. The old
+    /// pipelines are kept when this fails.
+    fn reload_shaders(&mut self, _device: &Device) -> Result<(), TerrainError> {
+        Ok(())
+    }
+}
+
+impl<T> Renderer for Rc<RefCell<T>>
+where
+    T: Renderer,
+{
+    fn render(&mut self, context: &RenderingContext) {
+        self.borrow_mut().render(context);
+    }
+
+    fn stage(&self) -> RenderStage {
+        self.borrow().stage()
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.borrow().sample_count()
+    }
+
+    fn needs_opaque_snapshot(&self) -> bool {
+        self.borrow().needs_opaque_snapshot()
+    }
+
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        self.borrow().shader_paths()
+    }
+
+    fn reload_shaders(&mut self, device: &Device) -> Result<(), TerrainError> {
+        self.borrow_mut().reload_shaders(device)
+    }
 }
@@ -1,17 +1,22 @@
-use std::rc::Rc;
+use std::{mem::offset_of, rc::Rc};
 
 use bytemuck::{bytes_of, Pod, Zeroable};
-use glam::{Mat4, Quat, Vec2, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
     BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
     BufferBinding, BufferBindingType, BufferUsages, Device, FilterMode, Queue, Sampler,
-    SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureSampleType, TextureView,
-    TextureViewDimension,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureAspect, TextureSampleType,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
 };
 
-use super::bind_group::BindGroupHelper;
+use crate::utils::srgb_to_linear;
+
+use super::{
+    bind_group::BindGroupHelper,
+    coordinate_system::{CoordinateSystem, WORLD_UP},
+};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -43,10 +48,118 @@ impl Default for GlobalLight {
     }
 }
 
+/// Vertical sky gradient drawn by the skybox, shared with the fog
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct SkyUniform {
+    pub sky_color: Vec3,
+    pub scattering: f32,
+    pub horizon_color: Vec3,
+    _padding1: f32,
+    pub bottom_color: Vec3,
+    _padding2: f32,
+}
+
+impl SkyUniform {
+    pub fn new(sky_color: Vec3, horizon_color: Vec3, bottom_color: Vec3, scattering: f32) -> Self {
+        SkyUniform {
+            sky_color,
+            scattering,
+            horizon_color,
+            bottom_color,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SkyUniform {
+    fn default() -> Self {
+        Self {
+            sky_color: srgb_to_linear(Vec3::new(0.17, 0.49, 0.988)),
+            scattering: 0.45,
+            horizon_color: srgb_to_linear(Vec3::new(0.72, 0.9, 0.96)),
+            _padding1: Default::default(),
+            bottom_color: srgb_to_linear(Vec3::new(0.15, 0.47, 0.76)),
+            _padding2: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FogMode {
+    Disabled,
+    Color(Vec3),
+    /// Fog takes the sky gradient color in the view direction, so distant geometry
+    /// fades into the sky behind it. Cubemap skyboxes still provide the gradient
+    /// from their settings.
+    AerialPerspective,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    /// Distance from the camera where fog starts
+    pub start: f32,
+    /// Distance from the camera where fog hides everything
+    pub end: f32,
+    /// Fog has full density below this height
+    pub height: f32,
+    /// How fast fog thins out above `height`, 0 keeps it uniform
+    pub height_falloff: f32,
+    pub linear_colors: bool,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Disabled,
+            start: 20.0,
+            end: 90.0,
+            height: 0.0,
+            height_falloff: 0.3,
+            linear_colors: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub struct FogUniform {
+    pub color: Vec3,
+    pub mode: u32,
+    pub start: f32,
+    pub end: f32,
+    pub height: f32,
+    pub height_falloff: f32,
+}
+
+impl From<&FogSettings> for FogUniform {
+    fn from(settings: &FogSettings) -> Self {
+        let (mode, color) = match settings.mode {
+            FogMode::Disabled => (0, Vec3::ZERO),
+            FogMode::Color(color) if settings.linear_colors => (1, srgb_to_linear(color)),
+            FogMode::Color(color) => (1, color),
+            FogMode::AerialPerspective => (2, Vec3::ZERO),
+        };
+
+        FogUniform {
+            color,
+            mode,
+            start: settings.start,
+            end: settings.end,
+            height: settings.height,
+            height_falloff: settings.height_falloff,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct SceneUniform {
     pub view_proj_matrix: Mat4,
+    /// Inverse of the left-handed projection, giving view positions with +Z forward
+    pub inv_proj_matrix: Mat4,
+    pub inv_view_proj_matrix: Mat4,
     pub camera_dir: Vec3,
     _padding1: f32,
     pub camera_pos: Vec3,
@@ -57,6 +170,8 @@ pub struct SceneUniform {
     pub global_light: GlobalLight,
     pub ambient_light: Vec3,
     pub time: f32,
+    pub sky: SkyUniform,
+    pub fog: FogUniform,
 }
 
 impl SceneUniform {
@@ -64,11 +179,13 @@ impl SceneUniform {
         view_proj_matrix: Mat4,
         global_light: GlobalLight,
         ambient_light: Vec3,
+        fog: FogUniform,
     ) -> SceneUniform {
         SceneUniform {
             view_proj_matrix,
             global_light,
             ambient_light,
+            fog,
             ..Default::default()
         }
     }
@@ -78,6 +195,8 @@ impl Default for SceneUniform {
     fn default() -> Self {
         Self {
             view_proj_matrix: Default::default(),
+            inv_proj_matrix: Default::default(),
+            inv_view_proj_matrix: Default::default(),
             camera_dir: Default::default(),
             _padding1: Default::default(),
             camera_pos: Default::default(),
@@ -88,6 +207,8 @@ impl Default for SceneUniform {
             global_light: Default::default(),
             ambient_light: Vec3::new(0.085, 0.245, 0.494),
             time: 0.0,
+            sky: Default::default(),
+            fog: Default::default(),
         }
     }
 }
@@ -108,10 +229,11 @@ pub struct SceneBindGroup {
 impl SceneBindGroup {
     pub fn new(
         device: &Device,
+        uniform: SceneUniform,
         opaque_texture: Texture,
         opaque_depth_texture: Texture,
     ) -> SceneBindGroup {
-        let uniform = Box::new(SceneUniform::default());
+        let uniform = Box::new(uniform);
 
         let buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: None,
@@ -126,9 +248,9 @@ impl SceneBindGroup {
             address_mode_w: AddressMode::ClampToEdge,
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Nearest,
-            lod_min_clamp: 1.0,
-            lod_max_clamp: 1.0,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
             compare: None,
             anisotropy_clamp: 1,
             border_color: None,
@@ -139,7 +261,7 @@ impl SceneBindGroup {
             opaque_sampler,
             opaque_view: opaque_texture.create_view(&Default::default()),
             opaque_texture,
-            opaque_depth_view: opaque_depth_texture.create_view(&Default::default()),
+            opaque_depth_view: Self::create_depth_view(&opaque_depth_texture),
             opaque_depth_texture,
 
             buffer,
@@ -157,6 +279,25 @@ impl SceneBindGroup {
         queue.write_buffer(&self.buffer, 0, bytes_of(uniform));
     }
 
+    /// Uploads only the light instead of the whole uniform
+    pub fn set_global_light(&mut self, queue: &Queue, global_light: GlobalLight) {
+        self.uniform.global_light = global_light;
+        queue.write_buffer(
+            &self.buffer,
+            offset_of!(SceneUniform, global_light) as u64,
+            bytes_of(&global_light),
+        );
+    }
+
+    pub fn set_ambient_light(&mut self, queue: &Queue, ambient_light: Vec3) {
+        self.uniform.ambient_light = ambient_light;
+        queue.write_buffer(
+            &self.buffer,
+            offset_of!(SceneUniform, ambient_light) as u64,
+            bytes_of(&ambient_light),
+        );
+    }
+
     pub fn opaque_texture(&self) -> &Texture {
         &self.opaque_texture
     }
@@ -177,11 +318,19 @@ impl SceneBindGroup {
         self.opaque_texture = opaque_texture;
         self.opaque_view = self.opaque_texture.create_view(&Default::default());
         self.opaque_depth_texture = opaque_depth_texture;
-        self.opaque_depth_view = self.opaque_depth_texture.create_view(&Default::default());
+        self.opaque_depth_view = Self::create_depth_view(&self.opaque_depth_texture);
 
         self.bind_group = None;
     }
 
+    // Formats with stencil can only be sampled through their depth aspect
+    fn create_depth_view(texture: &Texture) -> TextureView {
+        texture.create_view(&TextureViewDescriptor {
+            aspect: TextureAspect::DepthOnly,
+            ..Default::default()
+        })
+    }
+
     fn create_bind_group_layout(device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
@@ -271,6 +420,66 @@ impl BindGroupHelper for SceneBindGroup {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Transform {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Matrix transforming normals, which stays correct under non-uniform scale
+    pub fn normal_matrix(&self) -> Mat4 {
+        self.matrix().inverse().transpose()
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+/// Plane satisfying `normal.dot(p) + d = 0`
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn from_vec4(v: Vec4) -> Plane {
+        let normal = v.truncate();
+        let length = normal.length();
+
+        Plane {
+            normal: normal / length,
+            d: v.w / length,
+        }
+    }
+
+    /// Signed distance to the point, positive on the side the normal points to
+    pub fn distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     position: Vec3,
     rotation: Quat,
@@ -278,11 +487,16 @@ pub struct Camera {
     aspect_ratio: f32,
     near_plane: f32,
     far_plane: f32,
+    reversed_z: bool,
+    coordinate_system: CoordinateSystem,
     is_dirty: bool,
     look_dir: Vec3,
     view_matrix: Mat4,
     proj_matrix: Mat4,
     view_proj_matrix: Mat4,
+    inv_proj_matrix: Mat4,
+    inv_view_proj_matrix: Mat4,
+    frustum_planes: [Plane; 6],
 }
 
 impl Camera {
@@ -301,14 +515,40 @@ impl Camera {
             aspect_ratio,
             near_plane,
             far_plane,
+            reversed_z: false,
+            coordinate_system: CoordinateSystem::LeftHanded,
             is_dirty: true,
             look_dir: Default::default(),
             view_matrix: Default::default(),
             proj_matrix: Default::default(),
             view_proj_matrix: Default::default(),
+            inv_proj_matrix: Default::default(),
+            inv_view_proj_matrix: Default::default(),
+            frustum_planes: Default::default(),
         }
     }
 
+    /// The rotation is found for left-handed coordinates, as cameras are created
+    /// with them
+    pub fn look_at(
+        position: Vec3,
+        target: Vec3,
+        up: Vec3,
+        fov: f32,
+        aspect_ratio: f32,
+        near_plane: f32,
+        far_plane: f32,
+    ) -> Camera {
+        Camera::new(
+            position,
+            look_rotation(target - position, up, CoordinateSystem::LeftHanded),
+            fov,
+            aspect_ratio,
+            near_plane,
+            far_plane,
+        )
+    }
+
     pub fn position(&self) -> Vec3 {
         self.position
     }
@@ -327,6 +567,15 @@ impl Camera {
         self.is_dirty = true;
     }
 
+    /// Rotates the camera to face `target`, keeping the world up axis up
+    pub fn set_look_at(&mut self, target: Vec3) {
+        self.set_rotation(look_rotation(
+            target - self.position,
+            WORLD_UP,
+            self.coordinate_system,
+        ));
+    }
+
     pub fn fov(&self) -> f32 {
         self.fov
     }
@@ -363,6 +612,28 @@ impl Camera {
         self.is_dirty = true;
     }
 
+    pub fn reversed_z(&self) -> bool {
+        self.reversed_z
+    }
+
+    /// Maps the near plane to depth 1 and the far plane to depth 0, which spreads
+    /// floating point precision more evenly over the view distance
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.reversed_z = reversed_z;
+        self.is_dirty = true;
+    }
+
+    pub fn coordinate_system(&self) -> CoordinateSystem {
+        self.coordinate_system
+    }
+
+    /// Changes the projection handedness and the local axis the camera looks along,
+    /// keeping the rotation
+    pub fn set_coordinate_system(&mut self, coordinate_system: CoordinateSystem) {
+        self.coordinate_system = coordinate_system;
+        self.is_dirty = true;
+    }
+
     pub fn look_dir(&mut self) -> Vec3 {
         if self.is_dirty {
             self.update_values();
@@ -399,15 +670,96 @@ impl Camera {
         self.view_proj_matrix
     }
 
+    pub fn inv_proj_matrix(&mut self) -> Mat4 {
+        if self.is_dirty {
+            self.update_values();
+            self.is_dirty = false;
+        }
+
+        self.inv_proj_matrix
+    }
+
+    /// Maps clip space positions back to the world, e.g. for reconstructing them
+    /// from depth
+    pub fn inv_view_proj_matrix(&mut self) -> Mat4 {
+        if self.is_dirty {
+            self.update_values();
+            self.is_dirty = false;
+        }
+
+        self.inv_view_proj_matrix
+    }
+
+    /// Left, right, bottom, top, near and far planes of the view frustum with normals
+    /// pointing inside
+    pub fn frustum_planes(&mut self) -> [Plane; 6] {
+        if self.is_dirty {
+            self.update_values();
+            self.is_dirty = false;
+        }
+
+        self.frustum_planes
+    }
+
+    /// Whether the box lies entirely inside the frustum
+    pub fn contains_aabb(&mut self, min: Vec3, max: Vec3) -> bool {
+        self.frustum_planes().iter().all(|plane| {
+            let p = Vec3::select(plane.normal.cmpge(Vec3::ZERO), min, max);
+            plane.distance(p) >= 0.0
+        })
+    }
+
+    /// Whether the box may be at least partially visible. Conservative, so boxes near
+    /// frustum corners can be reported as intersecting while lying outside.
+    pub fn intersects_aabb(&mut self, min: Vec3, max: Vec3) -> bool {
+        self.frustum_planes().iter().all(|plane| {
+            let p = Vec3::select(plane.normal.cmpge(Vec3::ZERO), max, min);
+            plane.distance(p) >= 0.0
+        })
+    }
+
     fn update_values(&mut self) {
-        self.look_dir = self.rotation.mul_vec3(Vec3::Z);
+        self.look_dir = self
+            .rotation
+            .mul_vec3(self.coordinate_system.camera_forward());
         self.view_matrix = Mat4::from_rotation_translation(self.rotation, self.position).inverse();
-        self.proj_matrix = Mat4::perspective_lh(
+        let (near_plane, far_plane) = if self.reversed_z {
+            (self.far_plane, self.near_plane)
+        } else {
+            (self.near_plane, self.far_plane)
+        };
+        self.proj_matrix = self.coordinate_system.perspective(
             self.fov.to_radians(),
             self.aspect_ratio,
-            self.near_plane,
-            self.far_plane,
+            near_plane,
+            far_plane,
         );
         self.view_proj_matrix = self.proj_matrix * self.view_matrix;
+        self.inv_proj_matrix = self.proj_matrix.inverse();
+        self.inv_view_proj_matrix = self.view_proj_matrix.inverse();
+
+        // Gribb-Hartmann extraction for the [0, 1] clip space depth range
+        let m = self.view_proj_matrix;
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        self.frustum_planes =
+            [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(Plane::from_vec4);
     }
 }
+
+/// Rotation that maps the camera forward axis onto `dir` and keeps the up axis as
+/// close to `up` as possible
+fn look_rotation(dir: Vec3, up: Vec3, coordinate_system: CoordinateSystem) -> Quat {
+    let local_forward = coordinate_system.camera_forward();
+    let forward = dir.try_normalize().unwrap_or(local_forward);
+
+    let (right, back) = match coordinate_system {
+        CoordinateSystem::LeftHanded => (up.cross(forward), forward),
+        CoordinateSystem::RightHanded => (forward.cross(up), -forward),
+    };
+    let right = right
+        .try_normalize()
+        .unwrap_or_else(|| forward.any_orthonormal_vector());
+    let up = back.cross(right);
+
+    Quat::from_mat3(&Mat3::from_cols(right, up, back))
+}
@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+
+use bytemuck::bytes_of;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, Device, IndexFormat, LoadOp, Operations, PipelineLayout,
+    PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, ShaderModule, StoreOp,
+};
+
+use crate::{
+    core::error::TerrainError,
+    utils::{create_uniform_init, dynamic_uniform::DynamicUniformBuffer},
+};
+
+use super::{
+    material::{Material, VertexColorMaterial},
+    mesh::Mesh,
+    mesh_renderer::{MeshRenderMode, MeshRenderer, MeshRendererSettings, ModelUniform},
+    render_manager::RenderManager,
+    renderer::{RenderStage, RenderTargets, Renderer, RenderingContext},
+    scene::Transform,
+    shader::catch_validation_errors,
+};
+
+pub type ObjectId = usize;
+
+struct SceneObject {
+    id: ObjectId,
+    mesh: Mesh,
+    transform: Transform,
+    is_transform_dirty: bool,
+}
+
+/// Draws many meshes with one pipeline and material, like `MeshRenderer` does for a
+/// single mesh. Each object has its own transform, stored in a slot of a shared
+/// uniform buffer.
+pub struct SceneObjects {
+    _shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+    topology: PrimitiveTopology,
+    settings: MeshRendererSettings,
+    targets: RenderTargets,
+
+    _uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    material: Box<dyn Material>,
+
+    objects: Vec<SceneObject>,
+    next_object_id: ObjectId,
+    model_uniforms: DynamicUniformBuffer<ModelUniform>,
+}
+
+impl SceneObjects {
+    /// Shades the objects with a `VertexColorMaterial`. All meshes must have the
+    /// `topology`.
+    pub fn new(
+        topology: PrimitiveTopology,
+        settings: &MeshRendererSettings,
+        render_manager: &RenderManager,
+    ) -> Result<SceneObjects, TerrainError> {
+        Self::with_material(
+            topology,
+            Box::new(VertexColorMaterial::new(render_manager)),
+            settings,
+            render_manager,
+        )
+    }
+
+    pub fn with_material(
+        topology: PrimitiveTopology,
+        material: Box<dyn Material>,
+        settings: &MeshRendererSettings,
+        render_manager: &RenderManager,
+    ) -> Result<SceneObjects, TerrainError> {
+        let device = render_manager.device();
+
+        let shader = MeshRenderer::create_shader(device, &settings.shader, material.as_ref())?;
+
+        let (uniform_buffer, bind_group_layout, bind_group) =
+            create_uniform_init(&MeshRenderer::mesh_uniform(settings), device);
+        let model_uniforms = DynamicUniformBuffer::new(device, 16);
+
+        let pipeline_layout = MeshRenderer::create_pipeline_layout(
+            render_manager,
+            &bind_group_layout,
+            material.as_ref(),
+            model_uniforms.bind_group_layout(),
+        );
+
+        let targets = render_manager.render_targets();
+        let pipeline = catch_validation_errors(device, "scene objects", || {
+            MeshRenderer::create_pipeline(
+                device,
+                &shader,
+                &pipeline_layout,
+                topology,
+                settings,
+                &targets,
+            )
+        })?;
+
+        Ok(SceneObjects {
+            _shader: shader,
+            pipeline_layout,
+            pipeline,
+            topology,
+            settings: settings.clone(),
+            targets,
+
+            _uniform_buffer: uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+
+            material,
+
+            objects: Vec::new(),
+            next_object_id: 0,
+            model_uniforms,
+        })
+    }
+
+    pub fn add(
+        &mut self,
+        mesh: Mesh,
+        transform: Transform,
+        render_manager: &RenderManager,
+    ) -> Result<ObjectId, TerrainError> {
+        if mesh.topology() != self.topology {
+            return Err(TerrainError::MismatchedTopology(
+                mesh.topology(),
+                self.topology,
+            ));
+        }
+
+        let capacity = self.objects.len() as u32 + 1;
+        if self
+            .model_uniforms
+            .reserve(render_manager.device(), capacity)
+        {
+            self.mark_all_dirty();
+        }
+
+        let id = self.next_object_id;
+        self.next_object_id += 1;
+
+        self.objects.push(SceneObject {
+            id,
+            mesh,
+            transform,
+            is_transform_dirty: true,
+        });
+
+        Ok(id)
+    }
+
+    /// Gives the mesh of the removed object back
+    pub fn remove(&mut self, id: ObjectId) -> Option<Mesh> {
+        let index = self.objects.iter().position(|object| object.id == id)?;
+        let object = self.objects.remove(index);
+
+        // Following objects move to the previous slots
+        self.mark_all_dirty();
+
+        Some(object.mesh)
+    }
+
+    pub fn transform(&self, id: ObjectId) -> Option<Transform> {
+        self.object(id).map(|object| object.transform)
+    }
+
+    /// Returns `false` when there's no object with the id
+    pub fn set_transform(&mut self, id: ObjectId, transform: Transform) -> bool {
+        let Some(object) = self.objects.iter_mut().find(|object| object.id == id) else {
+            return false;
+        };
+
+        object.transform = transform;
+        object.is_transform_dirty = true;
+
+        true
+    }
+
+    pub fn mesh(&self, id: ObjectId) -> Option<&Mesh> {
+        self.object(id).map(|object| &object.mesh)
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    fn object(&self, id: ObjectId) -> Option<&SceneObject> {
+        self.objects.iter().find(|object| object.id == id)
+    }
+
+    fn mark_all_dirty(&mut self) {
+        for object in &mut self.objects {
+            object.is_transform_dirty = true;
+        }
+    }
+
+    fn slot_offset(&self, index: usize) -> u32 {
+        (index as u64 * self.model_uniforms.stride()) as u32
+    }
+}
+
+impl Renderer for SceneObjects {
+    fn render(&mut self, context: &RenderingContext) {
+        if self.objects.is_empty() {
+            return;
+        }
+
+        for index in 0..self.objects.len() {
+            if !self.objects[index].is_transform_dirty {
+                continue;
+            }
+
+            let offset = self.slot_offset(index);
+            let object = &mut self.objects[index];
+            context.queue().borrow().write_buffer(
+                self.model_uniforms.buffer(),
+                offset as u64,
+                bytes_of(&MeshRenderer::model_uniform(&object.transform)),
+            );
+            object.is_transform_dirty = false;
+        }
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_bind_group(2, self.material.bind_group(), &[]);
+
+        for (index, object) in self.objects.iter().enumerate() {
+            pass.set_bind_group(
+                3,
+                self.model_uniforms.bind_group(),
+                &[self.slot_offset(index)],
+            );
+            pass.set_vertex_buffer(0, object.mesh.vertex_buffer().slice(..));
+            pass.set_index_buffer(object.mesh.index_buffer().slice(..), IndexFormat::Uint32);
+
+            pass.draw_indexed(0..(object.mesh.indices().len() as u32), 0, 0..1);
+            context.record_draw(
+                object.mesh.indices().len() as u32,
+                object.mesh.triangles_count(),
+            );
+        }
+    }
+
+    fn stage(&self) -> RenderStage {
+        match self.settings.render_mode {
+            MeshRenderMode::AlphaBlend => RenderStage::TRANSPARENT,
+            _ => RenderStage::OPAQUE,
+        }
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.targets.sample_count
+    }
+
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        self.settings
+            .shader
+            .path()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(self.material.shader_paths())
+            .collect()
+    }
+
+    fn reload_shaders(&mut self, device: &Device) -> Result<(), TerrainError> {
+        let shader =
+            MeshRenderer::create_shader(device, &self.settings.shader, self.material.as_ref())?;
+        self.pipeline = catch_validation_errors(device, "scene objects", || {
+            MeshRenderer::create_pipeline(
+                device,
+                &shader,
+                &self.pipeline_layout,
+                self.topology,
+                &self.settings,
+                &self.targets,
+            )
+        })?;
+        self._shader = shader;
+
+        Ok(())
+    }
+}
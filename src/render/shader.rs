@@ -0,0 +1,187 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+use std::{
+    collections::HashSet,
+    sync::mpsc::{self, Receiver},
+};
+
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use wgpu::{naga::ShaderStage, Device, ErrorFilter, ShaderModule, ShaderModuleDescriptor};
+
+use crate::core::error::TerrainError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShaderLanguage {
+    Wgsl,
+    /// A GLSL module holds a single stage, with the `main` entry point
+    Glsl(ShaderStage),
+}
+
+impl ShaderLanguage {
+    /// Tells the language by the extension: `.wgsl`, or `.vert`, `.frag` and `.comp`
+    /// for GLSL, which may also be followed by `.glsl`
+    pub fn from_path(path: &Path) -> Option<ShaderLanguage> {
+        let mut extension = path.extension()?.to_str()?;
+        if extension == "glsl" {
+            extension = Path::new(path.file_stem()?).extension()?.to_str()?;
+        }
+
+        match extension {
+            "wgsl" => Some(ShaderLanguage::Wgsl),
+            "vert" => Some(ShaderLanguage::Glsl(ShaderStage::Vertex)),
+            "frag" => Some(ShaderLanguage::Glsl(ShaderStage::Fragment)),
+            "comp" => Some(ShaderLanguage::Glsl(ShaderStage::Compute)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ShaderLanguage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShaderLanguage::Wgsl => "WGSL",
+            ShaderLanguage::Glsl(_) => "GLSL",
+        })
+    }
+}
+
+/// Where a renderer takes its shader from. Custom shaders must keep the entry points
+/// and bindings of the builtin one, so renderers drawing with both stages of a single
+/// module only accept WGSL.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub enum ShaderSource {
+    #[default]
+    Builtin,
+    /// File read when the renderer is created, its language is told by the extension
+    Path(PathBuf),
+    /// WGSL code
+    Source(String),
+}
+
+impl ShaderSource {
+    pub fn load(&self, builtin: &'static str) -> Result<Cow<'static, str>, TerrainError> {
+        Ok(match self {
+            ShaderSource::Builtin => Cow::Borrowed(builtin),
+            ShaderSource::Path(path) => Cow::Owned(fs::read_to_string(path)?),
+            ShaderSource::Source(source) => Cow::Owned(source.clone()),
+        })
+    }
+
+    pub fn language(&self) -> Result<ShaderLanguage, TerrainError> {
+        match self {
+            ShaderSource::Path(path) => ShaderLanguage::from_path(path)
+                .ok_or_else(|| TerrainError::UnknownShaderLanguage(path.clone())),
+            _ => Ok(ShaderLanguage::Wgsl),
+        }
+    }
+
+    /// File the shader is read from, the only source which can change at runtime
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ShaderSource::Path(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Compiles the shader, returning validation errors instead of panicking on them
+pub fn create_shader_module(
+    device: &Device,
+    name: &str,
+    source: &ShaderSource,
+    builtin: &'static str,
+) -> Result<ShaderModule, TerrainError> {
+    let language = source.language()?;
+    let code = source.load(builtin)?;
+    let source = match language {
+        ShaderLanguage::Wgsl => wgpu::ShaderSource::Wgsl(code),
+        ShaderLanguage::Glsl(stage) => wgpu::ShaderSource::Glsl {
+            shader: code,
+            stage,
+            defines: Default::default(),
+        },
+    };
+
+    catch_validation_errors(device, &format!("{name} ({language})"), || {
+        device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(name),
+            source,
+        })
+    })
+}
+
+/// Runs `f` reporting validation errors of the shader `name` instead of panicking.
+/// Custom shaders may also break pipeline creation, e.g. with a missing entry point.
+pub fn catch_validation_errors<T>(
+    device: &Device,
+    name: &str,
+    f: impl FnOnce() -> T,
+) -> Result<T, TerrainError> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let result = f();
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(TerrainError::ShaderCompilation(
+            name.to_owned(),
+            error.to_string(),
+        )),
+        None => Ok(result),
+    }
+}
+
+/// Collects changes of shader files
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+pub struct ShaderWatcher {
+    watcher: RecommendedWatcher,
+    watched_dirs: HashSet<PathBuf>,
+    receiver: Receiver<PathBuf>,
+}
+
+#[cfg(all(feature = "hot-reload", debug_assertions))]
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<ShaderWatcher> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+        })?;
+
+        Ok(ShaderWatcher {
+            watcher,
+            watched_dirs: HashSet::new(),
+            receiver,
+        })
+    }
+
+    pub fn watch(&mut self, path: &Path) -> notify::Result<()> {
+        let path = path.canonicalize().map_err(notify::Error::io)?;
+        // Editors often save by replacing the file, which drops watches of the file
+        // itself, so its directory is watched instead
+        let Some(dir) = path.parent() else {
+            return Ok(());
+        };
+        if !self.watched_dirs.contains(dir) {
+            self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            self.watched_dirs.insert(dir.to_path_buf());
+        }
+
+        Ok(())
+    }
+
+    /// Canonical paths of the files changed since the last call
+    pub fn changed_paths(&self) -> HashSet<PathBuf> {
+        self.receiver.try_iter().collect()
+    }
+}
@@ -1,39 +1,65 @@
+use std::{mem::size_of, rc::Rc};
+
 use bytemuck::{bytes_of, Pod, Zeroable};
 use glam::{Mat3, Mat4, Vec3};
+use image::DynamicImage;
 use once_cell::sync::Lazy;
 use wgpu::{
-    include_wgsl, BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState, ColorWrites,
-    Face, FragmentState, FrontFace, IndexFormat, LoadOp, MultisampleState, Operations,
-    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    ColorTargetState, ColorWrites, Device, Extent3d, Face, FilterMode, FragmentState,
+    ImageCopyTexture, ImageDataLayout, IndexFormat, LoadOp, Operations, Origin3d, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveTopology, PushConstantRange, Queue,
     RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
-    ShaderModule, StoreOp, VertexState,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StoreOp, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension, VertexState,
 };
 
-use crate::utils::create_uniform_init;
+use crate::{
+    core::error::TerrainError,
+    utils::{pipeline::primitive_state, srgb_to_linear},
+};
 
 use super::{
-    bind_group::BindGroupHelper,
+    bind_group::{BindGroupHelper, UniformBindGroup},
     mesh::Mesh,
     render_manager::RenderManager,
     renderer::{RenderStage, Renderer, RenderingContext},
+    scene::SkyUniform,
     vertex::Vertex,
 };
 
-#[derive(Clone, Copy)]
+/// Cubemap faces are ordered +X, -X, +Y, -Y, +Z, -Z and must all be squares of the same size.
+#[derive(Clone, Default)]
+pub enum SkyboxSource {
+    #[default]
+    Procedural,
+    Cubemap(Box<[DynamicImage; 6]>),
+}
+
+#[derive(Clone)]
 pub struct SkyboxRendererSettings {
+    pub source: SkyboxSource,
     pub sky_color: Vec3,
     pub horizon_color: Vec3,
     pub bottom_color: Vec3,
     pub scattering: f32,
+    /// The gradient colors are given in sRGB and converted to linear, which the
+    /// gradient is interpolated in. Otherwise they're taken as linear already.
+    pub linear_colors: bool,
 }
 
 impl Default for SkyboxRendererSettings {
     fn default() -> Self {
         Self {
+            source: SkyboxSource::Procedural,
             sky_color: Vec3::new(0.17, 0.49, 0.988),
             horizon_color: Vec3::new(0.72, 0.9, 0.96),
             bottom_color: Vec3::new(0.15, 0.47, 0.76),
             scattering: 0.45,
+            linear_colors: true,
         }
     }
 }
@@ -42,12 +68,15 @@ impl Default for SkyboxRendererSettings {
 #[derive(Clone, Copy, Default, Pod, Zeroable)]
 struct SkyboxUniform {
     pub transform_matrix: Mat4,
-    pub sky_color: Vec3,
-    _padding1: f32,
-    pub horizon_color: Vec3,
-    _padding2: f32,
-    pub bottom_color: Vec3,
-    pub scattering: f32,
+    pub use_cubemap: u32,
+    _padding: [u32; 3],
+}
+
+/// How the skybox uniform reaches the shader. It's rewritten every frame, so push
+/// constants are preferred when supported.
+enum SkyboxUniformSource {
+    PushConstants(SkyboxUniform),
+    BindGroup(UniformBindGroup<SkyboxUniform>, Rc<BindGroup>),
 }
 
 static SKYBOX_VERTICES: Lazy<[Vertex; 24]> = Lazy::new(|| {
@@ -85,7 +114,7 @@ static SKYBOX_VERTICES: Lazy<[Vertex; 24]> = Lazy::new(|| {
     ]
 });
 
-static SKYBOX_INDICES: [u16; 36] = [
+static SKYBOX_INDICES: [u32; 36] = [
     0, 1, 2, 2, 3, 0, // Front face
     4, 5, 6, 6, 7, 4, // Left face
     8, 9, 10, 10, 11, 8, // Back face
@@ -101,41 +130,107 @@ pub struct SkyboxRenderer {
 
     skybox_mesh: Mesh,
 
-    uniform: Box<SkyboxUniform>,
-    uniform_buffer: Buffer,
-    _uniform_bind_group_layout: BindGroupLayout,
-    uniform_bind_group: BindGroup,
+    linear_colors: bool,
+    uniform: SkyboxUniformSource,
+
+    _cubemap_texture: Texture,
+    _cubemap_sampler: Sampler,
+    _cubemap_bind_group_layout: BindGroupLayout,
+    cubemap_bind_group: BindGroup,
+    sample_count: u32,
 }
 
 impl SkyboxRenderer {
     pub fn new(
         settings: &SkyboxRendererSettings,
         render_manager: &RenderManager,
-    ) -> SkyboxRenderer {
+    ) -> Result<SkyboxRenderer, TerrainError> {
         let device = render_manager.device();
 
-        let uniform = Box::new(SkyboxUniform {
-            sky_color: settings.sky_color,
-            horizon_color: settings.horizon_color,
-            bottom_color: settings.bottom_color,
-            scattering: settings.scattering,
+        let cubemap_texture = match &settings.source {
+            SkyboxSource::Procedural => {
+                create_cubemap_texture(device, &render_manager.queue().borrow(), 1, |_| vec![0; 4])
+            }
+            SkyboxSource::Cubemap(faces) => {
+                let size = validate_cubemap_faces(faces.as_ref())?;
+                create_cubemap_texture(device, &render_manager.queue().borrow(), size, |i| {
+                    faces[i].to_rgba8().into_raw()
+                })
+            }
+        };
+        let cubemap_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
             ..Default::default()
         });
+        let (cubemap_bind_group_layout, cubemap_bind_group) =
+            create_cubemap_bind_group(device, &cubemap_texture, &cubemap_sampler);
 
-        let (uniform_buffer, uniform_bind_group_layout, uniform_bind_group) =
-            create_uniform_init(uniform.as_ref(), device);
+        let convert_color = |color: Vec3| {
+            if settings.linear_colors {
+                srgb_to_linear(color)
+            } else {
+                color
+            }
+        };
 
-        let shader = device.create_shader_module(include_wgsl!("../shaders/skybox.wgsl"));
-
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[
-                render_manager.scene_bind_group().borrow().layout(),
-                &uniform_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
+        // The gradient lives in the scene uniform, so fog can match the sky
+        update_sky(render_manager, |sky| {
+            *sky = SkyUniform::new(
+                convert_color(settings.sky_color),
+                convert_color(settings.horizon_color),
+                convert_color(settings.bottom_color),
+                settings.scattering,
+            )
         });
 
+        let skybox_uniform = SkyboxUniform {
+            use_cubemap: matches!(settings.source, SkyboxSource::Cubemap(_)) as u32,
+            ..Default::default()
+        };
+        let uniform = if render_manager.push_constant_size() as usize >= size_of::<SkyboxUniform>()
+        {
+            SkyboxUniformSource::PushConstants(skybox_uniform)
+        } else {
+            let mut uniform = UniformBindGroup::new(device, skybox_uniform);
+            let bind_group = uniform.bind_group(device);
+            SkyboxUniformSource::BindGroup(uniform, bind_group)
+        };
+
+        let scene_bind_group = render_manager.scene_bind_group().borrow();
+        let (shader, pipeline_layout) = match &uniform {
+            SkyboxUniformSource::PushConstants(_) => (
+                device.create_shader_module(ShaderModuleDescriptor {
+                    label: None,
+                    source: ShaderSource::Wgsl(push_constant_shader_source().into()),
+                }),
+                device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[scene_bind_group.layout(), &cubemap_bind_group_layout],
+                    push_constant_ranges: &[PushConstantRange {
+                        stages: ShaderStages::VERTEX_FRAGMENT,
+                        range: 0..size_of::<SkyboxUniform>() as u32,
+                    }],
+                }),
+            ),
+            SkyboxUniformSource::BindGroup(uniform, _) => (
+                device.create_shader_module(include_wgsl!("../shaders/skybox.wgsl")),
+                device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[
+                        scene_bind_group.layout(),
+                        uniform.layout(),
+                        &cubemap_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                }),
+            ),
+        };
+        drop(scene_bind_group);
+
         let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
@@ -144,21 +239,14 @@ impl SkyboxRenderer {
                 entry_point: "vs_main",
                 buffers: &[Vertex::buffer_layout()],
             },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
+            primitive: primitive_state(
+                PrimitiveTopology::TriangleList,
+                Some(Face::Back),
+                PolygonMode::Fill,
+                render_manager.coordinate_system().front_face(),
+            ),
             depth_stencil: None,
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+            multisample: render_manager.multisample_state(),
             fragment: Some(FragmentState {
                 module: &shader,
                 entry_point: "fs_main",
@@ -171,17 +259,44 @@ impl SkyboxRenderer {
             multiview: None,
         });
 
-        SkyboxRenderer {
+        Ok(SkyboxRenderer {
             _shader: shader,
             _pipeline_layout: pipeline_layout,
             pipeline,
 
             skybox_mesh: Mesh::from_slices(device, SKYBOX_VERTICES.as_ref(), &SKYBOX_INDICES),
 
+            linear_colors: settings.linear_colors,
             uniform,
-            uniform_buffer,
-            _uniform_bind_group_layout: uniform_bind_group_layout,
-            uniform_bind_group,
+
+            _cubemap_texture: cubemap_texture,
+            _cubemap_sampler: cubemap_sampler,
+            _cubemap_bind_group_layout: cubemap_bind_group_layout,
+            cubemap_bind_group,
+            sample_count: render_manager.sample_count(),
+        })
+    }
+
+    pub fn set_sky_color(&self, sky_color: Vec3, render_manager: &RenderManager) {
+        let sky_color = self.convert_color(sky_color);
+        update_sky(render_manager, |sky| sky.sky_color = sky_color);
+    }
+
+    pub fn set_horizon_color(&self, horizon_color: Vec3, render_manager: &RenderManager) {
+        let horizon_color = self.convert_color(horizon_color);
+        update_sky(render_manager, |sky| sky.horizon_color = horizon_color);
+    }
+
+    pub fn set_bottom_color(&self, bottom_color: Vec3, render_manager: &RenderManager) {
+        let bottom_color = self.convert_color(bottom_color);
+        update_sky(render_manager, |sky| sky.bottom_color = bottom_color);
+    }
+
+    fn convert_color(&self, color: Vec3) -> Vec3 {
+        if self.linear_colors {
+            srgb_to_linear(color)
+        } else {
+            color
         }
     }
 }
@@ -189,14 +304,18 @@ impl SkyboxRenderer {
 impl Renderer for SkyboxRenderer {
     fn render(&mut self, context: &RenderingContext) {
         let mut camera = context.camera().borrow_mut();
-        self.uniform.transform_matrix =
+        let transform_matrix =
             camera.proj_matrix() * Mat4::from_mat3(Mat3::from_mat4(camera.view_matrix()));
-
-        context.queue().borrow_mut().write_buffer(
-            &self.uniform_buffer,
-            0,
-            bytes_of(self.uniform.as_ref()),
-        );
+        match &mut self.uniform {
+            SkyboxUniformSource::PushConstants(uniform) => {
+                uniform.transform_matrix = transform_matrix;
+            }
+            SkyboxUniformSource::BindGroup(uniform_bind_group, _) => {
+                let mut uniform = *uniform_bind_group.uniform();
+                uniform.transform_matrix = transform_matrix;
+                uniform_bind_group.update(&context.queue().borrow(), &uniform);
+            }
+        }
 
         let mut encoder_ref = context.encoder().borrow_mut();
         let encoder = encoder_ref.as_mut().unwrap();
@@ -216,19 +335,182 @@ impl Renderer for SkyboxRenderer {
             occlusion_query_set: None,
         });
 
+        context.apply_viewport(&mut pass);
         pass.set_pipeline(&self.pipeline);
         pass.set_vertex_buffer(0, self.skybox_mesh.vertex_buffer().slice(..));
         pass.set_index_buffer(
             self.skybox_mesh.index_buffer().slice(..),
-            IndexFormat::Uint16,
+            IndexFormat::Uint32,
         );
         pass.set_bind_group(0, context.scene_bind_group(), &[]);
-        pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        match &self.uniform {
+            SkyboxUniformSource::PushConstants(uniform) => {
+                pass.set_push_constants(ShaderStages::VERTEX_FRAGMENT, 0, bytes_of(uniform));
+                pass.set_bind_group(1, &self.cubemap_bind_group, &[]);
+            }
+            SkyboxUniformSource::BindGroup(_, bind_group) => {
+                pass.set_bind_group(1, bind_group, &[]);
+                pass.set_bind_group(2, &self.cubemap_bind_group, &[]);
+            }
+        }
 
         pass.draw_indexed(0..(self.skybox_mesh.indices().len() as u32), 0, 0..1);
+        context.record_draw(
+            self.skybox_mesh.indices().len() as u32,
+            self.skybox_mesh.triangles_count(),
+        );
     }
 
     fn stage(&self) -> RenderStage {
         RenderStage::OPAQUE
     }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
+
+/// The shader with the skybox uniform declared as a push constant, which moves the
+/// cubemap to group 1
+fn push_constant_shader_source() -> String {
+    include_str!("../shaders/skybox.wgsl")
+        .replace(
+            "@group(1) @binding(0)\nvar<uniform> skybox",
+            "var<push_constant> skybox",
+        )
+        .replace("@group(2)", "@group(1)")
+}
+
+fn validate_cubemap_faces(faces: &[DynamicImage; 6]) -> Result<u32, TerrainError> {
+    let size = faces[0].width();
+
+    for (i, face) in faces.iter().enumerate() {
+        if face.width() != face.height() {
+            return Err(TerrainError::InvalidCubemap(format!(
+                "face {} is {}x{}, but must be square",
+                i,
+                face.width(),
+                face.height()
+            )));
+        }
+        if face.width() != size {
+            return Err(TerrainError::InvalidCubemap(format!(
+                "face {} is {}x{}, but face 0 is {}x{}",
+                i,
+                face.width(),
+                face.height(),
+                size,
+                size
+            )));
+        }
+    }
+
+    Ok(size)
+}
+
+fn create_cubemap_texture(
+    device: &Device,
+    queue: &Queue,
+    size: u32,
+    face_data: impl Fn(usize) -> Vec<u8>,
+) -> Texture {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for i in 0..6 {
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: i as u32,
+                },
+                aspect: TextureAspect::All,
+            },
+            &face_data(i),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    texture
+}
+
+fn create_cubemap_bind_group(
+    device: &Device,
+    texture: &Texture,
+    sampler: &Sampler,
+) -> (BindGroupLayout, BindGroup) {
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let view = texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..Default::default()
+    });
+
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Sampler(sampler),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&view),
+            },
+        ],
+    });
+
+    (layout, bind_group)
+}
+
+fn update_sky(render_manager: &RenderManager, f: impl FnOnce(&mut SkyUniform)) {
+    let mut scene_bind_group = render_manager.scene_bind_group().borrow_mut();
+    let mut uniform = *scene_bind_group.uniform();
+    f(&mut uniform.sky);
+    scene_bind_group.update_uniform(&render_manager.queue().borrow(), &uniform);
 }
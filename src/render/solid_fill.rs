@@ -0,0 +1,99 @@
+use glam::Vec4;
+use wgpu::{
+    include_wgsl, BlendComponent, BlendFactor, BlendOperation, BlendState, Color, ColorTargetState,
+    ColorWrites, Device, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StoreOp, TextureFormat, VertexState,
+};
+
+use super::renderer::RenderingContext;
+
+/// Fills a rectangle of the surface with a color, unlike clears which always cover
+/// the whole texture
+pub struct SolidFillPass {
+    _shader: ShaderModule,
+    pipeline: RenderPipeline,
+}
+
+impl SolidFillPass {
+    pub fn new(device: &Device, format: TextureFormat, sample_count: u32) -> SolidFillPass {
+        let shader = device.create_shader_module(include_wgsl!("../shaders/solid_fill.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        // Replaces the target with the blend constant
+        let component = BlendComponent {
+            src_factor: BlendFactor::Constant,
+            dst_factor: BlendFactor::Zero,
+            operation: BlendOperation::Add,
+        };
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState {
+                        color: component,
+                        alpha: component,
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        SolidFillPass {
+            _shader: shader,
+            pipeline,
+        }
+    }
+
+    /// Fills the `(x, y, width, height)` rectangle in pixels
+    pub fn render(&self, context: &RenderingContext, rect: Vec4, color: Color) {
+        if rect.z <= 0.0 || rect.w <= 0.0 {
+            return;
+        }
+
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_viewport(rect.x, rect.y, rect.z, rect.w, 0.0, 1.0);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_blend_constant(color);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,300 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, ColorTargetState, ColorWrites, DepthBiasState, DepthStencilState, Device, Face,
+    FilterMode, FragmentState, IndexFormat, LoadOp, Operations, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderStages, StencilFaceState, StencilState, StoreOp, Texture, TextureSampleType, TextureView,
+    TextureViewDimension, VertexState,
+};
+
+use crate::utils::create_uniform_init;
+
+use super::{
+    bind_group::BindGroupHelper,
+    mesh::Mesh,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+/// Four albedo layers blended by the RGBA weights of `splatmap`.
+///
+/// The splatmap is sampled with the mesh UVs, so the terrain should be generated
+/// with a `uv_scale` mapping it onto `[0, 1]`. Each layer repeats `tiling[i]` times
+/// across the splatmap.
+pub struct SplatTerrainMaterial {
+    pub layers: [Texture; 4],
+    pub splatmap: Texture,
+    pub tiling: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct SplatUniform {
+    pub tiling: [f32; 4],
+}
+
+pub struct SplatTerrainRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+    mesh: Mesh,
+
+    _uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    _material: SplatTerrainMaterial,
+    _layer_sampler: Sampler,
+    _splatmap_sampler: Sampler,
+    _material_bind_group_layout: BindGroupLayout,
+    material_bind_group: BindGroup,
+    sample_count: u32,
+}
+
+impl SplatTerrainRenderer {
+    pub fn new(
+        mesh: Mesh,
+        material: SplatTerrainMaterial,
+        render_manager: &RenderManager,
+    ) -> SplatTerrainRenderer {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/splat_terrain.wgsl"));
+
+        let uniform = SplatUniform {
+            tiling: material.tiling,
+        };
+        let (uniform_buffer, bind_group_layout, bind_group) = create_uniform_init(&uniform, device);
+
+        let layer_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy_clamp: render_manager.max_anisotropy(),
+            ..Default::default()
+        });
+        let splatmap_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let material_bind_group_layout = Self::create_material_bind_group_layout(device);
+        let material_bind_group = Self::create_material_bind_group(
+            device,
+            &material_bind_group_layout,
+            &material,
+            &layer_sampler,
+            &splatmap_sampler,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                render_manager.scene_bind_group().borrow().layout(),
+                &bind_group_layout,
+                &material_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: mesh.topology(),
+                strip_index_format: mesh.strip_index_format(),
+                front_face: render_manager.coordinate_system().front_face(),
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        SplatTerrainRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+            mesh,
+
+            _uniform_buffer: uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+
+            _material: material,
+            _layer_sampler: layer_sampler,
+            _splatmap_sampler: splatmap_sampler,
+            _material_bind_group_layout: material_bind_group_layout,
+            material_bind_group,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+
+    fn create_material_bind_group_layout(device: &Device) -> BindGroupLayout {
+        let sampler_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                sampler_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                texture_entry(6),
+            ],
+        })
+    }
+
+    fn create_material_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        material: &SplatTerrainMaterial,
+        layer_sampler: &Sampler,
+        splatmap_sampler: &Sampler,
+    ) -> BindGroup {
+        let splatmap_view = material.splatmap.create_view(&Default::default());
+        let layer_views: [TextureView; 4] =
+            [0, 1, 2, 3].map(|i| material.layers[i].create_view(&Default::default()));
+
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(layer_sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(splatmap_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&splatmap_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&layer_views[0]),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&layer_views[1]),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&layer_views[2]),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&layer_views[3]),
+                },
+            ],
+        })
+    }
+}
+
+impl Renderer for SplatTerrainRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
+        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint32);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_bind_group(2, &self.material_bind_group, &[]);
+
+        pass.draw_indexed(0..(self.mesh.indices().len() as u32), 0, 0..1);
+        context.record_draw(
+            self.mesh.indices().len() as u32,
+            self.mesh.triangles_count(),
+        );
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
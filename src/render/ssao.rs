@@ -0,0 +1,278 @@
+use bytemuck::{bytes_of, Pod, Zeroable};
+use glam::{Mat4, Vec4};
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, Buffer, Color, ColorTargetState, ColorWrites, Device,
+    FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderStages, StoreOp, Texture, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::utils::{create_texture_2d, create_uniform_init};
+
+use super::{bind_group::BindGroupHelper, renderer::RenderingContext, scene::SceneBindGroup};
+
+const AO_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+#[derive(Clone, Copy)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    pub radius: f32,
+    pub bias: f32,
+    pub intensity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct SsaoUniform {
+    pub proj_matrix: Mat4,
+    pub radius: f32,
+    pub bias: f32,
+    pub intensity: f32,
+    pub reversed_z: u32,
+    pub viewport: Vec4,
+}
+
+pub struct SsaoPass {
+    _shader: ShaderModule,
+    ao_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+
+    uniform: Box<SsaoUniform>,
+    uniform_buffer: Buffer,
+    _uniform_bind_group_layout: BindGroupLayout,
+    uniform_bind_group: BindGroup,
+
+    ao_texture: Texture,
+    ao_view: TextureView,
+    ao_bind_group_layout: BindGroupLayout,
+    ao_bind_group: BindGroup,
+}
+
+impl SsaoPass {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        settings: &SsaoSettings,
+        device: &Device,
+        scene_bind_group: &SceneBindGroup,
+        reversed_z: bool,
+        surface_format: TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+    ) -> SsaoPass {
+        let shader = device.create_shader_module(include_wgsl!("../shaders/ssao.wgsl"));
+
+        let uniform = Box::new(SsaoUniform {
+            radius: settings.radius,
+            bias: settings.bias,
+            intensity: settings.intensity,
+            reversed_z: reversed_z as u32,
+            ..Default::default()
+        });
+        let (uniform_buffer, uniform_bind_group_layout, uniform_bind_group) =
+            create_uniform_init(uniform.as_ref(), device);
+
+        let ao_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let ao_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[scene_bind_group.layout(), &uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                scene_bind_group.layout(),
+                &uniform_bind_group_layout,
+                &ao_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |layout, entry_point, target: ColorTargetState, sample_count| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: None,
+                layout: Some(layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(target)],
+                }),
+                multiview: None,
+            })
+        };
+
+        let ao_pipeline = create_pipeline(
+            &ao_pipeline_layout,
+            "fs_ao",
+            ColorTargetState {
+                format: AO_FORMAT,
+                blend: Some(BlendState::REPLACE),
+                write_mask: ColorWrites::ALL,
+            },
+            1,
+        );
+        let composite_pipeline = create_pipeline(
+            &composite_pipeline_layout,
+            "fs_composite",
+            ColorTargetState {
+                format: surface_format,
+                blend: Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: BlendFactor::Dst,
+                        dst_factor: BlendFactor::Zero,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent::OVER,
+                }),
+                write_mask: ColorWrites::COLOR,
+            },
+            sample_count,
+        );
+
+        let ao_texture = Self::create_ao_texture(device, width, height);
+        let ao_view = ao_texture.create_view(&Default::default());
+        let ao_bind_group = Self::create_ao_bind_group(device, &ao_bind_group_layout, &ao_view);
+
+        SsaoPass {
+            _shader: shader,
+            ao_pipeline,
+            composite_pipeline,
+
+            uniform,
+            uniform_buffer,
+            _uniform_bind_group_layout: uniform_bind_group_layout,
+            uniform_bind_group,
+
+            ao_texture,
+            ao_view,
+            ao_bind_group_layout,
+            ao_bind_group,
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.ao_texture = Self::create_ao_texture(device, width, height);
+        self.ao_view = self.ao_texture.create_view(&Default::default());
+        self.ao_bind_group =
+            Self::create_ao_bind_group(device, &self.ao_bind_group_layout, &self.ao_view);
+    }
+
+    pub fn update(&mut self, queue: &Queue, proj_matrix: Mat4, viewport_rect: Vec4) {
+        self.uniform.proj_matrix = proj_matrix;
+        self.uniform.viewport = viewport_rect;
+        queue.write_buffer(&self.uniform_buffer, 0, bytes_of(self.uniform.as_ref()));
+    }
+
+    pub fn render(&self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &self.ao_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::WHITE),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            context.apply_viewport(&mut pass);
+            pass.set_pipeline(&self.ao_pipeline);
+            pass.set_bind_group(0, context.scene_bind_group(), &[]);
+            pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(2, &self.ao_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn create_ao_texture(device: &Device, width: u32, height: u32) -> Texture {
+        create_texture_2d(
+            device,
+            AO_FORMAT,
+            width,
+            height,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        )
+    }
+
+    fn create_ao_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        view: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            }],
+        })
+    }
+}
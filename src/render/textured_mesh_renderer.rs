@@ -0,0 +1,221 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    include_wgsl, AddressMode, BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState,
+    ColorWrites, DepthBiasState, DepthStencilState, Face, FilterMode, FragmentState, FrontFace,
+    IndexFormat, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor,
+    ShaderModule, StencilFaceState, StencilState, StoreOp, Texture, VertexState,
+};
+
+use crate::utils::{
+    create_texture_bind_group, create_texture_bind_group_layout, create_uniform_init,
+};
+
+use super::{
+    bind_group::BindGroupHelper,
+    mesh::Mesh,
+    render_manager::RenderManager,
+    renderer::{RenderStage, Renderer, RenderingContext},
+    vertex::Vertex,
+};
+
+#[derive(Clone, Copy)]
+pub struct TexturedMeshRendererSettings {
+    pub vertex_color_blend: f32,
+    /// `None` draws both sides, flipping normals of back faces towards the viewer
+    pub cull_mode: Option<Face>,
+    pub front_face: FrontFace,
+}
+
+impl Default for TexturedMeshRendererSettings {
+    fn default() -> Self {
+        Self {
+            vertex_color_blend: 0.0,
+            cull_mode: Some(Face::Back),
+            front_face: FrontFace::Ccw,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct TexturedMeshUniform {
+    pub vertex_color_blend: f32,
+    pub double_sided: u32,
+    _padding: [f32; 2],
+}
+
+pub struct TexturedMeshRenderer {
+    _shader: ShaderModule,
+    _pipeline_layout: PipelineLayout,
+    pipeline: RenderPipeline,
+    mesh: Mesh,
+
+    _uniform_buffer: Buffer,
+    _bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+
+    _albedo_texture: Texture,
+    _albedo_sampler: Sampler,
+    _albedo_bind_group_layout: BindGroupLayout,
+    albedo_bind_group: BindGroup,
+    sample_count: u32,
+}
+
+impl TexturedMeshRenderer {
+    pub fn new(
+        mesh: Mesh,
+        albedo_texture: Texture,
+        settings: &TexturedMeshRendererSettings,
+        render_manager: &RenderManager,
+    ) -> TexturedMeshRenderer {
+        let device = render_manager.device();
+
+        let shader = device.create_shader_module(include_wgsl!("../shaders/textured_mesh.wgsl"));
+
+        let uniform = TexturedMeshUniform {
+            vertex_color_blend: settings.vertex_color_blend,
+            double_sided: settings.cull_mode.is_none() as u32,
+            ..Default::default()
+        };
+        let (uniform_buffer, bind_group_layout, bind_group) = create_uniform_init(&uniform, device);
+
+        let albedo_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy_clamp: render_manager.max_anisotropy(),
+            ..Default::default()
+        });
+        let albedo_bind_group_layout = create_texture_bind_group_layout(device);
+        let albedo_bind_group = create_texture_bind_group(
+            device,
+            &albedo_bind_group_layout,
+            &albedo_texture.create_view(&Default::default()),
+            &albedo_sampler,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                render_manager.scene_bind_group().borrow().layout(),
+                &bind_group_layout,
+                &albedo_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: PrimitiveState {
+                topology: mesh.topology(),
+                strip_index_format: mesh.strip_index_format(),
+                front_face: settings.front_face,
+                cull_mode: settings.cull_mode,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: render_manager.depth_texture().format(),
+                depth_write_enabled: true,
+                depth_compare: render_manager.depth_compare(),
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState::default(),
+            }),
+            multisample: render_manager.multisample_state(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: render_manager.surface_format(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        TexturedMeshRenderer {
+            _shader: shader,
+            _pipeline_layout: pipeline_layout,
+            pipeline,
+            mesh,
+
+            _uniform_buffer: uniform_buffer,
+            _bind_group_layout: bind_group_layout,
+            bind_group,
+
+            _albedo_texture: albedo_texture,
+            _albedo_sampler: albedo_sampler,
+            _albedo_bind_group_layout: albedo_bind_group_layout,
+            albedo_bind_group,
+            sample_count: render_manager.sample_count(),
+        }
+    }
+}
+
+impl Renderer for TexturedMeshRenderer {
+    fn render(&mut self, context: &RenderingContext) {
+        let mut encoder_ref = context.encoder().borrow_mut();
+        let encoder = encoder_ref.as_mut().unwrap();
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.surface_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: context.depth_view(),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        context.apply_viewport(&mut pass);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
+        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint32);
+        pass.set_bind_group(0, context.scene_bind_group(), &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
+        pass.set_bind_group(2, &self.albedo_bind_group, &[]);
+
+        pass.draw_indexed(0..(self.mesh.indices().len() as u32), 0, 0..1);
+        context.record_draw(
+            self.mesh.indices().len() as u32,
+            self.mesh.triangles_count(),
+        );
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::OPAQUE
+    }
+
+    fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
@@ -1,32 +1,68 @@
-use std::mem::size_of;
+use std::mem::{offset_of, size_of};
 
 use bytemuck::{Pod, Zeroable};
-use glam::Vec3;
-use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexStepMode};
+use glam::{Vec2, Vec3, Vec4};
+use wgpu::{VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
 
 #[repr(C)]
 #[derive(Clone, Copy, Default, Pod, Zeroable)]
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
-    pub color: Vec3,
+    pub uv: Vec2,
+    pub color: Vec4,
+    pub tangent: Vec4,
 }
 
-static VERTEX_ATTRIBUTES: [VertexAttribute; 3] = vertex_attr_array![
-    0 => Float32x3,
-    1 => Float32x3,
-    2 => Float32x3
+// Vec4 is 16-byte aligned, so the offsets are taken from the struct instead of
+// being packed back to back
+static VERTEX_ATTRIBUTES: [VertexAttribute; 5] = [
+    VertexAttribute {
+        format: VertexFormat::Float32x3,
+        offset: offset_of!(Vertex, position) as u64,
+        shader_location: 0,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32x3,
+        offset: offset_of!(Vertex, normal) as u64,
+        shader_location: 1,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32x4,
+        offset: offset_of!(Vertex, color) as u64,
+        shader_location: 2,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32x2,
+        offset: offset_of!(Vertex, uv) as u64,
+        shader_location: 3,
+    },
+    VertexAttribute {
+        format: VertexFormat::Float32x4,
+        offset: offset_of!(Vertex, tangent) as u64,
+        shader_location: 4,
+    },
 ];
 
 impl Vertex {
     pub fn new(position: Vec3, normal: Vec3, color: Vec3) -> Vertex {
+        Self::new_rgba(position, normal, color.extend(1.0))
+    }
+
+    pub fn new_rgba(position: Vec3, normal: Vec3, color: Vec4) -> Vertex {
         Vertex {
             position,
             normal,
             color,
+            ..Default::default()
         }
     }
 
+    pub fn with_uv(mut self, uv: Vec2) -> Vertex {
+        self.uv = uv;
+        self
+    }
+
     pub fn buffer_layout() -> VertexBufferLayout<'static> {
         VertexBufferLayout {
             array_stride: size_of::<Vertex>() as u64,
@@ -1,29 +1,42 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
 use bytemuck::{Pod, Zeroable};
-use glam::{Vec2, Vec3};
+use glam::{Vec2, Vec3, Vec4};
+use log::warn;
 use noise::Constant;
 use wgpu::{
-    include_wgsl, BindGroup, BindGroupLayout, BlendState, Buffer, ColorTargetState, ColorWrites,
-    CompareFunction, DepthBiasState, DepthStencilState, Face, FragmentState, FrontFace,
-    IndexFormat, LoadOp, MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor,
-    PolygonMode, PrimitiveState, PrimitiveTopology, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModule, StencilFaceState, StencilState, StoreOp, VertexState,
+    BindGroup, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, Face, FragmentState, FrontFace, IndexFormat, LoadOp,
+    MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilFaceState,
+    StencilOperation, StencilState, StoreOp, VertexState,
 };
 
-use crate::utils::{
-    create_uniform_init,
-    terrain_generator::{generate_terrain_mesh, TerrainSettings},
+use crate::{
+    core::error::TerrainError,
+    utils::{
+        color_ramp::ColorRamp,
+        pipeline::primitive_state,
+        srgb_to_linear,
+        terrain_generator::{generate_terrain_mesh, TerrainSettings},
+    },
 };
 
 use super::{
-    bind_group::BindGroupHelper,
+    bind_group::{BindGroupHelper, UniformBindGroup},
     mesh::Mesh,
+    mesh_renderer::CausticsSettings,
     render_manager::RenderManager,
-    renderer::{RenderStage, Renderer, RenderingContext},
+    renderer::{RenderStage, RenderTargets, Renderer, RenderingContext},
+    shader::{catch_validation_errors, create_shader_module, ShaderSource},
     vertex::Vertex,
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct WaterRendererSettings {
     pub tile_size: f32,
     pub tiles_count: u32,
@@ -35,6 +48,21 @@ pub struct WaterRendererSettings {
     pub wave_speed: Vec2,
     pub wave_scale: Vec2,
     pub wave_height: f32,
+    /// Mip levels of blur per unit of water depth behind the surface. Has no effect
+    /// unless the render manager generates opaque mips.
+    pub refraction_blur: f32,
+    pub linear_colors: bool,
+    pub depth_bias: DepthBiasState,
+    pub blend: BlendState,
+    /// Write `ColorWrites::COLOR` to keep the target's alpha untouched
+    pub write_mask: ColorWrites,
+    pub caustics_intensity: f32,
+    pub caustics_scale: f32,
+    /// Skips water fragments behind terrain lying above the water, found from the
+    /// opaque depth snapshot. Needs a depth format with stencil, like
+    /// `Depth24PlusStencil8`.
+    pub stencil_mask: bool,
+    pub shader: ShaderSource,
 }
 
 impl Default for WaterRendererSettings {
@@ -50,20 +78,44 @@ impl Default for WaterRendererSettings {
             wave_speed: Vec2::new(0.8, 0.4),
             wave_scale: Vec2::new(0.4, 0.4),
             wave_height: 0.2,
+            refraction_blur: 2.0,
+            linear_colors: true,
+            depth_bias: Default::default(),
+            blend: BlendState::ALPHA_BLENDING,
+            write_mask: ColorWrites::ALL,
+            caustics_intensity: 0.6,
+            caustics_scale: 1.5,
+            stencil_mask: false,
+            shader: ShaderSource::Builtin,
+        }
+    }
+}
+
+impl WaterRendererSettings {
+    /// Caustics for meshes lying under this water
+    pub fn caustics_settings(&self) -> CausticsSettings {
+        CausticsSettings {
+            water_level: self.level,
+            intensity: self.caustics_intensity,
+            scale: self.caustics_scale,
+            ..Default::default()
         }
     }
 }
 
 pub struct WaterRenderer {
     _shader: ShaderModule,
-    _pipeline_layout: PipelineLayout,
+    pipeline_layout: PipelineLayout,
     pipeline: RenderPipeline,
+    settings: WaterRendererSettings,
+    targets: RenderTargets,
+    front_face: FrontFace,
 
     mesh: Mesh,
+    stencil_mask_pipeline: Option<RenderPipeline>,
 
-    _uniform_buffer: Buffer,
-    _bind_group_layout: BindGroupLayout,
-    bind_group: BindGroup,
+    uniform: UniformBindGroup<WaterUniform>,
+    bind_group: Rc<BindGroup>,
 }
 
 #[repr(C)]
@@ -71,116 +123,252 @@ pub struct WaterRenderer {
 struct WaterUniform {
     pub specular: f32,
     pub density: f32,
-    _padding1: [f32; 2],
+    pub reversed_z: u32,
+    _padding1: f32,
     pub specular_color: Vec3,
     _padding2: f32,
     pub wave_speed: Vec2,
     pub wave_scale: Vec2,
     pub wave_height: f32,
-    _padding3: [f32; 3],
+    pub refraction_blur: f32,
+    pub mask_level: f32,
+    _padding3: f32,
+    pub viewport_rect: Vec4,
 }
 
 impl WaterRenderer {
-    pub fn new(settings: &WaterRendererSettings, render_manager: &RenderManager) -> WaterRenderer {
+    pub fn new(
+        settings: &WaterRendererSettings,
+        render_manager: &RenderManager,
+    ) -> Result<WaterRenderer, TerrainError> {
         let device = render_manager.device();
 
-        let shader = device.create_shader_module(include_wgsl!("../shaders/water.glsl"));
+        let shader = create_shader_module(
+            device,
+            "water",
+            &settings.shader,
+            include_str!("../shaders/water.wgsl"),
+        )?;
 
-        let uniform = WaterUniform {
-            specular: settings.specular,
-            density: settings.density,
-            specular_color: settings.specular_color,
-            wave_speed: settings.wave_speed,
-            wave_scale: settings.wave_scale,
-            wave_height: settings.wave_height,
-            ..Default::default()
-        };
-        let (uniform_buffer, bind_group_layout, bind_group) = create_uniform_init(&uniform, device);
+        let mut uniform = UniformBindGroup::new(
+            device,
+            WaterUniform {
+                specular: settings.specular,
+                density: settings.density,
+                reversed_z: render_manager.reversed_z() as u32,
+                specular_color: if settings.linear_colors {
+                    srgb_to_linear(settings.specular_color)
+                } else {
+                    settings.specular_color
+                },
+                wave_speed: settings.wave_speed,
+                wave_scale: settings.wave_scale,
+                wave_height: settings.wave_height,
+                refraction_blur: if render_manager.generates_opaque_mips() {
+                    settings.refraction_blur
+                } else {
+                    0.0
+                },
+                // Waves may rise above the water level
+                mask_level: settings.level + settings.wave_height.abs(),
+                ..Default::default()
+            },
+        );
+        let bind_group = uniform.bind_group(device);
 
-        let mesh: Mesh = generate_terrain_mesh(
+        let (mesh, _) = generate_terrain_mesh(
             device,
             &TerrainSettings {
                 tile_size: settings.tile_size,
                 tiles_count: settings.tiles_count,
-                colors: vec![settings.color].into_boxed_slice(),
-                colors_thresholds: vec![].into_boxed_slice(),
+                world_size: None,
+                origin: Vec3::ZERO,
+                colors: ColorRamp::solid(settings.color),
+                cliff_color: settings.color,
+                slope_threshold: 1.0,
+                slope_blend: 0.0,
+                color_variation: 0.0,
                 noise: Constant::new(settings.level.into()),
                 scale: 1.0,
+                warp_strength: 0.0,
+                warp_scale: 1.0,
                 max_height: 1.0,
+                uv_scale: 1.0,
+                linear_colors: settings.linear_colors,
+                erosion: None,
+                topology: PrimitiveTopology::TriangleList,
+                flip_winding: false,
             },
-        );
+        )?;
+
+        let stencil_mask =
+            settings.stencil_mask && render_manager.depth_texture().format().has_stencil_aspect();
+        if settings.stencil_mask && !stencil_mask {
+            warn!("Water stencil mask needs a depth format with stencil, it's disabled");
+        }
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[
                 render_manager.scene_bind_group().borrow().layout(),
-                &bind_group_layout,
+                uniform.layout(),
             ],
             push_constant_ranges: &[],
         });
 
+        let targets = render_manager.render_targets();
+        let front_face = render_manager.coordinate_system().front_face();
+        let (pipeline, stencil_mask_pipeline) = catch_validation_errors(device, "water", || {
+            Self::create_pipelines(
+                device,
+                &shader,
+                &pipeline_layout,
+                settings,
+                &targets,
+                front_face,
+                stencil_mask,
+            )
+        })?;
+
+        Ok(WaterRenderer {
+            _shader: shader,
+            pipeline_layout,
+            pipeline,
+            settings: settings.clone(),
+            targets,
+            front_face,
+
+            mesh,
+            stencil_mask_pipeline,
+
+            uniform,
+            bind_group,
+        })
+    }
+
+    fn create_pipelines(
+        device: &Device,
+        shader: &ShaderModule,
+        pipeline_layout: &PipelineLayout,
+        settings: &WaterRendererSettings,
+        targets: &RenderTargets,
+        front_face: FrontFace,
+        stencil_mask: bool,
+    ) -> (RenderPipeline, Option<RenderPipeline>) {
         let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 buffers: &[Vertex::buffer_layout()],
             },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
+            primitive: primitive_state(
+                PrimitiveTopology::TriangleList,
+                Some(Face::Back),
+                PolygonMode::Fill,
+                front_face,
+            ),
             depth_stencil: Some(DepthStencilState {
-                format: render_manager.depth_texture().format(),
+                format: targets.depth_format,
                 depth_write_enabled: true,
-                depth_compare: CompareFunction::Less,
-                stencil: StencilState {
-                    front: StencilFaceState::IGNORE,
-                    back: StencilFaceState::IGNORE,
-                    read_mask: 0,
-                    write_mask: 0,
+                depth_compare: targets.depth_compare,
+                stencil: if stencil_mask {
+                    let face = StencilFaceState {
+                        compare: CompareFunction::NotEqual,
+                        ..StencilFaceState::IGNORE
+                    };
+                    StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: !0,
+                        write_mask: 0,
+                    }
+                } else {
+                    StencilState {
+                        front: StencilFaceState::IGNORE,
+                        back: StencilFaceState::IGNORE,
+                        read_mask: 0,
+                        write_mask: 0,
+                    }
                 },
-                bias: DepthBiasState::default(),
+                bias: settings.depth_bias,
             }),
             multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+                count: targets.sample_count,
+                ..Default::default()
             },
             fragment: Some(FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(ColorTargetState {
-                    format: render_manager.surface_format(),
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::COLOR,
+                    format: targets.color_format,
+                    blend: Some(settings.blend),
+                    write_mask: settings.write_mask,
                 })],
             }),
             multiview: None,
         });
 
-        WaterRenderer {
-            _shader: shader,
-            _pipeline_layout: pipeline_layout,
-            pipeline,
+        // Marks pixels of terrain above the water with stencil, without touching color
+        let stencil_mask_pipeline = stencil_mask.then(|| {
+            let face = StencilFaceState {
+                compare: CompareFunction::Always,
+                fail_op: StencilOperation::Keep,
+                depth_fail_op: StencilOperation::Keep,
+                pass_op: StencilOperation::Replace,
+            };
 
-            mesh,
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: None,
+                layout: Some(pipeline_layout),
+                vertex: VertexState {
+                    module: shader,
+                    entry_point: "vs_mask",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: Some(DepthStencilState {
+                    format: targets.depth_format,
+                    depth_write_enabled: false,
+                    depth_compare: CompareFunction::Always,
+                    stencil: StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: !0,
+                        write_mask: !0,
+                    },
+                    bias: DepthBiasState::default(),
+                }),
+                multisample: MultisampleState {
+                    count: targets.sample_count,
+                    ..Default::default()
+                },
+                fragment: Some(FragmentState {
+                    module: shader,
+                    entry_point: "fs_mask",
+                    targets: &[Some(ColorTargetState {
+                        format: targets.color_format,
+                        blend: None,
+                        write_mask: ColorWrites::empty(),
+                    })],
+                }),
+                multiview: None,
+            })
+        });
 
-            _uniform_buffer: uniform_buffer,
-            _bind_group_layout: bind_group_layout,
-            bind_group,
-        }
+        (pipeline, stencil_mask_pipeline)
     }
 }
 
 impl Renderer for WaterRenderer {
     fn render(&mut self, context: &RenderingContext) {
+        if self.stencil_mask_pipeline.is_some() {
+            let mut uniform = *self.uniform.uniform();
+            uniform.viewport_rect = context.viewport_rect();
+            self.uniform.update(&context.queue().borrow(), &uniform);
+        }
+
         let mut encoder_ref = context.encoder().borrow_mut();
         let encoder = encoder_ref.as_mut().unwrap();
 
@@ -200,22 +388,79 @@ impl Renderer for WaterRenderer {
                     load: LoadOp::Load,
                     store: StoreOp::Store,
                 }),
-                stencil_ops: None,
+                stencil_ops: self.stencil_mask_pipeline.as_ref().map(|_| Operations {
+                    load: LoadOp::Clear(0),
+                    store: StoreOp::Store,
+                }),
             }),
             timestamp_writes: None,
             occlusion_query_set: None,
         });
 
-        pass.set_pipeline(&self.pipeline);
-        pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
-        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint16);
+        context.apply_viewport(&mut pass);
         pass.set_bind_group(0, context.scene_bind_group(), &[]);
         pass.set_bind_group(1, &self.bind_group, &[]);
 
+        if let Some(pipeline) = &self.stencil_mask_pipeline {
+            pass.set_pipeline(pipeline);
+            pass.set_stencil_reference(1);
+            pass.draw(0..3, 0..1);
+            context.record_draw(3, 1);
+        }
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.mesh.vertex_buffer().slice(..));
+        pass.set_index_buffer(self.mesh.index_buffer().slice(..), IndexFormat::Uint32);
+
         pass.draw_indexed(0..(self.mesh.indices().len() as u32), 0, 0..1);
+        context.record_draw(
+            self.mesh.indices().len() as u32,
+            self.mesh.triangles_count(),
+        );
     }
 
     fn stage(&self) -> RenderStage {
         RenderStage::TRANSPARENT
     }
+
+    fn sample_count(&self) -> u32 {
+        self.targets.sample_count
+    }
+
+    fn needs_opaque_snapshot(&self) -> bool {
+        true
+    }
+
+    fn shader_paths(&self) -> Vec<PathBuf> {
+        self.settings
+            .shader
+            .path()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .collect()
+    }
+
+    fn reload_shaders(&mut self, device: &Device) -> Result<(), TerrainError> {
+        let shader = create_shader_module(
+            device,
+            "water",
+            &self.settings.shader,
+            include_str!("../shaders/water.wgsl"),
+        )?;
+        (self.pipeline, self.stencil_mask_pipeline) =
+            catch_validation_errors(device, "water", || {
+                Self::create_pipelines(
+                    device,
+                    &shader,
+                    &self.pipeline_layout,
+                    &self.settings,
+                    &self.targets,
+                    self.front_face,
+                    self.stencil_mask_pipeline.is_some(),
+                )
+            })?;
+        self._shader = shader;
+
+        Ok(())
+    }
 }
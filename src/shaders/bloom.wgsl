@@ -0,0 +1,80 @@
+struct BloomUniform {
+    threshold: f32,
+    intensity: f32,
+    radius: f32
+}
+
+@group(0) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+@group(1) @binding(0)
+var source_sampler: sampler;
+
+@group(1) @binding(1)
+var source_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) uv: vec2f
+}
+
+fn sample_box(uv: vec2f) -> vec3f {
+    let d = 1.0 / vec2f(textureDimensions(source_texture));
+
+    return (
+        textureSample(source_texture, source_sampler, uv + vec2f(-d.x, -d.y)).rgb +
+        textureSample(source_texture, source_sampler, uv + vec2f(d.x, -d.y)).rgb +
+        textureSample(source_texture, source_sampler, uv + vec2f(-d.x, d.y)).rgb +
+        textureSample(source_texture, source_sampler, uv + vec2f(d.x, d.y)).rgb
+    ) * 0.25;
+}
+
+fn sample_tent(uv: vec2f) -> vec3f {
+    let d = bloom.radius / vec2f(textureDimensions(source_texture));
+
+    var c = textureSample(source_texture, source_sampler, uv).rgb * 4.0;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(-d.x, 0.0)).rgb * 2.0;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(d.x, 0.0)).rgb * 2.0;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(0.0, -d.y)).rgb * 2.0;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(0.0, d.y)).rgb * 2.0;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(-d.x, -d.y)).rgb;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(d.x, -d.y)).rgb;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(-d.x, d.y)).rgb;
+    c += textureSample(source_texture, source_sampler, uv + vec2f(d.x, d.y)).rgb;
+
+    return c / 16.0;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return VertexOutput(
+        vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0),
+        uv
+    );
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4f {
+    let c = sample_box(in.uv);
+    let brightness = max(c.r, max(c.g, c.b));
+    let k = max(brightness - bloom.threshold, 0.0) / max(brightness, 0.0001);
+
+    return vec4f(c * k, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(sample_box(in.uv), 1.0);
+}
+
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(sample_tent(in.uv), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(sample_tent(in.uv) * bloom.intensity, 1.0);
+}
@@ -0,0 +1,62 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(2) color: vec4f
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(1) color: vec3f
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    return VertexOutput(
+        scene.view_proj_matrix * vec4f(in.position, 1.0),
+        in.color.rgb
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(in.color, 1.0);
+}
@@ -0,0 +1,111 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct DebugViewUniform {
+    // x, y, width, height in pixels
+    viewport: vec4f,
+    reversed_z: u32
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(0) @binding(3)
+var depth_texture: texture_depth_2d;
+
+@group(1) @binding(0)
+var<uniform> debug_view: DebugViewUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f
+}
+
+fn is_background(coord: vec2i) -> bool {
+    let far_depth = select(1.0, 0.0, debug_view.reversed_z != 0u);
+    return textureLoad(depth_texture, coord, 0) == far_depth;
+}
+
+fn view_position(coord: vec2i) -> vec3f {
+    let depth = textureLoad(depth_texture, coord, 0);
+    let uv = (vec2f(coord) + 0.5 - debug_view.viewport.xy) / debug_view.viewport.zw;
+    let ndc = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let p = scene.inv_proj_matrix * ndc;
+
+    return p.xyz / p.w;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return VertexOutput(vec4f(uv * 2.0 - 1.0, 0.0, 1.0));
+}
+
+// Linear view distance between the clip planes, white at the far plane
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4f {
+    let coord = vec2i(in.clip_pos.xy);
+    if is_background(coord) {
+        return vec4f(1.0);
+    }
+
+    let z = view_position(coord).z;
+    let k = clamp((z - scene.near_plane) / (scene.far_plane - scene.near_plane), 0.0, 1.0);
+
+    return vec4f(vec3f(k), 1.0);
+}
+
+// View space normals reconstructed from the depth, mapped from [-1, 1] to [0, 1]
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4f {
+    let coord = vec2i(in.clip_pos.xy);
+    if is_background(coord) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let max_coord = vec2i(debug_view.viewport.xy + debug_view.viewport.zw) - 1;
+    let p = view_position(coord);
+    let px = view_position(min(coord + vec2i(1, 0), max_coord));
+    let py = view_position(min(coord + vec2i(0, 1), max_coord));
+
+    var n = normalize(cross(px - p, py - p));
+    if n.z > 0.0 {
+        n = -n;
+    }
+
+    return vec4f(n * 0.5 + 0.5, 1.0);
+}
@@ -0,0 +1,13 @@
+@group(0) @binding(0)
+var source_texture: texture_depth_2d;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4f) -> @builtin(frag_depth) f32 {
+    return textureLoad(source_texture, vec2i(position.xy), 0);
+}
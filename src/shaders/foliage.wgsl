@@ -0,0 +1,140 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct FoliageUniform {
+    base_color: vec3f,
+    blade_width: f32,
+    tip_color: vec3f,
+    wind_strength: f32,
+    wind_direction: vec2f,
+    wind_speed: f32
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var<uniform> foliage: FoliageUniform;
+
+struct InstanceInput {
+    @location(0) position: vec3f,
+    @location(1) rotation: f32,
+    @location(2) height: f32,
+    @location(3) phase: f32
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) t: f32,
+    @location(1) position: vec3f
+}
+
+fn calc_sky_color(dir: vec3f) -> vec3f {
+    let k = pow(abs(dir.y), scene.sky.scattering);
+    return select(
+        mix(scene.sky.horizon_color, scene.sky.bottom_color, k),
+        mix(scene.sky.horizon_color, scene.sky.sky_color, k),
+        dir.y > 0.0
+    );
+}
+
+// Distance fog which thins out above the fog height. Aerial perspective takes the
+// sky color behind the point instead of the flat one.
+fn apply_fog(color: vec3f, position: vec3f) -> vec3f {
+    if scene.fog.mode == 0u {
+        return color;
+    }
+
+    let offset = position - scene.camera_pos;
+    let distance = length(offset);
+    let range = max(scene.fog.end - scene.fog.start, 0.0001);
+    let height = max(position.y - scene.fog.height, 0.0);
+    let k = clamp((distance - scene.fog.start) / range, 0.0, 1.0) *
+        exp(-height * scene.fog.height_falloff);
+
+    let fog_color = select(
+        scene.fog.color,
+        calc_sky_color(offset / max(distance, 0.0001)),
+        scene.fog.mode == 2u
+    );
+
+    return mix(color, fog_color, k);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: InstanceInput) -> VertexOutput {
+    // Blade outline in (side, height) fractions, narrowing towards the tip
+    var points = array<vec2f, 15>(
+        vec2f(-0.5, 0.0),
+        vec2f(0.5, 0.0),
+        vec2f(0.4, 0.4),
+        vec2f(-0.5, 0.0),
+        vec2f(0.4, 0.4),
+        vec2f(-0.4, 0.4),
+        vec2f(-0.4, 0.4),
+        vec2f(0.4, 0.4),
+        vec2f(0.25, 0.75),
+        vec2f(-0.4, 0.4),
+        vec2f(0.25, 0.75),
+        vec2f(-0.25, 0.75),
+        vec2f(-0.25, 0.75),
+        vec2f(0.25, 0.75),
+        vec2f(0.0, 1.0)
+    );
+    let p = points[index];
+
+    let side = vec3f(cos(in.rotation), 0.0, sin(in.rotation));
+    var position = in.position + side * p.x * foliage.blade_width + vec3f(0.0, p.y * in.height, 0.0);
+
+    // Gusts travel across the field along the wind, tips bend the most
+    let gust = sin(scene.time * foliage.wind_speed + dot(in.position.xz, foliage.wind_direction) + in.phase);
+    let sway = foliage.wind_strength * (0.6 + 0.4 * gust) * p.y * p.y;
+    position += vec3f(foliage.wind_direction.x, 0.0, foliage.wind_direction.y) * sway;
+
+    return VertexOutput(scene.view_proj_matrix * vec4f(position, 1.0), p.y, position);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let color = mix(foliage.base_color, foliage.tip_color, in.t);
+
+    // Lit like the flat ground the blades grow from, whose normal faces up
+    let n = vec3f(0.0, 1.0, 0.0);
+    let k = scene.ambient_light + scene.global_light.color * max(dot(n, -scene.global_light.dir), 0.0);
+
+    return vec4f(apply_fog(color * k, in.position), 1.0);
+}
@@ -0,0 +1,74 @@
+const EDGE_THRESHOLD: f32 = 0.125;
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const REDUCE_MUL: f32 = 0.125;
+const REDUCE_MIN: f32 = 0.0078125;
+const SPAN_MAX: f32 = 8.0;
+
+@group(0) @binding(0)
+var source_sampler: sampler;
+
+@group(0) @binding(1)
+var source_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) uv: vec2f
+}
+
+// Perceptual luminance, as the source holds linear colors
+fn luma(color: vec3f) -> f32 {
+    return sqrt(dot(color, vec3f(0.299, 0.587, 0.114)));
+}
+
+fn sample(uv: vec2f) -> vec4f {
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return VertexOutput(
+        vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0),
+        uv
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(source_texture));
+
+    let center = sample(in.uv);
+    let l_m = luma(center.rgb);
+    let l_nw = luma(sample(in.uv + vec2f(-1.0, -1.0) * texel).rgb);
+    let l_ne = luma(sample(in.uv + vec2f(1.0, -1.0) * texel).rgb);
+    let l_sw = luma(sample(in.uv + vec2f(-1.0, 1.0) * texel).rgb);
+    let l_se = luma(sample(in.uv + vec2f(1.0, 1.0) * texel).rgb);
+
+    let l_min = min(l_m, min(min(l_nw, l_ne), min(l_sw, l_se)));
+    let l_max = max(l_m, max(max(l_nw, l_ne), max(l_sw, l_se)));
+    if l_max - l_min < max(EDGE_THRESHOLD_MIN, l_max * EDGE_THRESHOLD) {
+        return center;
+    }
+
+    // Blur along the edge, which runs perpendicular to the luminance gradient
+    var dir = vec2f((l_sw + l_se) - (l_nw + l_ne), (l_nw + l_sw) - (l_ne + l_se));
+    let reduce = max((l_nw + l_ne + l_sw + l_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2f(-SPAN_MAX), vec2f(SPAN_MAX)) * texel;
+
+    let a = 0.5 * (
+        sample(in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        sample(in.uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let b = a * 0.5 + 0.25 * (
+        sample(in.uv - dir * 0.5).rgb +
+        sample(in.uv + dir * 0.5).rgb
+    );
+
+    // The wider blur is rejected when it picks up colors from beyond the edge
+    let l_b = luma(b);
+    let color = select(b, a, l_b < l_min || l_b > l_max);
+
+    return vec4f(color, center.a);
+}
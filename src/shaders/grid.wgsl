@@ -0,0 +1,104 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct GridUniform {
+    color: vec3f,
+    cell_size: f32,
+    x_axis_color: vec3f,
+    fade_distance: f32,
+    z_axis_color: vec3f,
+    height: f32
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var<uniform> grid: GridUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) position: vec3f
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0),
+        vec2f(1.0, -1.0),
+        vec2f(1.0, 1.0),
+        vec2f(-1.0, -1.0),
+        vec2f(1.0, 1.0),
+        vec2f(-1.0, 1.0)
+    );
+
+    let xz = scene.camera_pos.xz + corners[index] * grid.fade_distance;
+    let p = vec3f(xz.x, grid.height, xz.y);
+
+    return VertexOutput(
+        scene.view_proj_matrix * vec4f(p, 1.0),
+        p
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let coord = in.position.xz / grid.cell_size;
+    let d = fwidth(coord);
+    let g = abs(fract(coord - 0.5) - 0.5) / d;
+    let line = 1.0 - min(min(g.x, g.y), 1.0);
+
+    let axis = abs(in.position.xz) / fwidth(in.position.xz);
+
+    var color = grid.color;
+    if axis.y < 1.0 {
+        color = grid.x_axis_color;
+    }
+    if axis.x < 1.0 {
+        color = grid.z_axis_color;
+    }
+
+    let dist = distance(in.position.xz, scene.camera_pos.xz);
+    let fade = 1.0 - smoothstep(0.5 * grid.fade_distance, grid.fade_distance, dist);
+
+    let alpha = line * fade;
+    if alpha <= 0.0 {
+        discard;
+    }
+
+    return vec4f(color, alpha);
+}
@@ -3,8 +3,26 @@ struct GlobalLight {
     color: vec3f
 }
 
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
 struct SceneUniform {
     view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
     camera_dir: vec3f,
     camera_pos: vec3f,
     surface_size: vec2f,
@@ -12,39 +30,141 @@ struct SceneUniform {
     far_plane: f32,
     global_light: GlobalLight,
     ambient_light: vec3f,
-    time: f32
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct MeshUniform {
+    opacity: f32,
+    alpha_cutoff: f32,
+    use_caustics: u32,
+    caustics_level: f32,
+    caustics_intensity: f32,
+    caustics_scale: f32,
+    caustics_speed: f32,
+    caustics_falloff: f32,
+    double_sided: u32
 }
 
 @group(0) @binding(0)
 var<uniform> scene: SceneUniform;
 
+@group(1) @binding(0)
+var<uniform> mesh: MeshUniform;
+
+struct ModelUniform {
+    model_matrix: mat4x4f,
+    normal_matrix: mat4x4f
+}
+
+// Group 2 belongs to the material, which appends the fragment stage to this file
+
+@group(3) @binding(0)
+var<uniform> model: ModelUniform;
+
 struct VertexInput {
     @location(0) position: vec3f,
     @location(1) normal: vec3f,
-    @location(2) color: vec3f
+    @location(2) color: vec4f,
+    @location(3) uv: vec2f,
+    @location(4) tangent: vec4f
 }
 
 struct VertexOutput {
     @builtin(position) clip_pos: vec4f,
-    @location(1) @interpolate(perspective) color: vec3f
+    @location(1) @interpolate(perspective) color: vec4f,
+    @location(2) normal: vec3f,
+    @location(3) uv: vec2f,
+    @location(4) tangent: vec4f,
+    @location(5) position: vec3f
 }
 
 fn calc_global_light(color: vec3f, n: vec3f) -> vec3f {
-    let k = scene.ambient_light + scene.global_light.color * max(dot(n, scene.global_light.dir), 0.0);
+    let k = scene.ambient_light + scene.global_light.color * max(dot(n, -scene.global_light.dir), 0.0);
     return color * k;
 }
 
+fn calc_sky_color(dir: vec3f) -> vec3f {
+    let k = pow(abs(dir.y), scene.sky.scattering);
+    return select(
+        mix(scene.sky.horizon_color, scene.sky.bottom_color, k),
+        mix(scene.sky.horizon_color, scene.sky.sky_color, k),
+        dir.y > 0.0
+    );
+}
+
+// Distance fog which thins out above the fog height. Aerial perspective takes the
+// sky color behind the point instead of the flat one.
+fn apply_fog(color: vec3f, position: vec3f) -> vec3f {
+    if scene.fog.mode == 0u {
+        return color;
+    }
+
+    let offset = position - scene.camera_pos;
+    let distance = length(offset);
+    let range = max(scene.fog.end - scene.fog.start, 0.0001);
+    let height = max(position.y - scene.fog.height, 0.0);
+    let k = clamp((distance - scene.fog.start) / range, 0.0, 1.0) *
+        exp(-height * scene.fog.height_falloff);
+
+    let fog_color = select(
+        scene.fog.color,
+        calc_sky_color(offset / max(distance, 0.0001)),
+        scene.fog.mode == 2u
+    );
+
+    return mix(color, fog_color, k);
+}
+
+// Thin bright lines of two interfering warped wave patterns
+fn calc_caustics(p: vec2f) -> f32 {
+    let t = scene.time * mesh.caustics_speed;
+    let a = p * mesh.caustics_scale;
+    let w = a + vec2f(sin(a.y + t), cos(a.x - t * 0.8));
+    let v = sin(w.x * 2.0 + t) * sin(w.y * 2.0 - t * 0.6);
+
+    return pow(1.0 - abs(v), 8.0);
+}
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
+    let position = (model.model_matrix * vec4f(in.position, 1.0)).xyz;
+    let normal = (model.normal_matrix * vec4f(in.normal, 0.0)).xyz;
+    let tangent = (model.model_matrix * vec4f(in.tangent.xyz, 0.0)).xyz;
+
     let out = VertexOutput(
-        scene.view_proj_matrix * vec4f(in.position, 1.0),
-        calc_global_light(in.color, in.normal)
+        scene.view_proj_matrix * vec4f(position, 1.0),
+        in.color,
+        normal,
+        in.uv,
+        vec4f(tangent, in.tangent.w),
+        position
     );
 
     return out;
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return vec4f(in.color, 1.0);
+// Flips the normal of back faces towards the viewer for double sided meshes
+fn surface_normal(in: VertexOutput, front_facing: bool) -> vec3f {
+    return select(in.normal, -in.normal, mesh.double_sided != 0u && !front_facing);
+}
+
+// Lights the surface, adding caustics and fog, and discards it below the alpha cutoff.
+// Materials shade their base color with it in `fs_main`.
+fn shade_surface(in: VertexOutput, base_color: vec4f, n: vec3f) -> vec4f {
+    let alpha = base_color.a * mesh.opacity;
+    if alpha < mesh.alpha_cutoff {
+        discard;
+    }
+
+    var color = calc_global_light(base_color.rgb, n);
+
+    let depth = mesh.caustics_level - in.position.y;
+    if mesh.use_caustics != 0u && depth > 0.0 {
+        let k = calc_caustics(in.position.xz) * mesh.caustics_intensity;
+        color += scene.global_light.color * k * exp(-depth * mesh.caustics_falloff);
+    }
+
+    return vec4f(apply_fog(color, in.position), alpha);
 }
@@ -0,0 +1,113 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct MinimapUniform {
+    view_proj_matrix: mat4x4f,
+    marker_pos: vec2f,
+    marker_dir: vec2f,
+    marker_color: vec3f,
+    marker_size: f32
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var<uniform> minimap: MinimapUniform;
+
+@group(2) @binding(0)
+var minimap_sampler: sampler;
+
+@group(2) @binding(1)
+var minimap_texture: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) color: vec4f
+}
+
+struct TerrainOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) color: vec4f,
+    @location(1) normal: vec3f
+}
+
+struct OverlayOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) uv: vec2f
+}
+
+@vertex
+fn vs_terrain(in: VertexInput) -> TerrainOutput {
+    return TerrainOutput(
+        minimap.view_proj_matrix * vec4f(in.position, 1.0),
+        in.color,
+        in.normal
+    );
+}
+
+@fragment
+fn fs_terrain(in: TerrainOutput) -> @location(0) vec4f {
+    let k = scene.ambient_light + scene.global_light.color * max(dot(in.normal, -scene.global_light.dir), 0.0);
+
+    return vec4f(in.color.rgb * k, 1.0);
+}
+
+@vertex
+fn vs_overlay(@builtin(vertex_index) index: u32) -> OverlayOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return OverlayOutput(vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0), uv);
+}
+
+@fragment
+fn fs_overlay(in: OverlayOutput) -> @location(0) vec4f {
+    var color = textureSample(minimap_texture, minimap_sampler, in.uv).rgb;
+
+    // Arrow-like triangle pointing along the camera direction
+    let local = in.uv - minimap.marker_pos;
+    let dir = minimap.marker_dir;
+    let along = dot(local, dir);
+    let side = abs(dot(local, vec2f(-dir.y, dir.x)));
+    let size = minimap.marker_size;
+    if along > -size * 0.5 && along < size && side < (size - along) * 0.4 {
+        color = minimap.marker_color;
+    }
+
+    return vec4f(color, 1.0);
+}
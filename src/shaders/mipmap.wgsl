@@ -0,0 +1,26 @@
+@group(0) @binding(0)
+var source_sampler: sampler;
+
+@group(0) @binding(1)
+var source_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) uv: vec2f
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return VertexOutput(
+        vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0),
+        uv
+    );
+}
+
+// Bilinear filtering at the texel corner averages the 2x2 source block
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(source_texture, source_sampler, in.uv);
+}
@@ -0,0 +1,83 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct PointCloudUniform {
+    viewport_size: vec2f,
+    point_size: f32
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var<uniform> point_cloud: PointCloudUniform;
+
+struct PointInput {
+    @location(0) position: vec3f,
+    @location(1) color: vec4f
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(0) color: vec4f
+}
+
+// Corners of the two triangles of a square
+var<private> CORNERS: array<vec2f, 6> = array<vec2f, 6>(
+    vec2f(-1.0, -1.0),
+    vec2f(1.0, -1.0),
+    vec2f(1.0, 1.0),
+    vec2f(-1.0, -1.0),
+    vec2f(1.0, 1.0),
+    vec2f(-1.0, 1.0)
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, point: PointInput) -> VertexOutput {
+    var clip_pos = scene.view_proj_matrix * vec4f(point.position, 1.0);
+
+    // Offsetting in clip space before the perspective divide keeps the size in pixels
+    let offset = CORNERS[vertex_index] * point_cloud.point_size / point_cloud.viewport_size;
+    clip_pos += vec4f(offset * clip_pos.w, 0.0, 0.0);
+
+    return VertexOutput(clip_pos, point.color);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    return in.color;
+}
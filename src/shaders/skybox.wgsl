@@ -1,18 +1,61 @@
-struct SkyboxUniform {
-    transform_matrix: mat4x4f,
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
     sky_color: vec3f,
+    scattering: f32,
     horizon_color: vec3f,
-    bottom_color: vec3f,
-    scattering: f32
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct SkyboxUniform {
+    transform_matrix: mat4x4f,
+    use_cubemap: u32
 }
 
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
 @group(1) @binding(0)
 var<uniform> skybox: SkyboxUniform;
 
+@group(2) @binding(0)
+var cubemap_sampler: sampler;
+
+@group(2) @binding(1)
+var cubemap_texture: texture_cube<f32>;
+
 struct VertexInput {
     @location(0) position: vec3f,
     @location(1) normal: vec3f,
-    @location(2) color: vec3f
+    @location(2) color: vec4f
 }
 
 struct VertexOutput {
@@ -20,6 +63,15 @@ struct VertexOutput {
     @location(0) @interpolate(perspective) position: vec3f
 }
 
+fn calc_sky_color(dir: vec3f) -> vec3f {
+    let k = pow(abs(dir.y), scene.sky.scattering);
+    return select(
+        mix(scene.sky.horizon_color, scene.sky.bottom_color, k),
+        mix(scene.sky.horizon_color, scene.sky.sky_color, k),
+        dir.y > 0.0
+    );
+}
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     let out = VertexOutput(
@@ -32,13 +84,9 @@ fn vs_main(in: VertexInput) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    let a = normalize(in.position).y;
-    let k = pow(abs(a), skybox.scattering);
-    let c = select(
-        mix(skybox.horizon_color, skybox.bottom_color, k),
-        mix(skybox.horizon_color, skybox.sky_color, k),
-        a > 0.0
-    );
+    let c = calc_sky_color(normalize(in.position));
+
+    let cubemap_color = textureSample(cubemap_texture, cubemap_sampler, in.position).rgb;
 
-    return vec4f(c, 1.0);
+    return vec4f(select(c, cubemap_color, skybox.use_cubemap != 0u), 1.0);
 }
@@ -0,0 +1,11 @@
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The color comes from the blend constant
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
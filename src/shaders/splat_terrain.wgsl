@@ -0,0 +1,144 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct SplatUniform {
+    tiling: vec4f
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var<uniform> splat: SplatUniform;
+
+@group(2) @binding(0)
+var layer_sampler: sampler;
+
+@group(2) @binding(1)
+var splatmap_sampler: sampler;
+
+@group(2) @binding(2)
+var splatmap_texture: texture_2d<f32>;
+
+@group(2) @binding(3)
+var layer0_texture: texture_2d<f32>;
+
+@group(2) @binding(4)
+var layer1_texture: texture_2d<f32>;
+
+@group(2) @binding(5)
+var layer2_texture: texture_2d<f32>;
+
+@group(2) @binding(6)
+var layer3_texture: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) color: vec4f,
+    @location(3) uv: vec2f
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(2) normal: vec3f,
+    @location(3) uv: vec2f,
+    @location(4) position: vec3f
+}
+
+fn calc_global_light(color: vec3f, n: vec3f) -> vec3f {
+    let k = scene.ambient_light + scene.global_light.color * max(dot(n, -scene.global_light.dir), 0.0);
+    return color * k;
+}
+
+fn calc_sky_color(dir: vec3f) -> vec3f {
+    let k = pow(abs(dir.y), scene.sky.scattering);
+    return select(
+        mix(scene.sky.horizon_color, scene.sky.bottom_color, k),
+        mix(scene.sky.horizon_color, scene.sky.sky_color, k),
+        dir.y > 0.0
+    );
+}
+
+// Distance fog which thins out above the fog height. Aerial perspective takes the
+// sky color behind the point instead of the flat one.
+fn apply_fog(color: vec3f, position: vec3f) -> vec3f {
+    if scene.fog.mode == 0u {
+        return color;
+    }
+
+    let offset = position - scene.camera_pos;
+    let distance = length(offset);
+    let range = max(scene.fog.end - scene.fog.start, 0.0001);
+    let height = max(position.y - scene.fog.height, 0.0);
+    let k = clamp((distance - scene.fog.start) / range, 0.0, 1.0) *
+        exp(-height * scene.fog.height_falloff);
+
+    let fog_color = select(
+        scene.fog.color,
+        calc_sky_color(offset / max(distance, 0.0001)),
+        scene.fog.mode == 2u
+    );
+
+    return mix(color, fog_color, k);
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let out = VertexOutput(
+        scene.view_proj_matrix * vec4f(in.position, 1.0),
+        in.normal,
+        in.uv,
+        in.position
+    );
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    var w = textureSample(splatmap_texture, splatmap_sampler, in.uv);
+    w /= max(w.r + w.g + w.b + w.a, 0.0001);
+
+    let color =
+        textureSample(layer0_texture, layer_sampler, in.uv * splat.tiling.x).rgb * w.r +
+        textureSample(layer1_texture, layer_sampler, in.uv * splat.tiling.y).rgb * w.g +
+        textureSample(layer2_texture, layer_sampler, in.uv * splat.tiling.z).rgb * w.b +
+        textureSample(layer3_texture, layer_sampler, in.uv * splat.tiling.w).rgb * w.a;
+
+    return vec4f(apply_fog(calc_global_light(color, in.normal), in.position), 1.0);
+}
@@ -0,0 +1,152 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct SsaoUniform {
+    proj_matrix: mat4x4f,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    reversed_z: u32,
+    // x, y, width, height in pixels
+    viewport: vec4f
+}
+
+const SAMPLE_COUNT: u32 = 16u;
+const BLUR_SIZE: i32 = 2;
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(0) @binding(3)
+var depth_texture: texture_depth_2d;
+
+@group(1) @binding(0)
+var<uniform> ssao: SsaoUniform;
+
+@group(2) @binding(0)
+var ao_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f
+}
+
+fn hash(p: vec3f) -> vec3f {
+    var q = fract(p * vec3f(0.1031, 0.1030, 0.0973));
+    q += dot(q, q.yxz + 33.33);
+    return fract((q.xxy + q.yxx) * q.zyx);
+}
+
+fn view_position(coord: vec2i) -> vec3f {
+    let depth = textureLoad(depth_texture, coord, 0);
+    let uv = (vec2f(coord) + 0.5 - ssao.viewport.xy) / ssao.viewport.zw;
+    let ndc = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let p = scene.inv_proj_matrix * ndc;
+
+    return p.xyz / p.w;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+
+    return VertexOutput(vec4f(uv * 2.0 - 1.0, 0.0, 1.0));
+}
+
+@fragment
+fn fs_ao(in: VertexOutput) -> @location(0) vec4f {
+    let min_coord = vec2i(ssao.viewport.xy);
+    let max_coord = vec2i(ssao.viewport.xy + ssao.viewport.zw) - 1;
+    let coord = vec2i(in.clip_pos.xy);
+
+    let far_depth = select(1.0, 0.0, ssao.reversed_z != 0u);
+    if textureLoad(depth_texture, coord, 0) == far_depth {
+        return vec4f(1.0);
+    }
+
+    let p = view_position(coord);
+    let px = view_position(min(coord + vec2i(1, 0), max_coord));
+    let py = view_position(min(coord + vec2i(0, 1), max_coord));
+
+    var n = normalize(cross(px - p, py - p));
+    if n.z > 0.0 {
+        n = -n;
+    }
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        var s = normalize(hash(vec3f(in.clip_pos.xy, f32(i))) * 2.0 - 1.0);
+        if dot(s, n) < 0.0 {
+            s = -s;
+        }
+
+        let k = f32(i + 1u) / f32(SAMPLE_COUNT);
+        let sample_pos = p + s * ssao.radius * mix(0.1, 1.0, k * k);
+
+        let clip = ssao.proj_matrix * vec4f(sample_pos, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2f(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let sample_coord = clamp(
+            vec2i(ssao.viewport.xy + uv * ssao.viewport.zw),
+            min_coord,
+            max_coord
+        );
+        let scene_z = view_position(sample_coord).z;
+
+        let range = smoothstep(0.0, 1.0, ssao.radius / max(abs(p.z - scene_z), 0.0001));
+        occlusion += select(0.0, 1.0, scene_z <= sample_pos.z - ssao.bias) * range;
+    }
+
+    return vec4f(1.0 - occlusion / f32(SAMPLE_COUNT));
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4f {
+    let min_coord = vec2i(ssao.viewport.xy);
+    let max_coord = vec2i(ssao.viewport.xy + ssao.viewport.zw) - 1;
+    let coord = vec2i(in.clip_pos.xy);
+
+    var ao = 0.0;
+    for (var x = -BLUR_SIZE; x < BLUR_SIZE; x++) {
+        for (var y = -BLUR_SIZE; y < BLUR_SIZE; y++) {
+            let c = clamp(coord + vec2i(x, y), min_coord, max_coord);
+            ao += textureLoad(ao_texture, c, 0).r;
+        }
+    }
+    ao /= f32(4 * BLUR_SIZE * BLUR_SIZE);
+
+    return vec4f(vec3f(mix(1.0, ao, ssao.intensity)), 1.0);
+}
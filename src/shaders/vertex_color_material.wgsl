@@ -0,0 +1,25 @@
+struct VertexColorMaterialUniform {
+    use_normal_map: u32
+}
+
+@group(2) @binding(0)
+var normal_map_sampler: sampler;
+
+@group(2) @binding(1)
+var normal_map_texture: texture_2d<f32>;
+
+@group(2) @binding(2)
+var<uniform> material: VertexColorMaterialUniform;
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4f {
+    var n = surface_normal(in, front_facing);
+    if material.use_normal_map != 0u {
+        let t = normalize(in.tangent.xyz);
+        let b = cross(normalize(n), t) * in.tangent.w;
+        let tn = textureSample(normal_map_texture, normal_map_sampler, in.uv).xyz * 2.0 - 1.0;
+        n = normalize(t * tn.x + b * tn.y + normalize(n) * tn.z);
+    }
+
+    return shade_surface(in, in.color, n);
+}
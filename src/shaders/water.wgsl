@@ -0,0 +1,232 @@
+struct GlobalLight {
+    dir: vec3f,
+    color: vec3f
+}
+
+struct SkyUniform {
+    sky_color: vec3f,
+    scattering: f32,
+    horizon_color: vec3f,
+    bottom_color: vec3f
+}
+
+struct FogUniform {
+    color: vec3f,
+    mode: u32,
+    start: f32,
+    end: f32,
+    height: f32,
+    height_falloff: f32
+}
+
+struct SceneUniform {
+    view_proj_matrix: mat4x4f,
+    inv_proj_matrix: mat4x4f,
+    inv_view_proj_matrix: mat4x4f,
+    camera_dir: vec3f,
+    camera_pos: vec3f,
+    surface_size: vec2f,
+    near_plane: f32,
+    far_plane: f32,
+    global_light: GlobalLight,
+    ambient_light: vec3f,
+    time: f32,
+    sky: SkyUniform,
+    fog: FogUniform
+}
+
+struct WaterUniform {
+    specular: f32,
+    density: f32,
+    reversed_z: u32,
+    specular_color: vec3f,
+    alpha: f32,
+    wave_speed: vec2f,
+    wave_scale: vec2f,
+    wave_height: f32,
+    refraction_blur: f32,
+    mask_level: f32,
+    viewport_rect: vec4f
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+@group(0) @binding(2)
+var opaque_texture: texture_2d<f32>;
+
+@group(0) @binding(3)
+var depth_texture: texture_depth_2d;
+
+@group(1) @binding(0)
+var<uniform> water: WaterUniform;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) color: vec4f
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4f,
+    @location(1) @interpolate(perspective) position: vec3f,
+    @location(2) @interpolate(perspective) color: vec4f
+}
+
+fn random(v: vec2f) -> vec2f {
+    let d = vec2f(
+        dot(v, vec2f(127.1, 311.7)),
+        dot(v, vec2f(269.5, 183.3))
+    );
+
+    return -1.0 + 2.0 * fract(sin(d) * 43758.5453123);
+}
+
+fn noise(v: vec2f) -> f32 {
+    let i = floor(v);
+    let f = fract(v);
+
+    let u = f * f * (3.0 - 2.0 * f);
+    
+    return mix(
+        mix(
+            dot(random(i + vec2f(0.0, 0.0)), f - vec2f(0.0, 0.0)),
+            dot(random(i + vec2f(1.0, 0.0)), f - vec2f(1.0, 0.0)),
+            u.x
+        ),
+        mix(
+            dot(random(i + vec2f(0.0, 1.0)), f - vec2f(0.0, 1.0)),
+            dot(random(i + vec2f(1.0, 1.0)), f - vec2f(1.0, 1.0)),
+            u.x
+        ),
+        u.y
+    );
+}
+
+fn calc_global_light(color: vec3f, pos: vec3f, n: vec3f) -> vec3f {
+    let e = normalize(pos - scene.camera_pos);
+    let r = normalize(reflect(-scene.global_light.dir, n));
+
+    let k = scene.ambient_light +
+        scene.global_light.color * max(dot(n, -scene.global_light.dir), 0.0) +
+        water.specular_color * pow(max(dot(e, r), 0.0), water.specular);
+
+    return color * k;
+}
+
+fn calc_sky_color(dir: vec3f) -> vec3f {
+    let k = pow(abs(dir.y), scene.sky.scattering);
+    return select(
+        mix(scene.sky.horizon_color, scene.sky.bottom_color, k),
+        mix(scene.sky.horizon_color, scene.sky.sky_color, k),
+        dir.y > 0.0
+    );
+}
+
+// Distance fog which thins out above the fog height. Aerial perspective takes the
+// sky color behind the point instead of the flat one.
+fn apply_fog(color: vec3f, position: vec3f) -> vec3f {
+    if scene.fog.mode == 0u {
+        return color;
+    }
+
+    let offset = position - scene.camera_pos;
+    let distance = length(offset);
+    let range = max(scene.fog.end - scene.fog.start, 0.0001);
+    let height = max(position.y - scene.fog.height, 0.0);
+    let k = clamp((distance - scene.fog.start) / range, 0.0, 1.0) *
+        exp(-height * scene.fog.height_falloff);
+
+    let fog_color = select(
+        scene.fog.color,
+        calc_sky_color(offset / max(distance, 0.0001)),
+        scene.fog.mode == 2u
+    );
+
+    return mix(color, fog_color, k);
+}
+
+fn linearize_depth(raw_depth: f32) -> f32 {
+    // Reversed depth is exactly one minus the regular one
+    let depth = select(raw_depth, 1.0 - raw_depth, water.reversed_z != 0u);
+    return
+        (2.0 * scene.near_plane * scene.far_plane) /
+        (scene.far_plane + scene.near_plane - depth * (scene.far_plane - scene.near_plane));
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let h = noise(
+        in.position.xz * water.wave_scale + water.wave_speed * scene.time
+    ) * water.wave_height;
+
+    let p = in.position + vec3f(0.0, h, 0.0);
+    let np = scene.view_proj_matrix * vec4f(p, 1.0);
+
+    let out = VertexOutput(
+        np,
+        p,
+        in.color
+    );
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    let n = normalize(cross(
+        normalize(dpdx(in.position)),
+        normalize(dpdy(in.position))
+    ));
+
+    let uv = in.clip_pos.xy / scene.surface_size;
+    let depth = linearize_depth(
+        textureSample(depth_texture, scene_sampler, uv)
+    ) / scene.far_plane;
+    let curr_depth = linearize_depth(
+        in.clip_pos.z
+    ) / scene.far_plane;
+    let dist = depth - curr_depth;
+
+    let k = 1.0 - pow(2.0, -water.density * dist);
+
+    let color = apply_fog(calc_global_light(in.color.rgb, in.position, n), in.position);
+
+    // Deeper water scatters more, so the refracted bottom is taken from a blurrier mip
+    if water.refraction_blur > 0.0 {
+        let lod = max(dist, 0.0) * scene.far_plane * water.refraction_blur;
+        let refracted = textureSampleLevel(opaque_texture, scene_sampler, uv, lod).rgb;
+        return vec4f(mix(refracted, color, k * in.color.a), 1.0);
+    }
+
+    return vec4f(color, k * in.color.a);
+}
+
+@vertex
+fn vs_mask(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Marks pixels where the opaque geometry lies above the water. Seen from above the
+// water, such geometry is always in front of the surface.
+@fragment
+fn fs_mask(@builtin(position) clip_pos: vec4f) -> @location(0) vec4f {
+    let depth = textureLoad(depth_texture, vec2i(clip_pos.xy), 0);
+    let far_depth = select(1.0, 0.0, water.reversed_z != 0u);
+    if depth == far_depth || scene.camera_pos.y <= water.mask_level {
+        discard;
+    }
+
+    let uv = (clip_pos.xy - water.viewport_rect.xy) / water.viewport_rect.zw;
+    let ndc = vec4f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = scene.inv_view_proj_matrix * ndc;
+    if world.y / world.w <= water.mask_level {
+        discard;
+    }
+
+    return vec4f(0.0);
+}
@@ -0,0 +1,85 @@
+use glam::Vec3;
+
+use crate::core::error::TerrainError;
+
+/// Colors assigned to sorted positions, e.g. terrain heights.
+///
+/// A stepped ramp returns the color of the last stop at or below the sampled
+/// position, while a smooth one interpolates between the neighbouring stops. Values
+/// outside the stops are clamped to the first and the last color.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Vec3)>,
+    smooth: bool,
+}
+
+impl ColorRamp {
+    pub fn builder() -> ColorRampBuilder {
+        ColorRampBuilder {
+            stops: Vec::new(),
+            smooth: false,
+        }
+    }
+
+    pub fn solid(color: Vec3) -> ColorRamp {
+        ColorRamp {
+            stops: vec![(0.0, color)],
+            smooth: false,
+        }
+    }
+
+    pub fn stops(&self) -> &[(f32, Vec3)] {
+        &self.stops
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.smooth
+    }
+
+    pub fn sample(&self, t: f32) -> Vec3 {
+        let next = self.stops.partition_point(|(position, _)| *position <= t);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        if next == self.stops.len() || !self.smooth {
+            return self.stops[next - 1].1;
+        }
+
+        let (p0, c0) = self.stops[next - 1];
+        let (p1, c1) = self.stops[next];
+        c0.lerp(c1, (t - p0) / (p1 - p0))
+    }
+}
+
+pub struct ColorRampBuilder {
+    stops: Vec<(f32, Vec3)>,
+    smooth: bool,
+}
+
+impl ColorRampBuilder {
+    pub fn stop(mut self, position: f32, color: Vec3) -> ColorRampBuilder {
+        self.stops.push((position, color));
+        self
+    }
+
+    pub fn smooth(mut self, smooth: bool) -> ColorRampBuilder {
+        self.smooth = smooth;
+        self
+    }
+
+    pub fn build(self) -> Result<ColorRamp, TerrainError> {
+        if self.stops.is_empty() {
+            return Err(TerrainError::InvalidColorRamp("no stops".into()));
+        }
+        if !self.stops.windows(2).all(|w| w[0].0 < w[1].0) {
+            return Err(TerrainError::InvalidColorRamp(
+                "stop positions must be strictly increasing".into(),
+            ));
+        }
+
+        Ok(ColorRamp {
+            stops: self.stops,
+            smooth: self.smooth,
+        })
+    }
+}
@@ -0,0 +1,152 @@
+use std::{marker::PhantomData, mem};
+
+use bytemuck::{bytes_of, Pod};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
+    BufferBinding, BufferDescriptor, BufferSize, BufferUsages, Device, Queue,
+};
+
+use super::create_uniform_bind_group_layout;
+
+/// Uniform buffer of many values sharing one bind group, e.g. transforms of objects.
+/// Each value gets its own aligned slot, selected by the dynamic offset passed to
+/// `set_bind_group`.
+///
+/// Values are pushed every frame, then uploaded at once with `flush`, which rewinds
+/// the buffer to the first slot. The buffer grows when more values are pushed than
+/// it fits, so the bind group should be taken again after flushing.
+pub struct DynamicUniformBuffer<T: Pod> {
+    buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    stride: u64,
+    capacity: u32,
+    staging: Vec<u8>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> DynamicUniformBuffer<T> {
+    pub fn new(device: &Device, capacity: u32) -> DynamicUniformBuffer<T> {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = (mem::size_of::<T>() as u64).next_multiple_of(alignment);
+        let capacity = capacity.max(1);
+
+        let bind_group_layout =
+            create_uniform_bind_group_layout(device, true, BufferSize::new(Self::value_size()));
+        let (buffer, bind_group) =
+            Self::create_buffer(device, &bind_group_layout, stride, capacity);
+
+        DynamicUniformBuffer {
+            buffer,
+            bind_group_layout,
+            bind_group,
+            stride,
+            capacity,
+            staging: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Stores the value in the next slot, returning its dynamic offset
+    pub fn push(&mut self, value: &T) -> u32 {
+        let offset = self.staging.len();
+        self.staging.extend_from_slice(bytes_of(value));
+        self.staging.resize(offset + self.stride as usize, 0);
+
+        offset as u32
+    }
+
+    /// Uploads the values pushed since the last call, growing the buffer if needed
+    pub fn flush(&mut self, device: &Device, queue: &Queue) {
+        if self.staging.is_empty() {
+            return;
+        }
+
+        let len = self.len();
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            (self.buffer, self.bind_group) =
+                Self::create_buffer(device, &self.bind_group_layout, self.stride, self.capacity);
+        }
+
+        queue.write_buffer(&self.buffer, 0, &self.staging);
+        self.staging.clear();
+    }
+
+    /// Grows the buffer to fit at least `capacity` values, dropping its contents if it
+    /// gets replaced. Returns whether it did.
+    pub fn reserve(&mut self, device: &Device, capacity: u32) -> bool {
+        if capacity <= self.capacity {
+            return false;
+        }
+
+        self.capacity = capacity.next_power_of_two();
+        (self.buffer, self.bind_group) =
+            Self::create_buffer(device, &self.bind_group_layout, self.stride, self.capacity);
+
+        true
+    }
+
+    /// Number of values pushed since the last flush
+    pub fn len(&self) -> u32 {
+        (self.staging.len() as u64 / self.stride) as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staging.is_empty()
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Distance between the offsets of neighbouring values
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    fn value_size() -> u64 {
+        mem::size_of::<T>() as u64
+    }
+
+    fn create_buffer(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        stride: u64,
+        capacity: u32,
+    ) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: stride * capacity as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: BufferSize::new(Self::value_size()),
+                }),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+}
@@ -0,0 +1,173 @@
+use glam::Vec2;
+
+use super::XorShift;
+
+#[derive(Clone, Copy)]
+pub struct ErosionSettings {
+    pub iterations: u32,
+    pub seed: u32,
+    pub max_lifetime: u32,
+    pub inertia: f32,
+    pub capacity: f32,
+    pub min_capacity: f32,
+    pub deposition: f32,
+    pub erosion: f32,
+    pub evaporation: f32,
+    pub gravity: f32,
+}
+
+impl Default for ErosionSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 5000,
+            seed: 0,
+            max_lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+        }
+    }
+}
+
+/// Simulates water droplets running down a square height grid of `size * size`
+/// points stored row by row, moving sediment from steep slopes into pits.
+pub fn erode(heights: &mut [f32], size: usize, settings: &ErosionSettings) {
+    if size < 2 {
+        return;
+    }
+
+    let max_pos = (size - 1) as f32;
+    let mut rng = XorShift::new(settings.seed);
+
+    for _ in 0..settings.iterations {
+        let mut pos = Vec2::new(rng.next_f32() * max_pos, rng.next_f32() * max_pos);
+        let mut dir = Vec2::ZERO;
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..settings.max_lifetime {
+            let cell = pos.floor();
+            let offset = pos - cell;
+            let (height, gradient) = height_and_gradient(heights, size, pos);
+
+            dir = dir * settings.inertia - gradient * (1.0 - settings.inertia);
+            if dir.length_squared() == 0.0 {
+                break;
+            }
+            pos += dir.normalize();
+
+            if pos.x < 0.0 || pos.y < 0.0 || pos.x >= max_pos || pos.y >= max_pos {
+                break;
+            }
+
+            let delta_height = height_and_gradient(heights, size, pos).0 - height;
+            let capacity =
+                (-delta_height * speed * water * settings.capacity).max(settings.min_capacity);
+
+            if sediment > capacity || delta_height > 0.0 {
+                let amount = if delta_height > 0.0 {
+                    delta_height.min(sediment)
+                } else {
+                    (sediment - capacity) * settings.deposition
+                };
+                sediment -= amount;
+                apply_to_cell(heights, size, cell, offset, amount);
+            } else {
+                let amount = ((capacity - sediment) * settings.erosion).min(-delta_height);
+                sediment += amount;
+                apply_to_cell(heights, size, cell, offset, -amount);
+            }
+
+            speed = (speed * speed + delta_height * settings.gravity)
+                .max(0.0)
+                .sqrt();
+            water *= 1.0 - settings.evaporation;
+        }
+    }
+}
+
+fn height_and_gradient(heights: &[f32], size: usize, pos: Vec2) -> (f32, Vec2) {
+    let x = pos.x as usize;
+    let y = pos.y as usize;
+    let offset = pos - pos.floor();
+
+    let h00 = heights[y * size + x];
+    let h10 = heights[y * size + x + 1];
+    let h01 = heights[(y + 1) * size + x];
+    let h11 = heights[(y + 1) * size + x + 1];
+
+    let gradient = Vec2::new(
+        (h10 - h00) * (1.0 - offset.y) + (h11 - h01) * offset.y,
+        (h01 - h00) * (1.0 - offset.x) + (h11 - h10) * offset.x,
+    );
+    let height = h00 * (1.0 - offset.x) * (1.0 - offset.y)
+        + h10 * offset.x * (1.0 - offset.y)
+        + h01 * (1.0 - offset.x) * offset.y
+        + h11 * offset.x * offset.y;
+
+    (height, gradient)
+}
+
+fn apply_to_cell(heights: &mut [f32], size: usize, cell: Vec2, offset: Vec2, amount: f32) {
+    let x = cell.x as usize;
+    let y = cell.y as usize;
+
+    heights[y * size + x] += amount * (1.0 - offset.x) * (1.0 - offset.y);
+    heights[y * size + x + 1] += amount * offset.x * (1.0 - offset.y);
+    heights[(y + 1) * size + x] += amount * (1.0 - offset.x) * offset.y;
+    heights[(y + 1) * size + x + 1] += amount * offset.x * offset.y;
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::{NoiseFn, Perlin};
+
+    use super::*;
+
+    const SIZE: usize = 64;
+
+    fn noise_grid() -> Vec<f32> {
+        let noise = Perlin::new(42);
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let (x, z) = ((i % SIZE) as f64 * 0.1, (i / SIZE) as f64 * 0.1);
+                noise.get([x, z]) as f32 * 4.0
+            })
+            .collect()
+    }
+
+    fn variance(heights: &[f32]) -> f32 {
+        let mean = heights.iter().sum::<f32>() / heights.len() as f32;
+        heights.iter().map(|h| (h - mean).powi(2)).sum::<f32>() / heights.len() as f32
+    }
+
+    #[test]
+    fn erosion_reduces_variance() {
+        let original = noise_grid();
+        let mut eroded = original.clone();
+        erode(&mut eroded, SIZE, &ErosionSettings::default());
+
+        assert_ne!(eroded, original);
+        assert!(
+            variance(&eroded) < variance(&original),
+            "{} >= {}",
+            variance(&eroded),
+            variance(&original)
+        );
+    }
+
+    #[test]
+    fn erosion_is_deterministic() {
+        let mut a = noise_grid();
+        let mut b = noise_grid();
+        erode(&mut a, SIZE, &ErosionSettings::default());
+        erode(&mut b, SIZE, &ErosionSettings::default());
+
+        assert_eq!(a, b);
+    }
+}
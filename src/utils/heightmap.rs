@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageBuffer, Luma};
+use noise::NoiseFn;
+
+use crate::core::error::TerrainError;
+
+/// Grayscale heightmap sampled with bilinear filtering.
+///
+/// Sample coordinates are normalized UVs in `[0, 1]`: `u` runs along the image
+/// columns (left to right) and `v` along the image rows (top to bottom). Values
+/// are the pixel luminance remapped to `[0, 1]`.
+#[derive(Clone)]
+pub struct Heightmap {
+    image: ImageBuffer<Luma<u16>, Vec<u16>>,
+}
+
+impl Heightmap {
+    pub fn from_image(image: &DynamicImage) -> Heightmap {
+        Heightmap {
+            image: image.to_luma16(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Heightmap, TerrainError> {
+        Ok(Self::from_image(&image::open(path)?))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = u.clamp(0.0, 1.0) * (self.width() - 1) as f32;
+        let y = v.clamp(0.0, 1.0) * (self.height() - 1) as f32;
+
+        let x0 = x.floor() as u32;
+        let y0 = y.floor() as u32;
+        let x1 = (x0 + 1).min(self.width() - 1);
+        let y1 = (y0 + 1).min(self.height() - 1);
+        let tx = x.fract();
+        let ty = y.fract();
+
+        let h00 = self.pixel(x0, y0);
+        let h10 = self.pixel(x1, y0);
+        let h01 = self.pixel(x0, y1);
+        let h11 = self.pixel(x1, y1);
+
+        let top = h00 + (h10 - h00) * tx;
+        let bottom = h01 + (h11 - h01) * tx;
+
+        top + (bottom - top) * ty
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> f32 {
+        self.image.get_pixel(x, y).0[0] as f32 / u16::MAX as f32
+    }
+}
+
+impl NoiseFn<f64, 2> for Heightmap {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.sample(point[0] as f32, point[1] as f32) as f64
+    }
+}
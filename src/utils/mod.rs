@@ -1,16 +1,32 @@
+use std::{iter, sync::mpsc};
+
 use bytemuck::{bytes_of, Pod};
-use glam::Vec3;
+use glam::{Vec2, Vec3, Vec4};
+use image::DynamicImage;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType,
-    BufferUsages, Device, Extent3d, ImageCopyTexture, Origin3d, ShaderStages, Texture,
-    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    BufferDescriptor, BufferSize, BufferUsages, Device, Extent3d, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, LoadOp, Maintain, MapMode, Operations, Origin3d, Queue,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, Sampler, SamplerBindingType,
+    ShaderStages, StoreOp, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
-use crate::render::{renderer::RenderingContext, vertex::Vertex};
+use crate::{
+    core::error::TerrainError,
+    render::{renderer::RenderingContext, vertex::Vertex},
+};
 
+pub mod color_ramp;
+pub mod dynamic_uniform;
+pub mod erosion;
+pub mod heightmap;
+pub mod pipeline;
 pub mod terrain_generator;
+pub mod terrain_noise;
 
 pub fn create_texture_2d(
     device: &Device,
@@ -18,6 +34,17 @@ pub fn create_texture_2d(
     width: u32,
     height: u32,
     usage: TextureUsages,
+) -> Texture {
+    create_texture_2d_with_mips(device, format, width, height, 1, usage)
+}
+
+pub fn create_texture_2d_with_mips(
+    device: &Device,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    usage: TextureUsages,
 ) -> Texture {
     device.create_texture(&TextureDescriptor {
         label: None,
@@ -26,7 +53,7 @@ pub fn create_texture_2d(
             height,
             depth_or_array_layers: 1,
         },
-        mip_level_count: 1,
+        mip_level_count,
         sample_count: 1,
         dimension: TextureDimension::D2,
         format,
@@ -35,6 +62,87 @@ pub fn create_texture_2d(
     })
 }
 
+pub fn create_multisampled_texture_2d(
+    device: &Device,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    usage: TextureUsages,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+pub fn create_texture_2d_init(
+    device: &Device,
+    queue: &Queue,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Texture {
+    let texture = create_texture_2d(
+        device,
+        format,
+        width,
+        height,
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+    );
+
+    queue.write_texture(
+        ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        data,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(format.block_copy_size(None).unwrap_or(4) * width),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    texture
+}
+
+pub fn create_texture_from_image(
+    device: &Device,
+    queue: &Queue,
+    image: &DynamicImage,
+    format: TextureFormat,
+) -> Texture {
+    let image = image.to_rgba8();
+
+    create_texture_2d_init(
+        device,
+        queue,
+        format,
+        image.width(),
+        image.height(),
+        image.as_raw(),
+    )
+}
+
 pub fn copy_textures_2d(context: &RenderingContext, source: &Texture, target: &Texture) {
     context
         .encoder()
@@ -62,6 +170,97 @@ pub fn copy_textures_2d(context: &RenderingContext, source: &Texture, target: &T
         );
 }
 
+/// Fills the depth texture with `depth` and its stencil, if any, with zero. The texture
+/// must be usable as a render attachment.
+pub fn clear_depth_texture(device: &Device, queue: &Queue, texture: &Texture, depth: f32) {
+    let view = texture.create_view(&Default::default());
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[],
+        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+            view: &view,
+            depth_ops: Some(Operations {
+                load: LoadOp::Clear(depth),
+                store: StoreOp::Store,
+            }),
+            stencil_ops: texture.format().has_stencil_aspect().then_some(Operations {
+                load: LoadOp::Clear(0),
+                store: StoreOp::Store,
+            }),
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+
+    queue.submit(iter::once(encoder.finish()));
+}
+
+/// Reads the first mip level of the texture back into tightly packed rows, blocking
+/// until the GPU finishes the copy
+pub fn read_texture_2d(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+) -> Result<Vec<u8>, TerrainError> {
+    let (width, height) = (texture.width(), texture.height());
+    let row_size = texture.format().block_copy_size(None).unwrap_or(4) * width;
+    // Buffer copies require rows aligned to 256 bytes
+    let padded_row_size =
+        row_size.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (padded_row_size * height) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_size),
+                rows_per_image: Some(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(iter::once(encoder.finish()));
+
+    let (sender, receiver) = mpsc::channel();
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(Maintain::Wait);
+    if let Ok(result) = receiver.recv() {
+        result?;
+    }
+
+    let data = slice
+        .get_mapped_range()
+        .chunks(padded_row_size as usize)
+        .flat_map(|row| &row[..row_size as usize])
+        .copied()
+        .collect();
+    buffer.unmap();
+
+    Ok(data)
+}
+
 pub fn create_uniform_init(
     uniform: &impl Pod,
     device: &Device,
@@ -72,19 +271,7 @@ pub fn create_uniform_init(
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
     });
 
-    let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: None,
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::all(),
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-    });
+    let bind_group_layout = create_uniform_bind_group_layout(device, false, None);
 
     let bind_group = device.create_bind_group(&BindGroupDescriptor {
         label: None,
@@ -102,10 +289,82 @@ pub fn create_uniform_init(
     (buffer, bind_group_layout, bind_group)
 }
 
+/// Layout of a single uniform buffer at binding 0, visible to all stages
+pub fn create_uniform_bind_group_layout(
+    device: &Device,
+    has_dynamic_offset: bool,
+    min_binding_size: Option<BufferSize>,
+) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::all(),
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size,
+            },
+            count: None,
+        }],
+    })
+}
+
+pub fn create_texture_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+pub fn create_texture_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    view: &TextureView,
+    sampler: &Sampler,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Sampler(sampler),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(view),
+            },
+        ],
+    })
+}
+
+/// The normal is `(p2 - p0) × (p1 - p0)`, so triangles wound counter-clockwise when
+/// seen from above face +Y. Generated terrain keeps this convention, which the
+/// builtin shaders expect: they light surfaces by `dot(n, -light_direction)` with the
+/// light direction pointing the way the light travels.
 pub fn create_triangle_plane(points: [Vec3; 3], color: Vec3) -> [Vertex; 3] {
     let a = points[1] - points[0];
     let b = points[2] - points[0];
-    let n = a.cross(b);
+    // Degenerate triangles have no direction to face, so they're lit as flat ground
+    let n = b.cross(a).try_normalize().unwrap_or(Vec3::Y);
 
     [
         Vertex::new(points[0], n, color),
@@ -113,3 +372,196 @@ pub fn create_triangle_plane(points: [Vec3; 3], color: Vec3) -> [Vertex; 3] {
         Vertex::new(points[2], n, color),
     ]
 }
+
+/// Decodes with the exact sRGB curve, the one sRGB textures and surfaces use, so
+/// converted colors match the encoded ones rather than a 2.2 gamma approximation
+pub fn srgb_to_linear(color: Vec3) -> Vec3 {
+    color.to_array().map(srgb_channel_to_linear).into()
+}
+
+pub fn linear_to_srgb(color: Vec3) -> Vec3 {
+    color.to_array().map(linear_channel_to_srgb).into()
+}
+
+fn srgb_channel_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_channel_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let (v0, v1, v2) = (vertices[i0], vertices[i1], vertices[i2]);
+
+        let e1 = v1.position - v0.position;
+        let e2 = v2.position - v0.position;
+        let d1: Vec2 = v1.uv - v0.uv;
+        let d2: Vec2 = v2.uv - v0.uv;
+
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+
+        let t = (e1 * d2.y - e2 * d1.y) * r;
+        let b = (e2 * d1.x - e1 * d2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += t;
+            bitangents[i] += b;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let n = vertex.normal.normalize_or_zero();
+        let t = (tangents[i] - n * n.dot(tangents[i])).normalize_or_zero();
+        let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+
+        vertex.tangent = Vec4::new(t.x, t.y, t.z, w);
+    }
+}
+
+/// Small seeded generator, so random placement is reproducible
+pub(crate) struct XorShift(u32);
+
+impl XorShift {
+    pub fn new(seed: u32) -> XorShift {
+        // Zero state would only produce zeros
+        XorShift(seed.wrapping_mul(0x9E37_79B9) | 1)
+    }
+
+    /// Uniform value in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Device of any available adapter, including software ones. Tests needing the GPU
+/// are skipped without it.
+#[cfg(test)]
+pub(crate) fn test_device() -> Option<(Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter =
+        pollster::block_on(instance.request_adapter(&Default::default())).or_else(|| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            }))
+        })?;
+
+    pollster::block_on(adapter.request_device(&Default::default(), None)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::skybox_renderer::SkyboxRendererSettings;
+
+    use super::*;
+
+    #[test]
+    fn srgb_conversion_matches_reference_values() {
+        let linear = srgb_to_linear(Vec3::new(0.0, 0.5, 1.0));
+        assert!(linear.abs_diff_eq(Vec3::new(0.0, 0.214_041, 1.0), 1e-6));
+
+        // Both pieces of the curve meet at the threshold
+        let below = srgb_channel_to_linear(0.04045);
+        let above = ((0.04045f32 + 0.055) / 1.055).powf(2.4);
+        assert!((below - above).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=255 {
+            let c = Vec3::splat(i as f32 / 255.0);
+            let round_trip = linear_to_srgb(srgb_to_linear(c));
+            assert!(round_trip.abs_diff_eq(c, 1e-5), "{c} -> {round_trip}");
+
+            let round_trip = srgb_to_linear(linear_to_srgb(c));
+            assert!(round_trip.abs_diff_eq(c, 1e-5), "{c} -> {round_trip}");
+        }
+    }
+
+    #[test]
+    fn sky_pixel_is_encoded_by_srgb_target() {
+        let Some((device, queue)) = test_device() else {
+            eprintln!("No adapter available, skipping");
+            return;
+        };
+
+        // The skybox converts the authored colors to linear and outputs them as is
+        let horizon_color = SkyboxRendererSettings::default().horizon_color;
+        let linear = srgb_to_linear(horizon_color);
+
+        let texture = create_texture_2d(
+            &device,
+            TextureFormat::Rgba8UnormSrgb,
+            1,
+            1,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+        let view = texture.create_view(&Default::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: linear.x as f64,
+                        g: linear.y as f64,
+                        b: linear.z as f64,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        queue.submit(iter::once(encoder.finish()));
+
+        let pixel = read_texture_2d(&device, &queue, &texture).unwrap();
+        let expected = linear_to_srgb(linear) * 255.0;
+        for (channel, expected) in pixel.iter().zip(expected.to_array()) {
+            assert!(
+                (*channel as f32 - expected).abs() <= 1.0,
+                "{pixel:?} != {expected}"
+            );
+        }
+    }
+}
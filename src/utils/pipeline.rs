@@ -0,0 +1,20 @@
+use wgpu::{Face, FrontFace, IndexFormat, PolygonMode, PrimitiveState, PrimitiveTopology};
+
+/// Strip topologies take the `u32` indices meshes have, restarting strips at
+/// `STRIP_RESTART_INDEX`
+pub fn primitive_state(
+    topology: PrimitiveTopology,
+    cull_mode: Option<Face>,
+    polygon_mode: PolygonMode,
+    front_face: FrontFace,
+) -> PrimitiveState {
+    PrimitiveState {
+        topology,
+        strip_index_format: topology.is_strip().then_some(IndexFormat::Uint32),
+        front_face,
+        cull_mode,
+        unclipped_depth: false,
+        polygon_mode,
+        conservative: false,
+    }
+}
@@ -1,22 +1,71 @@
-use glam::{Vec3, Vec3Swizzles};
-use noise::{NoiseFn, Perlin};
-use wgpu::Device;
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+};
 
-use crate::render::{mesh::Mesh, vertex::Vertex};
+use glam::{DVec2, Vec2, Vec3, Vec4};
+use noise::{Billow, MultiFractal, NoiseFn, Perlin, RidgedMulti, Seedable};
+use rayon::prelude::*;
+use wgpu::{Device, PrimitiveTopology};
 
-use super::create_triangle_plane;
+use crate::{
+    core::error::TerrainError,
+    render::{
+        mesh::{Mesh, MeshData, STRIP_RESTART_INDEX},
+        vertex::Vertex,
+    },
+};
 
+use super::{
+    color_ramp::ColorRamp,
+    compute_tangents, create_triangle_plane,
+    erosion::{erode, ErosionSettings},
+    heightmap::Heightmap,
+    srgb_to_linear,
+    terrain_noise::{NoiseKind, TerrainNoise},
+};
+
+#[derive(Clone)]
 pub struct TerrainSettings<T>
 where
     T: NoiseFn<f64, 2>,
 {
+    /// Width of a tile, ignored when `world_size` is set
     pub tile_size: f32,
     pub tiles_count: u32,
-    pub colors: Box<[Vec3]>,
-    pub colors_thresholds: Box<[f32]>,
+    /// Extent of the whole terrain along X and Z. When set, tiles are
+    /// `world_size / tiles_count` wide, so `tiles_count` only picks the mesh resolution
+    /// and the terrain keeps its shape. Noise is sampled at world positions times
+    /// `scale` either way, which keeps features at the same world size.
+    pub world_size: Option<f32>,
+    /// World position of the grid corner the tiles extend from in +X and +Z. Heights
+    /// are offset by its Y.
+    pub origin: Vec3,
+    /// Sampled by height
+    pub colors: ColorRamp,
+    pub cliff_color: Vec3,
+    pub slope_threshold: f32,
+    pub slope_blend: f32,
+    /// Brightness jitter between tiles, as a fraction of the color. Tiles are hashed by
+    /// their grid coordinates, so regenerated terrain keeps the same pattern.
+    pub color_variation: f32,
     pub noise: T,
     pub scale: f32,
+    /// Offsets the noise sample points by the noise itself sampled at `warp_scale`
+    /// times their coordinates, bending features into swirls. It's measured in noise
+    /// coordinates, i.e. world distances multiplied by `scale`, so 0 disables warping.
+    pub warp_strength: f32,
+    pub warp_scale: f64,
     pub max_height: f32,
+    pub uv_scale: f32,
+    pub linear_colors: bool,
+    pub erosion: Option<ErosionSettings>,
+    /// `TriangleList` gives flat shaded triangles, while `TriangleStrip` shares grid
+    /// vertices between triangles, which are smooth shaded then.
+    pub topology: PrimitiveTopology,
+    /// Top faces are wound counter-clockwise when seen from above, matching the
+    /// builtin pipelines. Flipping makes them clockwise, keeping the normals.
+    pub flip_winding: bool,
 }
 
 impl Default for TerrainSettings<Perlin> {
@@ -24,84 +73,1063 @@ impl Default for TerrainSettings<Perlin> {
         Self {
             tile_size: 0.75,
             tiles_count: 15,
-            colors: vec![
-                Vec3::new(0.94, 0.85, 0.09),
-                Vec3::new(0.47, 0.83, 0.22),
-                Vec3::new(0.95, 0.95, 0.95),
-            ]
-            .into_boxed_slice(),
-            colors_thresholds: vec![-0.25, 0.5].into_boxed_slice(),
+            world_size: None,
+            origin: Vec3::ZERO,
+            colors: ColorRamp::builder()
+                .stop(-1.0, Vec3::new(0.94, 0.85, 0.09))
+                .stop(-0.25, Vec3::new(0.47, 0.83, 0.22))
+                .stop(0.5, Vec3::new(0.95, 0.95, 0.95))
+                .build()
+                .unwrap(),
+            cliff_color: Vec3::new(0.45, 0.4, 0.36),
+            slope_threshold: 0.35,
+            slope_blend: 0.1,
+            color_variation: 0.0,
             noise: Perlin::new(Perlin::DEFAULT_SEED),
             scale: 0.2,
+            warp_strength: 0.0,
+            warp_scale: 0.5,
             max_height: 1.0,
+            uv_scale: 1.0,
+            linear_colors: true,
+            erosion: None,
+            topology: PrimitiveTopology::TriangleList,
+            flip_winding: false,
+        }
+    }
+}
+
+impl Default for TerrainSettings<TerrainNoise> {
+    fn default() -> Self {
+        TerrainSettings::<Perlin>::default().with_noise(TerrainNoise::default())
+    }
+}
+
+impl TerrainSettings<Perlin> {
+    /// Default settings with Perlin noise seeded by `seed`. Generation is
+    /// deterministic, so the same settings always give the same terrain.
+    pub fn with_seed(seed: u32) -> Self {
+        Self::default().seeded(seed)
+    }
+}
+
+impl TerrainSettings<TerrainNoise> {
+    /// Default settings with the noise picked by `kind`, seeded by `seed`
+    pub fn with_noise_kind(kind: NoiseKind, seed: u32) -> Self {
+        Self::default()
+            .with_noise(TerrainNoise::new(kind, seed))
+            .seeded(seed)
+    }
+
+    /// Mountains with sharp peaks. Every octave of the ridged noise takes
+    /// `1 - |noise|`, which turns zero crossings into ridges.
+    pub fn ridged(seed: u32) -> Self {
+        Self {
+            noise: TerrainNoise::Ridged(RidgedMulti::new(seed).set_octaves(5)),
+            max_height: 1.5,
+            colors: ColorRamp::builder()
+                .stop(-1.5, Vec3::new(0.36, 0.55, 0.24))
+                .stop(-0.4, Vec3::new(0.5, 0.47, 0.43))
+                .stop(0.6, Vec3::new(0.96, 0.96, 0.98))
+                .build()
+                .unwrap(),
+            cliff_color: Vec3::new(0.38, 0.35, 0.33),
+            slope_threshold: 0.45,
+            ..Self::default()
+        }
+    }
+
+    /// Rolling hills of rounded billow noise
+    pub fn billow(seed: u32) -> Self {
+        Self {
+            noise: TerrainNoise::Billow(
+                Billow::new(seed)
+                    .set_octaves(3)
+                    .set_frequency(0.8)
+                    .set_persistence(0.4),
+            ),
+            max_height: 0.6,
+            colors: ColorRamp::builder()
+                .stop(-0.6, Vec3::new(0.86, 0.8, 0.45))
+                .stop(-0.2, Vec3::new(0.42, 0.7, 0.25))
+                .stop(0.3, Vec3::new(0.3, 0.58, 0.2))
+                .build()
+                .unwrap(),
+            ..Self::default()
         }
     }
 }
 
-pub fn generate_terrain_mesh<T>(device: &Device, settings: &TerrainSettings<T>) -> Mesh
+impl<T> TerrainSettings<T>
 where
     T: NoiseFn<f64, 2>,
 {
-    let mut vertices = Vec::<Vertex>::new();
-    let mut indices = Vec::<u16>::new();
+    /// Checks that `generate_terrain_mesh` can build a mesh of the whole terrain,
+    /// whose vertices are indexed with 32 bits
+    pub fn validate(&self) -> Result<(), TerrainError> {
+        if self.tiles_count == 0 {
+            return Err(TerrainError::InvalidTerrainSettings(
+                "tiles count must be positive".into(),
+            ));
+        }
+        let tile_size = self.effective_tile_size();
+        if !(tile_size > 0.0 && tile_size.is_finite()) {
+            return Err(TerrainError::InvalidTerrainSettings(format!(
+                "tile size must be positive, but it's {}",
+                tile_size
+            )));
+        }
+
+        let tiles_count = self.tiles_count as u64;
+        let (vertices_count, max_vertices_count) = match self.topology {
+            PrimitiveTopology::TriangleStrip => {
+                ((tiles_count + 1).pow(2), STRIP_RESTART_INDEX as u64)
+            }
+            _ => (tiles_count.pow(2) * 6, u32::MAX as u64 + 1),
+        };
+        if vertices_count > max_vertices_count {
+            return Err(TerrainError::InvalidTerrainSettings(format!(
+                "{} tiles give {} vertices, but a {:?} mesh holds at most {}",
+                self.tiles_count, vertices_count, self.topology, max_vertices_count
+            )));
+        }
+
+        Ok(())
+    }
 
-    fn apply_noise<T>(v: &mut Vec3, settings: &TerrainSettings<T>)
+    /// Width of a tile, derived from `world_size` when it's set
+    pub fn effective_tile_size(&self) -> f32 {
+        self.world_size.map_or(self.tile_size, |world_size| {
+            world_size / self.tiles_count as f32
+        })
+    }
+
+    /// Same settings over another noise
+    pub fn with_noise<U>(self, noise: U) -> TerrainSettings<U>
     where
-        T: NoiseFn<f64, 2>,
+        U: NoiseFn<f64, 2>,
     {
-        v.y = settings
-            .noise
-            .get((v.xz() * settings.scale).as_dvec2().to_array()) as f32
-            * settings.max_height;
+        TerrainSettings {
+            tile_size: self.tile_size,
+            tiles_count: self.tiles_count,
+            world_size: self.world_size,
+            origin: self.origin,
+            colors: self.colors,
+            cliff_color: self.cliff_color,
+            slope_threshold: self.slope_threshold,
+            slope_blend: self.slope_blend,
+            color_variation: self.color_variation,
+            noise,
+            scale: self.scale,
+            warp_strength: self.warp_strength,
+            warp_scale: self.warp_scale,
+            max_height: self.max_height,
+            uv_scale: self.uv_scale,
+            linear_colors: self.linear_colors,
+            erosion: self.erosion,
+            topology: self.topology,
+            flip_winding: self.flip_winding,
+        }
+    }
+}
+
+/// Seeds apply to the current `noise`, so swapping it for another noise replaces the
+/// seeding too.
+impl<T> TerrainSettings<T>
+where
+    T: NoiseFn<f64, 2> + Seedable,
+{
+    pub fn seed(&self) -> u32 {
+        self.noise.seed()
     }
 
-    fn calc_triangle_color<T>(points: [Vec3; 3], settings: &TerrainSettings<T>) -> Vec3
+    /// Reseeds the noise and the erosion, if it's enabled
+    pub fn seeded(self, seed: u32) -> Self {
+        Self {
+            noise: self.noise.set_seed(seed),
+            erosion: self
+                .erosion
+                .map(|erosion| ErosionSettings { seed, ..erosion }),
+            ..self
+        }
+    }
+}
+
+pub struct HeightmapTerrainSettings {
+    /// Width of a tile, ignored when `world_size` is set
+    pub tile_size: f32,
+    pub tiles_count: u32,
+    /// See `TerrainSettings::world_size`
+    pub world_size: Option<f32>,
+    /// World position of the grid corner the tiles extend from in +X and +Z. Heights
+    /// are offset by its Y.
+    pub origin: Vec3,
+    /// Sampled by height
+    pub colors: ColorRamp,
+    pub cliff_color: Vec3,
+    pub slope_threshold: f32,
+    pub slope_blend: f32,
+    /// See `TerrainSettings::color_variation`
+    pub color_variation: f32,
+    pub heightmap: Heightmap,
+    pub max_height: f32,
+    pub uv_scale: f32,
+    pub linear_colors: bool,
+    pub erosion: Option<ErosionSettings>,
+    /// `TriangleList` gives flat shaded triangles, while `TriangleStrip` shares grid
+    /// vertices between triangles, which are smooth shaded then.
+    pub topology: PrimitiveTopology,
+    /// See `TerrainSettings::flip_winding`
+    pub flip_winding: bool,
+}
+
+/// World space box enclosing a generated terrain. Its vertical extent is the actual
+/// height range of the terrain rather than the nominal `max_height`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TerrainBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl TerrainBounds {
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+}
+
+/// Height grid of a generated terrain, queried in world space. The heights match the
+/// triangles of the list mesh.
+#[derive(Clone)]
+pub struct TerrainHeights {
+    heights: Vec<f32>,
+    origin: Vec3,
+    tile_size: f32,
+    tiles_count: u32,
+}
+
+impl TerrainHeights {
+    pub fn new<T>(settings: &TerrainSettings<T>) -> TerrainHeights
     where
-        T: NoiseFn<f64, 2>,
+        T: NoiseFn<f64, 2> + Sync,
     {
-        let h = ((points[0] + points[1] + points[2]) / 3.0).y;
-        for i in 0..settings.colors_thresholds.len() {
-            if h < settings.colors_thresholds[i] {
-                return settings.colors[i];
+        TerrainHeights {
+            heights: generate_height_grid(settings),
+            origin: settings.origin,
+            tile_size: settings.effective_tile_size(),
+            tiles_count: settings.tiles_count,
+        }
+    }
+
+    /// Height of the terrain surface at a world XZ position, or `None` outside the
+    /// terrain
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let size = self.tiles_count as f32 * self.tile_size;
+        let (lx, lz) = (x - self.origin.x, z - self.origin.z);
+
+        ((0.0..=size).contains(&lx) && (0.0..=size).contains(&lz)).then(|| self.sample(x, z))
+    }
+
+    /// Like `height_at`, but positions outside the terrain are clamped to its edges
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let last = self.tiles_count.saturating_sub(1);
+        let gx = ((x - self.origin.x) / self.tile_size).clamp(0.0, self.tiles_count as f32);
+        let gz = ((z - self.origin.z) / self.tile_size).clamp(0.0, self.tiles_count as f32);
+        let (x0, z0) = ((gx as u32).min(last), (gz as u32).min(last));
+        let (fx, fz) = (gx - x0 as f32, gz - z0 as f32);
+
+        let grid_size = self.tiles_count as usize + 1;
+        let h = |dx: u32, dz: u32| {
+            self.heights[(z0 + dz) as usize * grid_size + (x0 + dx) as usize] + self.origin.y
+        };
+        let (h00, h10, h11, h01) = (h(0, 0), h(1, 0), h(1, 1), h(0, 1));
+
+        // Tiles are split along the diagonal from (x0, z0) to (x1, z1)
+        if fx >= fz {
+            h00 + fx * (h10 - h00) + fz * (h11 - h10)
+        } else {
+            h00 + fz * (h01 - h00) + fx * (h11 - h01)
+        }
+    }
+}
+
+/// Generates terrain by stretching the heightmap over the whole tile grid.
+///
+/// The image's columns map to world +X and its rows to world +Z, so the top-left
+/// pixel lands at the grid origin. Non-square images are stretched to the square
+/// grid. Heights lie in `[0, max_height]`.
+pub fn generate_terrain_mesh_from_heightmap(
+    device: &Device,
+    settings: &HeightmapTerrainSettings,
+) -> Result<(Mesh, TerrainBounds), TerrainError> {
+    generate_terrain_mesh(
+        device,
+        &TerrainSettings {
+            tile_size: settings.tile_size,
+            tiles_count: settings.tiles_count,
+            world_size: settings.world_size,
+            origin: settings.origin,
+            colors: settings.colors.clone(),
+            cliff_color: settings.cliff_color,
+            slope_threshold: settings.slope_threshold,
+            slope_blend: settings.slope_blend,
+            color_variation: settings.color_variation,
+            noise: &settings.heightmap,
+            scale: 1.0
+                / settings
+                    .world_size
+                    .unwrap_or(settings.tile_size * settings.tiles_count as f32),
+            warp_strength: 0.0,
+            warp_scale: 1.0,
+            max_height: settings.max_height,
+            uv_scale: settings.uv_scale,
+            linear_colors: settings.linear_colors,
+            erosion: settings.erosion,
+            topology: settings.topology,
+            flip_winding: settings.flip_winding,
+        },
+    )
+}
+
+/// Fails when the settings don't pass `TerrainSettings::validate`. Normals of the
+/// top faces point +Y, see `create_triangle_plane`.
+pub fn generate_terrain_mesh<T>(
+    device: &Device,
+    settings: &TerrainSettings<T>,
+) -> Result<(Mesh, TerrainBounds), TerrainError>
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    let data = generate_terrain_data(settings)?;
+    Ok((Mesh::from_data(device, data.mesh), data.bounds))
+}
+
+/// CPU part of `generate_terrain_mesh`, which doesn't need the device and may run
+/// on any thread
+pub fn generate_terrain_data<T>(settings: &TerrainSettings<T>) -> Result<TerrainData, TerrainError>
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    settings.validate()?;
+
+    let heights = generate_height_grid(settings);
+
+    let mesh = generate_grid_data(
+        settings,
+        &heights,
+        &GridRegion {
+            x: 0,
+            z: 0,
+            tiles_count: settings.tiles_count,
+            step: 1,
+            skirt_depth: None,
+        },
+    );
+
+    Ok(TerrainData {
+        mesh,
+        bounds: terrain_bounds(settings, &heights),
+    })
+}
+
+pub struct TerrainData {
+    pub mesh: MeshData,
+    pub bounds: TerrainBounds,
+}
+
+/// Terrain generated on a worker thread, e.g. to show a placeholder instead of
+/// blocking the first frames
+pub struct TerrainJob {
+    receiver: Receiver<Result<TerrainData, TerrainError>>,
+}
+
+impl TerrainJob {
+    pub fn spawn<T>(settings: TerrainSettings<T>) -> TerrainJob
+    where
+        T: NoiseFn<f64, 2> + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(generate_terrain_data(&settings));
+        });
+
+        TerrainJob { receiver }
+    }
+
+    /// Returns the data once it's generated without waiting for it. The data is
+    /// returned only once.
+    pub fn try_take(&self) -> Option<Result<TerrainData, TerrainError>> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn wait(self) -> Result<TerrainData, TerrainError> {
+        self.receiver
+            .recv()
+            .expect("Terrain generation thread panicked")
+    }
+}
+
+pub(crate) fn terrain_bounds<T>(settings: &TerrainSettings<T>, heights: &[f32]) -> TerrainBounds
+where
+    T: NoiseFn<f64, 2>,
+{
+    let (min_height, max_height) = heights.iter().fold((f32::MAX, f32::MIN), |(min, max), &h| {
+        (min.min(h), max.max(h))
+    });
+    let size = settings.tiles_count as f32 * settings.effective_tile_size();
+
+    TerrainBounds {
+        min: settings.origin + Vec3::new(0.0, min_height, 0.0),
+        max: settings.origin + Vec3::new(size, max_height, size),
+    }
+}
+
+/// Square part of the height grid turned into a mesh by `generate_grid_mesh`
+pub(crate) struct GridRegion {
+    pub x: u32,
+    pub z: u32,
+    pub tiles_count: u32,
+    /// Distance in tiles between sampled grid points, used to lower mesh density
+    pub step: u32,
+    /// Depth of vertical walls hanging from the region edges, which hide cracks
+    /// between neighbouring regions of different density
+    pub skirt_depth: Option<f32>,
+}
+
+pub(crate) fn generate_grid_mesh<T>(
+    device: &Device,
+    settings: &TerrainSettings<T>,
+    heights: &[f32],
+    region: &GridRegion,
+) -> Mesh
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    Mesh::from_data(device, generate_grid_data(settings, heights, region))
+}
+
+fn generate_grid_data<T>(
+    settings: &TerrainSettings<T>,
+    heights: &[f32],
+    region: &GridRegion,
+) -> MeshData
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    match settings.topology {
+        PrimitiveTopology::TriangleStrip => generate_grid_strip_data(settings, heights, region),
+        _ => generate_grid_list_data(settings, heights, region),
+    }
+}
+
+fn generate_grid_list_data<T>(
+    settings: &TerrainSettings<T>,
+    heights: &[f32],
+    region: &GridRegion,
+) -> MeshData
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    let colored_triangle = |points: [Vec3; 3]| {
+        let mut triangle = create_triangle_plane(points, Vec3::ZERO);
+        let color = calc_triangle_color(&triangle, settings);
+        for vertex in &mut triangle {
+            vertex.color = color;
+        }
+        triangle
+    };
+
+    let grid_vertex = |x: u32, z: u32| grid_point(settings, heights, x, z);
+    let offsets = region_offsets(region);
+
+    // Columns of tiles are built in parallel, keeping their order
+    let mut vertices: Vec<Vertex> = offsets
+        .par_windows(2)
+        .flat_map_iter(|x| {
+            offsets.windows(2).flat_map(move |z| {
+                let (x0, x1) = (region.x + x[0], region.x + x[1]);
+                let (z0, z1) = (region.z + z[0], region.z + z[1]);
+
+                let v1 = grid_vertex(x0, z0);
+                let v2 = grid_vertex(x1, z0);
+                let v3 = grid_vertex(x1, z1);
+                let v4 = grid_vertex(x0, z1);
+
+                [
+                    colored_triangle([v1, v2, v3]),
+                    colored_triangle([v1, v3, v4]),
+                ]
+                .into_iter()
+                .flatten()
+            })
+        })
+        .collect();
+
+    if let Some(skirt_depth) = region.skirt_depth {
+        for (outward, [(start_x, start_z), (dir_x, dir_z)]) in region_edges(region) {
+            for t in offsets.windows(2) {
+                let point = |t: u32| {
+                    grid_vertex(
+                        region.x + start_x + dir_x * t,
+                        region.z + start_z + dir_z * t,
+                    )
+                };
+                let (p, q) = (point(t[0]), point(t[1]));
+                let (p_low, q_low) = (p - Vec3::Y * skirt_depth, q - Vec3::Y * skirt_depth);
+
+                for mut triangle in [[p, q_low, q], [p, p_low, q_low]] {
+                    // Skirts face outward, like the top faces face up
+                    let n = (triangle[2] - triangle[0]).cross(triangle[1] - triangle[0]);
+                    if n.dot(outward) < 0.0 {
+                        triangle.swap(1, 2);
+                    }
+                    vertices.extend(colored_triangle(triangle));
+                }
             }
         }
+    }
+
+    if settings.flip_winding {
+        flip_triangles(&mut vertices);
+    }
 
-        *settings.colors.last().unwrap()
+    // Planar UVs projected from above, in world units scaled by `uv_scale`
+    for vertex in &mut vertices {
+        vertex.uv = Vec2::new(vertex.position.x, vertex.position.z) * settings.uv_scale;
     }
+    let indices: Vec<u32> = (0..vertices.len()).map(|i| i as u32).collect();
+    compute_tangents(&mut vertices, &indices);
+
+    MeshData {
+        vertices,
+        indices,
+        topology: PrimitiveTopology::TriangleList,
+    }
+}
+
+/// Flat shaded triangle list of the whole terrain where tiles with centers within
+/// `radius` of the world XZ `center` are split into `subdivisions`² smaller tiles.
+///
+/// Detail heights come from the noise, offset to match the grid (which may be eroded)
+/// at grid points. Detail tile edges shared with coarse tiles are snapped onto the
+/// coarse edge, so there are no cracks between the two.
+pub(crate) fn generate_detail_vertices<T>(
+    settings: &TerrainSettings<T>,
+    heights: &[f32],
+    center: Vec2,
+    radius: f32,
+    subdivisions: u32,
+) -> Vec<Vertex>
+where
+    T: NoiseFn<f64, 2>,
+{
+    let tiles_count = settings.tiles_count as i64;
+    let n = subdivisions.max(1);
+    let origin = Vec2::new(settings.origin.x, settings.origin.z);
+    let tile_size = settings.effective_tile_size();
+
+    let is_detail = |x: i64, z: i64| {
+        let tile_center = origin + (Vec2::new(x as f32, z as f32) + 0.5) * tile_size;
+        (0..tiles_count).contains(&x)
+            && (0..tiles_count).contains(&z)
+            && tile_center.distance(center) <= radius
+    };
+    let noise_height = |x: f32, z: f32| sample_noise_height(settings, x, z);
 
-    for x in 0..(settings.tiles_count) {
-        for z in 0..(settings.tiles_count) {
-            let mut v1 = Vec3::new(
-                x as f32 * settings.tile_size,
-                0.0,
-                z as f32 * settings.tile_size,
+    let mut vertices = Vec::new();
+    let mut push_triangle = |points: [Vec3; 3]| {
+        let mut triangle = create_triangle_plane(points, Vec3::ZERO);
+        let color = calc_triangle_color(&triangle, settings);
+        for vertex in &mut triangle {
+            vertex.color = color;
+            vertex.uv = Vec2::new(vertex.position.x, vertex.position.z) * settings.uv_scale;
+        }
+
+        vertices.extend(triangle);
+    };
+
+    for x in 0..settings.tiles_count {
+        for z in 0..settings.tiles_count {
+            let corners = [(0, 0), (1, 0), (1, 1), (0, 1)]
+                .map(|(dx, dz)| grid_point(settings, heights, x + dx, z + dz));
+
+            if !is_detail(x as i64, z as i64) {
+                push_triangle([corners[0], corners[1], corners[2]]);
+                push_triangle([corners[0], corners[2], corners[3]]);
+                continue;
+            }
+
+            // Difference between the grid and the raw noise, interpolated over the tile
+            let offsets = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(dx, dz)| {
+                grid_point(settings, heights, x + dx, z + dz).y
+                    - settings.origin.y
+                    - noise_height((x + dx) as f32, (z + dz) as f32)
+            });
+            let [c00, c10, c11, c01] = corners.map(|c| c.y);
+            let [o00, o10, o11, o01] = offsets;
+
+            // Edges bordering coarse tiles: -X, +X, -Z, +Z
+            let (x, z) = (x as i64, z as i64);
+            let coarse = [
+                !is_detail(x - 1, z),
+                !is_detail(x + 1, z),
+                !is_detail(x, z - 1),
+                !is_detail(x, z + 1),
+            ];
+
+            let point = |i: u32, j: u32| {
+                let (u, v) = (i as f32 / n as f32, j as f32 / n as f32);
+                let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+                let y = if i == 0 && coarse[0] {
+                    lerp(c00, c01, v)
+                } else if i == n && coarse[1] {
+                    lerp(c10, c11, v)
+                } else if j == 0 && coarse[2] {
+                    lerp(c00, c10, u)
+                } else if j == n && coarse[3] {
+                    lerp(c01, c11, u)
+                } else {
+                    let offset = lerp(lerp(o00, o10, u), lerp(o01, o11, u), v);
+                    settings.origin.y + noise_height(x as f32 + u, z as f32 + v) + offset
+                };
+
+                Vec3::new(
+                    origin.x + (x as f32 + u) * tile_size,
+                    y,
+                    origin.y + (z as f32 + v) * tile_size,
+                )
+            };
+
+            for i in 0..n {
+                for j in 0..n {
+                    let (v1, v2) = (point(i, j), point(i + 1, j));
+                    let (v3, v4) = (point(i + 1, j + 1), point(i, j + 1));
+
+                    push_triangle([v1, v2, v3]);
+                    push_triangle([v1, v3, v4]);
+                }
+            }
+        }
+    }
+
+    if settings.flip_winding {
+        flip_triangles(&mut vertices);
+    }
+
+    vertices
+}
+
+/// Reverses the winding of a triangle list, keeping the normals
+fn flip_triangles(vertices: &mut [Vertex]) {
+    for triangle in vertices.chunks_exact_mut(3) {
+        triangle.swap(1, 2);
+    }
+}
+
+fn generate_grid_strip_data<T>(
+    settings: &TerrainSettings<T>,
+    heights: &[f32],
+    region: &GridRegion,
+) -> MeshData
+where
+    T: NoiseFn<f64, 2>,
+{
+    let offsets = region_offsets(region);
+    let n = offsets.len();
+    let index = |x: usize, z: usize| (z * n + x) as u32;
+
+    let mut vertices = Vec::<Vertex>::with_capacity(n * n);
+    for z in &offsets {
+        for x in &offsets {
+            let p = grid_point(settings, heights, region.x + x, region.z + z);
+            vertices.push(Vertex::new(p, Vec3::ZERO, Vec3::ZERO));
+        }
+    }
+
+    // Same triangles as in the list mesh, used for normals and tangents
+    let mut list_indices = Vec::<u32>::new();
+    for z in 0..(n - 1) {
+        for x in 0..(n - 1) {
+            let (v1, v2, v3, v4) = (
+                index(x, z),
+                index(x + 1, z),
+                index(x + 1, z + 1),
+                index(x, z + 1),
             );
-            let mut v2 = v1 + Vec3::X * settings.tile_size;
-            let mut v3 = v2 + Vec3::Z * settings.tile_size;
-            let mut v4 = v1 + Vec3::Z * settings.tile_size;
+            list_indices.extend([v1, v2, v3, v1, v3, v4]);
+        }
+    }
+
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for triangle in list_indices.chunks_exact(3) {
+        let [p0, p1, p2] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+        let n = (p2 - p0).cross(p1 - p0);
+        for i in triangle {
+            normals[*i as usize] += n;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = normal.normalize_or_zero();
+        vertex.color = calc_color(vertex.position, vertex.normal, settings);
+        vertex.uv = Vec2::new(vertex.position.x, vertex.position.z) * settings.uv_scale;
+    }
+    compute_tangents(&mut vertices, &list_indices);
 
-            apply_noise(&mut v1, settings);
-            apply_noise(&mut v2, settings);
-            apply_noise(&mut v3, settings);
-            apply_noise(&mut v4, settings);
+    let mut indices = Vec::<u32>::new();
+    for z in 0..(n - 1) {
+        for x in 0..n {
+            indices.extend(if settings.flip_winding {
+                [index(x, z), index(x, z + 1)]
+            } else {
+                [index(x, z + 1), index(x, z)]
+            });
+        }
+        indices.push(STRIP_RESTART_INDEX);
+    }
+
+    if let Some(skirt_depth) = region.skirt_depth {
+        for (outward, [(start_x, start_z), (dir_x, dir_z)]) in region_edges(region) {
+            let mut strip = Vec::<[u32; 2]>::with_capacity(n);
+            // Edges start either at the first or at the last sampled row or column
+            let first = |start: u32| if start == 0 { 0 } else { n - 1 };
+            for t in 0..n {
+                let top = index(
+                    first(start_x) + dir_x as usize * t,
+                    first(start_z) + dir_z as usize * t,
+                );
+
+                let mut low = vertices[top as usize];
+                low.position.y -= skirt_depth;
+                vertices.push(low);
+
+                strip.push([top, (vertices.len() - 1) as u32]);
+            }
 
-            let c1 = calc_triangle_color([v1, v2, v3], settings);
-            vertices.extend(create_triangle_plane([v1, v2, v3], c1));
-            indices.push((vertices.len() - 3) as u16);
-            indices.push((vertices.len() - 2) as u16);
-            indices.push((vertices.len() - 1) as u16);
+            // Orders each pair so the first triangle faces outward, which the strip
+            // keeps for the following ones
+            let p0 = vertices[strip[0][0] as usize].position;
+            let p1 = vertices[strip[0][1] as usize].position;
+            let p2 = vertices[strip[1][0] as usize].position;
+            let flip = ((p2 - p0).cross(p1 - p0).dot(outward) < 0.0) != settings.flip_winding;
 
-            let c2 = calc_triangle_color([v1, v3, v4], settings);
-            vertices.extend(create_triangle_plane([v1, v3, v4], c2));
-            indices.push((vertices.len() - 3) as u16);
-            indices.push((vertices.len() - 2) as u16);
-            indices.push((vertices.len() - 1) as u16);
+            for [top, low] in strip {
+                indices.extend(if flip { [low, top] } else { [top, low] });
+            }
+            indices.push(STRIP_RESTART_INDEX);
         }
     }
 
-    Mesh::new(
-        device,
-        vertices.into_boxed_slice(),
-        indices.into_boxed_slice(),
-    )
+    MeshData {
+        vertices,
+        indices,
+        topology: PrimitiveTopology::TriangleStrip,
+    }
+}
+
+fn grid_point<T>(settings: &TerrainSettings<T>, heights: &[f32], x: u32, z: u32) -> Vec3
+where
+    T: NoiseFn<f64, 2>,
+{
+    let grid_size = settings.tiles_count as usize + 1;
+    let tile_size = settings.effective_tile_size();
+
+    settings.origin
+        + Vec3::new(
+            x as f32 * tile_size,
+            heights[z as usize * grid_size + x as usize],
+            z as f32 * tile_size,
+        )
+}
+
+/// Sampled offsets inside the region, always including its far edge
+fn region_offsets(region: &GridRegion) -> Vec<u32> {
+    let mut offsets: Vec<u32> = (0..region.tiles_count)
+        .step_by(region.step.max(1) as usize)
+        .collect();
+    offsets.push(region.tiles_count);
+
+    offsets
+}
+
+/// Outward direction, start offset and direction of each region edge
+fn region_edges(region: &GridRegion) -> [(Vec3, [(u32, u32); 2]); 4] {
+    let last = region.tiles_count;
+
+    [
+        (Vec3::NEG_Z, [(0, 0), (1, 0)]),
+        (Vec3::X, [(last, 0), (0, 1)]),
+        (Vec3::Z, [(0, last), (1, 0)]),
+        (Vec3::NEG_X, [(0, 0), (0, 1)]),
+    ]
+}
+
+fn calc_triangle_color<T>(triangle: &[Vertex; 3], settings: &TerrainSettings<T>) -> Vec4
+where
+    T: NoiseFn<f64, 2>,
+{
+    let center = (triangle[0].position + triangle[1].position + triangle[2].position) / 3.0;
+    calc_color(center, triangle[0].normal, settings)
+}
+
+fn calc_color<T>(position: Vec3, normal: Vec3, settings: &TerrainSettings<T>) -> Vec4
+where
+    T: NoiseFn<f64, 2>,
+{
+    let mut color = settings.colors.sample(position.y - settings.origin.y);
+
+    // 0 for flat ground, 1 for a vertical wall
+    let slope = 1.0 - normal.normalize_or_zero().y.abs();
+    let t = ((slope - settings.slope_threshold) / settings.slope_blend.max(f32::EPSILON))
+        .clamp(0.0, 1.0);
+    color = color.lerp(settings.cliff_color, t);
+
+    if settings.color_variation != 0.0 {
+        let tile = ((position - settings.origin) / settings.effective_tile_size()).floor();
+        let jitter = tile_hash(tile.x as i32, tile.z as i32) * 2.0 - 1.0;
+        color = (color * (1.0 + jitter * settings.color_variation)).clamp(Vec3::ZERO, Vec3::ONE);
+    }
+
+    if settings.linear_colors {
+        srgb_to_linear(color).extend(1.0)
+    } else {
+        color.extend(1.0)
+    }
+}
+
+/// Pseudo-random value in `[0, 1)` for the tile coordinates
+fn tile_hash(x: i32, z: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8DA6_B343) ^ (z as u32).wrapping_mul(0xD816_3841);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Height of the noise at the grid coordinates, which may be fractional
+fn sample_noise_height<T>(settings: &TerrainSettings<T>, x: f32, z: f32) -> f32
+where
+    T: NoiseFn<f64, 2>,
+{
+    let mut p = (Vec2::new(x, z) * settings.effective_tile_size() * settings.scale).as_dvec2();
+    if settings.warp_strength != 0.0 {
+        // The same noise shifted by arbitrary offsets, so the two warp axes differ
+        let q = p * settings.warp_scale;
+        let warp = DVec2::new(
+            settings.noise.get(q.to_array()),
+            settings.noise.get((q + DVec2::new(5.2, 1.3)).to_array()),
+        );
+        p += warp * settings.warp_strength as f64;
+    }
+
+    settings.noise.get(p.to_array()) as f32 * settings.max_height
+}
+
+pub(crate) fn generate_height_grid<T>(settings: &TerrainSettings<T>) -> Vec<f32>
+where
+    T: NoiseFn<f64, 2> + Sync,
+{
+    let grid_size = settings.tiles_count as usize + 1;
+    let mut heights = vec![0.0; grid_size * grid_size];
+
+    heights
+        .par_chunks_mut(grid_size)
+        .enumerate()
+        .for_each(|(z, row)| {
+            for (x, height) in row.iter_mut().enumerate() {
+                *height = sample_noise_height(settings, x as f32, z as f32);
+            }
+        });
+
+    if let Some(erosion) = &settings.erosion {
+        erode(&mut heights, grid_size, erosion);
+    }
+
+    heights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(tiles_count: u32, topology: PrimitiveTopology) -> TerrainSettings<Perlin> {
+        TerrainSettings {
+            tiles_count,
+            topology,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_rejects_zero_tiles() {
+        let result = settings(0, PrimitiveTopology::TriangleList).validate();
+        assert!(matches!(
+            result,
+            Err(TerrainError::InvalidTerrainSettings(_))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_bad_tile_size() {
+        for tile_size in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let settings = TerrainSettings {
+                tile_size,
+                ..settings(4, PrimitiveTopology::TriangleList)
+            };
+            assert!(
+                matches!(
+                    settings.validate(),
+                    Err(TerrainError::InvalidTerrainSettings(_))
+                ),
+                "tile size {tile_size} passed"
+            );
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_world_size() {
+        let settings = TerrainSettings {
+            world_size: Some(0.0),
+            ..settings(4, PrimitiveTopology::TriangleList)
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn validate_limits_list_vertices() {
+        // 6 vertices per tile, up to 2^32
+        assert!(settings(26754, PrimitiveTopology::TriangleList)
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            settings(26755, PrimitiveTopology::TriangleList).validate(),
+            Err(TerrainError::InvalidTerrainSettings(_))
+        ));
+    }
+
+    #[test]
+    fn validate_limits_strip_vertices() {
+        // One vertex per grid point, up to 2^32 - 1 as the last index restarts strips
+        assert!(settings(65534, PrimitiveTopology::TriangleStrip)
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            settings(65535, PrimitiveTopology::TriangleStrip).validate(),
+            Err(TerrainError::InvalidTerrainSettings(_))
+        ));
+    }
+
+    fn average_normal<T>(settings: &TerrainSettings<T>) -> Vec3
+    where
+        T: NoiseFn<f64, 2> + Sync,
+    {
+        let data = generate_terrain_data(settings).unwrap();
+        data.mesh
+            .vertices
+            .iter()
+            .map(|vertex| vertex.normal)
+            .sum::<Vec3>()
+            .normalize()
+    }
+
+    #[test]
+    fn normals_point_up() {
+        for topology in [
+            PrimitiveTopology::TriangleList,
+            PrimitiveTopology::TriangleStrip,
+        ] {
+            let flat = TerrainSettings {
+                max_height: 0.0,
+                ..settings(8, topology)
+            };
+            assert!(average_normal(&flat).abs_diff_eq(Vec3::Y, 1e-5));
+
+            let noisy = TerrainSettings::with_seed(7);
+            let noisy = TerrainSettings { topology, ..noisy };
+            assert!(average_normal(&noisy).dot(Vec3::Y) > 0.9);
+
+            // Flipping the winding keeps the normals
+            let flipped = TerrainSettings {
+                flip_winding: true,
+                ..noisy.clone()
+            };
+            assert_eq!(average_normal(&flipped), average_normal(&noisy));
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        for topology in [
+            PrimitiveTopology::TriangleList,
+            PrimitiveTopology::TriangleStrip,
+        ] {
+            let settings = TerrainSettings {
+                topology,
+                tiles_count: 48,
+                erosion: Some(Default::default()),
+                ..TerrainSettings::with_seed(3)
+            };
+            let a = generate_terrain_data(&settings).unwrap().mesh;
+            let b = generate_terrain_data(&settings).unwrap().mesh;
+
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&a.vertices),
+                bytemuck::cast_slice::<_, u8>(&b.vertices)
+            );
+            assert_eq!(a.indices, b.indices);
+        }
+    }
+
+    #[test]
+    fn warp_changes_heights() {
+        let heights = |warp_strength| {
+            let settings = TerrainSettings {
+                warp_strength,
+                ..TerrainSettings::with_seed(5)
+            };
+            generate_height_grid(&settings)
+        };
+
+        assert_ne!(heights(0.0), heights(2.0));
+    }
+
+    #[test]
+    fn list_mesh_counts() {
+        let mesh = generate_terrain_data(&settings(10, PrimitiveTopology::TriangleList))
+            .unwrap()
+            .mesh;
+
+        // Two unshared triangles per tile
+        assert_eq!(mesh.vertices.len(), 600);
+        assert_eq!(mesh.indices.len(), 600);
+    }
+
+    #[test]
+    fn strip_mesh_counts() {
+        let mesh = generate_terrain_data(&settings(10, PrimitiveTopology::TriangleStrip))
+            .unwrap()
+            .mesh;
+
+        // Shared grid points, and a strip of 2 indices per column and a restart per row
+        assert_eq!(mesh.vertices.len(), 121);
+        assert_eq!(mesh.indices.len(), 10 * (11 * 2 + 1));
+        assert_eq!(
+            mesh.indices
+                .iter()
+                .filter(|i| **i == STRIP_RESTART_INDEX)
+                .count(),
+            10
+        );
+        // Restarts aside, the strip draws as many triangles as the list
+        let triangles: usize = mesh
+            .indices
+            .split(|i| *i == STRIP_RESTART_INDEX)
+            .map(|strip| strip.len().saturating_sub(2))
+            .sum();
+        assert_eq!(triangles, 200);
+    }
 }
@@ -0,0 +1,236 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use noise::{
+    core::worley::{distance_functions, worley_2d, ReturnType},
+    permutationtable::PermutationTable,
+    Billow, NoiseFn, Perlin, RidgedMulti, Seedable, Simplex, Value,
+};
+
+use crate::core::error::TerrainError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+    Worley,
+    Value,
+    Ridged,
+    Billow,
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 6] = [
+        NoiseKind::Perlin,
+        NoiseKind::Simplex,
+        NoiseKind::Worley,
+        NoiseKind::Value,
+        NoiseKind::Ridged,
+        NoiseKind::Billow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoiseKind::Perlin => "perlin",
+            NoiseKind::Simplex => "simplex",
+            NoiseKind::Worley => "worley",
+            NoiseKind::Value => "value",
+            NoiseKind::Ridged => "ridged",
+            NoiseKind::Billow => "billow",
+        }
+    }
+}
+
+impl Display for NoiseKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the names given by `NoiseKind::name`, ignoring case
+impl FromStr for NoiseKind {
+    type Err = TerrainError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NoiseKind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| TerrainError::UnknownNoise(s.to_owned()))
+    }
+}
+
+/// Noise chosen at runtime, so terrains of different noises share the settings type
+#[derive(Clone)]
+pub enum TerrainNoise {
+    Perlin(Perlin),
+    Simplex(Simplex),
+    Worley(SyncWorley),
+    Value(Value),
+    Ridged(RidgedMulti<Perlin>),
+    Billow(Billow<Perlin>),
+}
+
+impl TerrainNoise {
+    pub fn new(kind: NoiseKind, seed: u32) -> Self {
+        match kind {
+            NoiseKind::Perlin => TerrainNoise::Perlin(Perlin::new(seed)),
+            NoiseKind::Simplex => TerrainNoise::Simplex(Simplex::new(seed)),
+            NoiseKind::Worley => TerrainNoise::Worley(SyncWorley::new(seed)),
+            NoiseKind::Value => TerrainNoise::Value(Value::new(seed)),
+            NoiseKind::Ridged => TerrainNoise::Ridged(RidgedMulti::new(seed)),
+            NoiseKind::Billow => TerrainNoise::Billow(Billow::new(seed)),
+        }
+    }
+
+    pub fn kind(&self) -> NoiseKind {
+        match self {
+            TerrainNoise::Perlin(_) => NoiseKind::Perlin,
+            TerrainNoise::Simplex(_) => NoiseKind::Simplex,
+            TerrainNoise::Worley(_) => NoiseKind::Worley,
+            TerrainNoise::Value(_) => NoiseKind::Value,
+            TerrainNoise::Ridged(_) => NoiseKind::Ridged,
+            TerrainNoise::Billow(_) => NoiseKind::Billow,
+        }
+    }
+}
+
+/// Worley noise with euclidean distances, which unlike `noise::Worley` can be shared
+/// between threads, as its distance function isn't boxed
+#[derive(Clone, Copy)]
+pub struct SyncWorley {
+    pub return_type: ReturnType,
+    pub frequency: f64,
+    seed: u32,
+    perm_table: PermutationTable,
+}
+
+impl SyncWorley {
+    pub const DEFAULT_FREQUENCY: f64 = 1.0;
+
+    pub fn new(seed: u32) -> Self {
+        SyncWorley {
+            return_type: ReturnType::Value,
+            frequency: Self::DEFAULT_FREQUENCY,
+            seed,
+            perm_table: PermutationTable::new(seed),
+        }
+    }
+}
+
+impl NoiseFn<f64, 2> for SyncWorley {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        worley_2d(
+            &self.perm_table,
+            distance_functions::euclidean,
+            self.return_type,
+            point.map(|x| x * self.frequency),
+        )
+    }
+}
+
+impl Seedable for SyncWorley {
+    fn set_seed(self, seed: u32) -> Self {
+        SyncWorley {
+            seed,
+            perm_table: PermutationTable::new(seed),
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl Default for TerrainNoise {
+    fn default() -> Self {
+        TerrainNoise::new(NoiseKind::Perlin, Perlin::DEFAULT_SEED)
+    }
+}
+
+impl NoiseFn<f64, 2> for TerrainNoise {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        match self {
+            TerrainNoise::Perlin(noise) => noise.get(point),
+            TerrainNoise::Simplex(noise) => noise.get(point),
+            TerrainNoise::Worley(noise) => noise.get(point),
+            TerrainNoise::Value(noise) => noise.get(point),
+            TerrainNoise::Ridged(noise) => noise.get(point),
+            TerrainNoise::Billow(noise) => noise.get(point),
+        }
+    }
+}
+
+impl Seedable for TerrainNoise {
+    fn set_seed(self, seed: u32) -> Self {
+        match self {
+            TerrainNoise::Perlin(noise) => TerrainNoise::Perlin(noise.set_seed(seed)),
+            TerrainNoise::Simplex(noise) => TerrainNoise::Simplex(noise.set_seed(seed)),
+            TerrainNoise::Worley(noise) => TerrainNoise::Worley(noise.set_seed(seed)),
+            TerrainNoise::Value(noise) => TerrainNoise::Value(noise.set_seed(seed)),
+            TerrainNoise::Ridged(noise) => TerrainNoise::Ridged(noise.set_seed(seed)),
+            TerrainNoise::Billow(noise) => TerrainNoise::Billow(noise.set_seed(seed)),
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        match self {
+            TerrainNoise::Perlin(noise) => noise.seed(),
+            TerrainNoise::Simplex(noise) => noise.seed(),
+            TerrainNoise::Worley(noise) => noise.seed(),
+            TerrainNoise::Value(noise) => noise.seed(),
+            TerrainNoise::Ridged(noise) => noise.seed(),
+            TerrainNoise::Billow(noise) => noise.seed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 128;
+    const STEP: f64 = 0.02;
+
+    /// Mean curvature at the local maxima of the noise, relative to its spread of
+    /// values. Smooth hills have low curvature at their tops, ridges have cusps.
+    fn peak_sharpness(noise: &TerrainNoise) -> f64 {
+        let values: Vec<f64> = (0..SIZE * SIZE)
+            .map(|i| noise.get([(i % SIZE) as f64 * STEP, (i / SIZE) as f64 * STEP]))
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let deviation =
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt();
+
+        let mut curvature = 0.0;
+        let mut peaks = 0;
+        for z in 1..SIZE - 1 {
+            for x in 1..SIZE - 1 {
+                let v = values[z * SIZE + x];
+                let neighbours = [
+                    values[z * SIZE + x - 1],
+                    values[z * SIZE + x + 1],
+                    values[(z - 1) * SIZE + x],
+                    values[(z + 1) * SIZE + x],
+                ];
+                if neighbours.iter().all(|n| *n < v) {
+                    curvature += neighbours.iter().map(|n| v - n).sum::<f64>();
+                    peaks += 1;
+                }
+            }
+        }
+
+        curvature / (peaks as f64 * deviation)
+    }
+
+    #[test]
+    fn ridged_peaks_are_sharper_than_perlin() {
+        for seed in [1, 2, 3] {
+            let perlin = peak_sharpness(&TerrainNoise::new(NoiseKind::Perlin, seed));
+            let ridged = peak_sharpness(&TerrainNoise::new(NoiseKind::Ridged, seed));
+            assert!(ridged > perlin, "seed {seed}: {ridged} <= {perlin}");
+        }
+    }
+}